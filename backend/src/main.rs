@@ -1,4 +1,182 @@
+//! Serves a database over TCP.
+//!
+//! The protocol is line based. Each line a client sends is SQL text, holding one or more statements separated by semicolons.
+//! The server answers with what each statement produced, every line of it prefixed with `| `,
+//! followed by a line that is either `OK`, or `ERROR: ` and a description of what went wrong.
+//! If a statement fails, the statements before it on the line keep their effects, but their output isn't sent.
+//! A line longer than a mebibyte isn't run, and is answered with `ERROR: line too long`.
+//!
+//! Rows are sent as tab separated values, with any newline, tab or backslash in a value escaped as `\n`, `\r`, `\t` or `\\`,
+//! so that each row takes up exactly one line.
+//!
+//! Any number of clients can be connected at once, but their statements are run one line at a time:
+//! each line has the database to itself until it has been answered.
+//!
+//! `EXPLAIN` and `EXPLAIN ANALYZE` answer with the plan of the statement they are given, one operator to a line:
+//!
+//! ```text
+//! EXPLAIN SELECT * FROM users WHERE USERNAME = 'alice'
+//! | Filter (USERNAME = 'alice')
+//! |   Key Lookup on users (USERNAME = 'alice')
+//! OK
+//! ```
+//!
+//! Usage: `backend [DIRECTORY] [ADDRESS]`, which serves the database stored in `DIRECTORY` (by default `data`)
+//! on `ADDRESS` (by default `127.0.0.1:7878`).
+
+extern crate zeppelin_db;
+
+use zeppelin_db::Database;
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 fn main() {
-    println!("Hello, World");
-}
\ No newline at end of file
+    let mut args = env::args().skip(1);
+    let directory = args.next().unwrap_or_else(|| "data".into());
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:7878".into());
+
+    let database = Database::open(&directory).unwrap_or_else(|e| {
+        eprintln!("Could not open the database in {}: {}", directory, e);
+        process::exit(1)
+    });
+    let listener = TcpListener::bind(&address).unwrap_or_else(|e| {
+        eprintln!("Could not listen on {}: {}", address, e);
+        process::exit(1)
+    });
+    println!("Serving the database in {} on {}", directory, address);
+
+    let database = Arc::new(Mutex::new(database));
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let database = database.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, &database) {
+                        eprintln!("Connection failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Could not accept a connection: {}", e)
+        }
+    }
+}
+
+/// The longest line a client may send, in bytes, not counting the line ending.
+const MAX_LINE_LENGTH: usize = 1 << 20;
+
+/// A line read from a client.
+enum Line {
+    Sql(String),
+    TooLong
+}
+
+/// Answers each line the client sends, until it disconnects.
+fn serve(stream: TcpStream, database: &Mutex<Database>) -> io::Result<()> {
+    let writer = stream.try_clone()?;
+    answer(BufReader::new(stream), writer, database)
+}
+
+/// Answers each line read from the reader, until there are none left.
+fn answer<R: BufRead, W: Write>(mut reader: R, mut writer: W, database: &Mutex<Database>) -> io::Result<()> {
+    while let Some(line) = read_line(&mut reader)? {
+        let response = match line {
+            Line::Sql(ref sql) if sql.trim().is_empty() => continue,
+            Line::Sql(sql) => {
+                let mut database = database.lock().expect("Another connection panicked while using the database");
+                respond(&mut database, &sql)
+            }
+            Line::TooLong => "ERROR: line too long\n".into()
+        };
+        writer.write_all(response.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads the next line, without its line ending, or `None` once the reader is exhausted.
+/// No more than `MAX_LINE_LENGTH` bytes of a line are ever held; the rest of a longer line is skipped.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<Line>> {
+    // Room for the longest line, its line ending, and a byte to tell that a line is longer than that.
+    let limit = MAX_LINE_LENGTH as u64 + 2;
+    let mut bytes = Vec::new();
+    reader.by_ref().take(limit).read_until(b'\n', &mut bytes)?;
+    if bytes.is_empty() {
+        return Ok(None)
+    }
+    if bytes.last() == Some(&b'\n') {
+        bytes.pop();
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+    } else if bytes.len() as u64 == limit {
+        // The line is too long, so the rest of it is skipped.
+        loop {
+            bytes.clear();
+            let read = reader.by_ref().take(limit).read_until(b'\n', &mut bytes)?;
+            if read == 0 || bytes.last() == Some(&b'\n') {
+                return Ok(Some(Line::TooLong))
+            }
+        }
+    }
+    if bytes.len() > MAX_LINE_LENGTH {
+        return Ok(Some(Line::TooLong))
+    }
+    String::from_utf8(bytes)
+        .map(|sql| Some(Line::Sql(sql)))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Runs the SQL text, and writes the response to it.
+fn respond(database: &mut Database, sql: &str) -> String {
+    match database.execute(sql) {
+        Ok(outputs) => {
+            let mut response = String::new();
+            for output in outputs {
+                for line in output.to_string().lines() {
+                    response.push_str("| ");
+                    response.push_str(line);
+                    response.push('\n');
+                }
+            }
+            response.push_str("OK\n");
+            response
+        }
+        Err(e) => format!("ERROR: {}\n", e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_are_answered() {
+        let mut database = Database::new();
+        assert_eq!(respond(&mut database, "CREATE TABLE users (NAME VARCHAR(10) PRIMARY KEY); INSERT INTO users VALUES ('ann')"), "| done\n| 1 row\nOK\n");
+        assert_eq!(respond(&mut database, "EXPLAIN SELECT * FROM users WHERE NAME = 'ann'"), "\
+| Filter (NAME = 'ann')
+|   Key Lookup on users (NAME = 'ann')
+OK
+");
+        assert!(respond(&mut database, "EXPLAIN ANALYZE SELECT * FROM users").starts_with("| Full Scan on users  (rows: 1, time: "));
+        assert_eq!(respond(&mut database, "SELECT NAME FROM users WHERE"), "ERROR: syntax error at line 1, column 29: expected a column name or a value, found end of input\n");
+    }
+
+    #[test]
+    fn long_lines_are_refused() {
+        let database = Mutex::new(Database::new());
+        let input = format!(
+            "{}\r\n\n{}\nCREATE TABLE t (A INT PRIMARY KEY)\r\nINSERT INTO t VALUES (1)\n{}",
+            " ".repeat(MAX_LINE_LENGTH),
+            "x".repeat(3 * MAX_LINE_LENGTH),
+            " ".repeat(MAX_LINE_LENGTH + 1)
+        );
+        let mut output = Vec::new();
+        answer(input.as_bytes(), &mut output, &database).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "ERROR: line too long\n| done\nOK\n| 1 row\nOK\nERROR: line too long\n");
+    }
+}
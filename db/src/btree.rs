@@ -1,245 +1,763 @@
-use schema::Schema;
-use table::Page;
+//! A B+tree mapping keys to the locations of rows within a table's pages.
+//!
+//! Nodes are kept in an arena and refer to each other by their position within it,
+//! which lets every leaf keep a link to its neighbours so that ordered scans
+//! can walk from leaf to leaf without going back up the tree.
 
-use std::cell::RefCell;
+use std::ops::Bound;
+use std::mem;
 
 const B_TREE_WIDTH: usize = 8;
+/// Every node other than the root must stay at least half full.
+const MIN_OCCUPANCY: usize = B_TREE_WIDTH / 2;
+
+type NodeId = usize;
 
 #[derive(Clone, Debug)]
-pub enum BTree<T>
-{
-    None,
+enum BTreeNode<T> {
+    /// An interior node.
+    /// Every key in `children_ptrs[i]` is greater than or equal to `children_keys[i - 1]`
+    /// and less than `children_keys[i]`.
     Node {
-        children_keys: [Option<T>; B_TREE_WIDTH - 1],
-        children_ptrs: [Box<RefCell<BTree<T>>>; B_TREE_WIDTH ],
+        children_keys: Vec<T>,
+        children_ptrs: Vec<NodeId>,
     },
+    /// A leaf, holding keys in ascending order along with the location that each key maps to.
     Leaf {
-        page_locations: [Option<(T, usize)>; B_TREE_WIDTH]
+        page_locations: Vec<(T, usize)>,
+        prev: Option<NodeId>,
+        next: Option<NodeId>,
+    },
+    /// A node that has been merged away, and can be reused.
+    Free
+}
+
+impl<T> BTreeNode<T> {
+    fn new_leaf() -> Self {
+        BTreeNode::Leaf {
+            page_locations: Vec::with_capacity(B_TREE_WIDTH + 1),
+            prev: None,
+            next: None
+        }
     }
+
+    /// The number of keys in a leaf, or the number of children of an interior node.
+    fn occupancy(&self) -> usize {
+        match *self {
+            BTreeNode::Node { ref children_ptrs, .. } => children_ptrs.len(),
+            BTreeNode::Leaf { ref page_locations, .. } => page_locations.len(),
+            BTreeNode::Free => 0
+        }
+    }
+}
+
+/// The result of inserting into a subtree.
+struct InsertResult<T> {
+    /// The location previously stored for the key, if any.
+    previous: Option<usize>,
+    /// If the subtree's root had to be split, the separating key and the new right hand node.
+    split: Option<(T, NodeId)>
 }
 
+#[derive(Clone, Debug)]
+pub struct BTree<T> {
+    nodes: Vec<BTreeNode<T>>,
+    free_nodes: Vec<NodeId>,
+    root: Option<NodeId>,
+    len: usize
+}
 
 impl<T> Default for BTree<T> {
     fn default() -> Self {
-        return BTree::None
+        BTree {
+            nodes: Vec::new(),
+            free_nodes: Vec::new(),
+            root: None,
+            len: 0
+        }
     }
 }
 
 impl <T> BTree<T>
-    where T: Clone + PartialOrd
+    where T: Clone + Ord
 {
-    /// Creates a new BTree.
-    /// By default, it is None.
-    /// The first insert operation will create a top-level node.
-    fn new() -> Self {
-        BTree::None
+    /// Creates a new, empty BTree.
+    /// The first insert operation will create a leaf to act as the root.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    /// Finds the index of the page in the database's vector of pages, using a key (which is the _other_ kind of index).
-    fn find(&self, search_key: &T) -> Option<usize> {
-        match *self {
-            BTree::None => {
-                None
-            }
-            BTree::Node {ref children_keys, ref children_ptrs} => {
-                // search to see if the search key is contained within this la
-                let mut node_iterator = BTreeNodeIterator::new(children_keys, children_ptrs);
-                while let Some(group) = node_iterator.next() {
-                    match group {
-                        KeyPtrGroup::NotLast {left_ptr, key} => {
-                            if search_key < key {
-                                return left_ptr.borrow().find(search_key)// keep searching in the LHS
-                            }
-                            // If the condition is not met, move onto the next group
-                        }
-                        KeyPtrGroup::Last {left_ptr, key, right_ptr} => {
-                            if search_key < key {
-                                return left_ptr.borrow().find(search_key) // keep searching in the LHS
-                            } else {
-                                return right_ptr.borrow().find(search_key) // Remember, that the RHS pointer may point to a None variant, causing this to return None
-                            }
-                        }
-                    }
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn allocate(&mut self, node: BTreeNode<T>) -> NodeId {
+        match self.free_nodes.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, id: NodeId) {
+        self.nodes[id] = BTreeNode::Free;
+        self.free_nodes.push(id);
+    }
+
+    /// Temporarily removes a node from the arena so that it and its siblings can be modified together.
+    fn take(&mut self, id: NodeId) -> BTreeNode<T> {
+        mem::replace(&mut self.nodes[id], BTreeNode::Free)
+    }
+
+    /// The index of the child of an interior node that the key belongs in.
+    fn child_index(children_keys: &[T], key: &T) -> usize {
+        match children_keys.binary_search(key) {
+            Ok(index) => index + 1,
+            Err(index) => index
+        }
+    }
+
+    /// Finds the leaf that the key belongs in.
+    fn find_leaf(&self, key: &T) -> Option<NodeId> {
+        let mut id = self.root?;
+        loop {
+            match self.nodes[id] {
+                BTreeNode::Node { ref children_keys, ref children_ptrs } => {
+                    id = children_ptrs[Self::child_index(children_keys, key)];
                 }
-                None // The while loop should exhaustively search the iterator and never give this None a chance to return
+                _ => return Some(id)
             }
-            BTree::Leaf { ref page_locations} => {
-                page_locations.iter()
-                    .cloned() // TODO, see if there is a better way to filter here.
-                    .filter_map(|x| x)
-                    .find(|x: &(T,usize)| &x.0 == search_key)
-                    .map(|x: (T,usize)| x.1)
+        }
+    }
+
+    fn leaf_entries(&self, id: NodeId) -> &[(T, usize)] {
+        match self.nodes[id] {
+            BTreeNode::Leaf { ref page_locations, .. } => page_locations,
+            _ => &[]
+        }
+    }
+
+    fn leaf_links(&self, id: NodeId) -> (Option<NodeId>, Option<NodeId>) {
+        match self.nodes[id] {
+            BTreeNode::Leaf { prev, next, .. } => (prev, next),
+            _ => (None, None)
+        }
+    }
+
+    /// Finds the page location stored for the key.
+    pub fn find(&self, search_key: &T) -> Option<usize> {
+        let leaf = self.find_leaf(search_key)?;
+        let entries = self.leaf_entries(leaf);
+        entries.binary_search_by(|entry| entry.0.cmp(search_key))
+            .ok()
+            .map(|index| entries[index].1)
+    }
+
+    /// Inserts a key into the BTree, returning the location previously stored for the key, if any.
+    pub fn insert(&mut self, insert_key: T, value: usize) -> Option<usize> {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                let root = self.allocate(BTreeNode::new_leaf());
+                self.root = Some(root);
+                root
             }
+        };
+
+        let result = self.insert_into(root, insert_key, value);
+        if let Some((separator, right)) = result.split {
+            let new_root = self.allocate(BTreeNode::Node {
+                children_keys: vec!(separator),
+                children_ptrs: vec!(root, right)
+            });
+            self.root = Some(new_root);
+        }
+        if result.previous.is_none() {
+            self.len += 1;
         }
+        result.previous
     }
 
+    fn insert_into(&mut self, id: NodeId, insert_key: T, value: usize) -> InsertResult<T> {
+        let child = match self.nodes[id] {
+            BTreeNode::Node { ref children_keys, ref children_ptrs } => {
+                let index = Self::child_index(children_keys, &insert_key);
+                Some((index, children_ptrs[index]))
+            }
+            _ => None
+        };
 
-    /// Inserts a value into the BTree
-    fn insert(&mut self, insert_key: T, value: usize) -> Option<Box<RefCell<BTree<T>>>>
-        where T: Ord + PartialOrd
-    {
-        match self {
-            BTree::None => {
-                let children_keys: [Option<T>; B_TREE_WIDTH - 1] = Default::default();
-                let children_ptrs: [Box<RefCell<BTree<T>>>; B_TREE_WIDTH] = Default::default();
-                let mut new_b_tree = BTree::Node {
-                    children_keys,
-                    children_ptrs
+        match child {
+            Some((index, child_id)) => {
+                let result = self.insert_into(child_id, insert_key, value);
+                let split = match result.split {
+                    Some((separator, right)) => self.insert_child(id, index, separator, right),
+                    None => None
                 };
-                new_b_tree.insert(insert_key, value);
-                Some(Box::new(RefCell::new(new_b_tree)))
-            },
-            BTree::Node {ref mut children_keys, ref mut children_ptrs} => {
-                let mut node_iterator = BTreeNodeIterator::new(children_keys, children_ptrs);
-                while let Some(ref mut group) = node_iterator.next() {
-                    let copied_inserted_key = insert_key.clone(); // This appears to be necessary
-                    match group {
-                        KeyPtrGroup::NotLast {left_ptr, key} => {
-                            if &insert_key < key {
-                                if let Some(mut child_btree) = left_ptr.borrow_mut().insert(copied_inserted_key, value) {
-                                    match child_btree.borrow_mut() {
-
-                                    }
-                                } else {
-                                    return None
-                                }
-                            }
-                        }
-                        KeyPtrGroup::Last {ref mut left_ptr, key, ref mut right_ptr } => {
-                            if &insert_key < key {
-                                return left_ptr.borrow_mut().insert(copied_inserted_key, value)
-                            } else {
-                                return right_ptr.borrow_mut().insert(copied_inserted_key, value)
-                            }
-                        }
+                InsertResult {
+                    previous: result.previous,
+                    split
+                }
+            }
+            None => self.insert_into_leaf(id, insert_key, value)
+        }
+    }
+
+    fn insert_into_leaf(&mut self, id: NodeId, insert_key: T, value: usize) -> InsertResult<T> {
+        let (right_entries, old_next) = match self.nodes[id] {
+            BTreeNode::Leaf { ref mut page_locations, next, .. } => {
+                match page_locations.binary_search_by(|entry| entry.0.cmp(&insert_key)) {
+                    Ok(index) => {
+                        let previous = mem::replace(&mut page_locations[index].1, value);
+                        return InsertResult { previous: Some(previous), split: None }
                     }
+                    Err(index) => page_locations.insert(index, (insert_key, value))
                 }
-                None
-            },
-            BTree::Leaf {mut page_locations} => {
+                if page_locations.len() <= B_TREE_WIDTH {
+                    return InsertResult { previous: None, split: None }
+                }
+                let half = page_locations.len() / 2;
+                (page_locations.split_off(half), next)
+            }
+            _ => unreachable!("Insert should always reach a leaf")
+        };
 
-                let index: usize = 0;
-                while index < B_TREE_WIDTH {
-                    let boi = page_locations[index]
+        // The leaf overflowed, so its upper half is moved into a new leaf linked in after it.
+        let separator = right_entries[0].0.clone();
+        let right = self.allocate(BTreeNode::Leaf {
+            page_locations: right_entries,
+            prev: Some(id),
+            next: old_next
+        });
+        self.set_next(id, Some(right));
+        if let Some(old_next) = old_next {
+            self.set_prev(old_next, Some(right));
+        }
+        InsertResult {
+            previous: None,
+            split: Some((separator, right))
+        }
+    }
+
+    /// Adds a new child to the right of the child at `index`,
+    /// splitting the interior node if it has too many children as a result.
+    fn insert_child(&mut self, id: NodeId, index: usize, separator: T, right: NodeId) -> Option<(T, NodeId)> {
+        let (split_key, right_keys, right_ptrs) = match self.nodes[id] {
+            BTreeNode::Node { ref mut children_keys, ref mut children_ptrs } => {
+                children_keys.insert(index, separator);
+                children_ptrs.insert(index + 1, right);
+                if children_ptrs.len() <= B_TREE_WIDTH {
+                    return None
                 }
+                let middle = children_keys.len() / 2;
+                let right_keys = children_keys.split_off(middle + 1);
+                let split_key = children_keys.pop().expect("The middle key exists");
+                let right_ptrs = children_ptrs.split_off(middle + 1);
+                (split_key, right_keys, right_ptrs)
+            }
+            _ => unreachable!("Only interior nodes have children")
+        };
+        let new_node = self.allocate(BTreeNode::Node {
+            children_keys: right_keys,
+            children_ptrs: right_ptrs
+        });
+        Some((split_key, new_node))
+    }
 
+    fn set_next(&mut self, id: NodeId, new_next: Option<NodeId>) {
+        if let BTreeNode::Leaf { ref mut next, .. } = self.nodes[id] {
+            *next = new_next;
+        }
+    }
 
-                while let Some(joe) = page_locations.iter().enumerate().next() {
+    fn set_prev(&mut self, id: NodeId, new_prev: Option<NodeId>) {
+        if let BTreeNode::Leaf { ref mut prev, .. } = self.nodes[id] {
+            *prev = new_prev;
+        }
+    }
 
-                    if let Some(joe) = joe {
+    /// Removes the key from the BTree, returning the location that was stored for it.
+    pub fn remove(&mut self, key: &T) -> Option<usize> {
+        let root = self.root?;
+        let removed = self.remove_from(root, key);
+        if removed.is_some() {
+            self.len -= 1;
+        }
 
-                    }
+        // Shrink the tree if the root was left with a single child, or nothing at all.
+        let replacement = match self.nodes[root] {
+            BTreeNode::Node { ref children_ptrs, .. } if children_ptrs.len() == 1 => Some(Some(children_ptrs[0])),
+            BTreeNode::Leaf { ref page_locations, .. } if page_locations.is_empty() => Some(None),
+            _ => None
+        };
+        if let Some(new_root) = replacement {
+            self.release(root);
+            self.root = new_root;
+        }
+        removed
+    }
+
+    fn remove_from(&mut self, id: NodeId, key: &T) -> Option<usize> {
+        let child = match self.nodes[id] {
+            BTreeNode::Node { ref children_keys, ref children_ptrs } => {
+                let index = Self::child_index(children_keys, key);
+                (index, children_ptrs[index])
+            }
+            BTreeNode::Leaf { ref mut page_locations, .. } => {
+                return page_locations.binary_search_by(|entry| entry.0.cmp(key))
+                    .ok()
+                    .map(|index| page_locations.remove(index).1)
+            }
+            BTreeNode::Free => return None
+        };
+
+        let (index, child_id) = child;
+        let removed = self.remove_from(child_id, key);
+        if removed.is_some() && self.nodes[child_id].occupancy() < MIN_OCCUPANCY {
+            self.rebalance(id, index);
+        }
+        removed
+    }
+
+    /// Restores the occupancy of the child at `index` by borrowing from or merging with a sibling.
+    fn rebalance(&mut self, parent: NodeId, index: usize) {
+        let (left, right) = match self.nodes[parent] {
+            BTreeNode::Node { ref children_ptrs, .. } => (
+                if index > 0 { Some(children_ptrs[index - 1]) } else { None },
+                children_ptrs.get(index + 1).cloned()
+            ),
+            _ => unreachable!("Only interior nodes have children")
+        };
+
+        if let Some(left) = left {
+            if self.nodes[left].occupancy() > MIN_OCCUPANCY {
+                return self.borrow_from_left(parent, index);
+            }
+        }
+        if let Some(right) = right {
+            if self.nodes[right].occupancy() > MIN_OCCUPANCY {
+                return self.borrow_from_right(parent, index);
+            }
+        }
+        if left.is_some() {
+            self.merge(parent, index - 1);
+        } else if right.is_some() {
+            self.merge(parent, index);
+        }
+    }
+
+    /// Moves the last entry of the left sibling into the child at `index`.
+    fn borrow_from_left(&mut self, parent: NodeId, index: usize) {
+        let mut parent_node = self.take(parent);
+        if let BTreeNode::Node { ref mut children_keys, ref children_ptrs } = parent_node {
+            let mut left = self.take(children_ptrs[index - 1]);
+            let mut child = self.take(children_ptrs[index]);
+            match (&mut left, &mut child) {
+                (&mut BTreeNode::Leaf { page_locations: ref mut left_entries, .. },
+                 &mut BTreeNode::Leaf { page_locations: ref mut child_entries, .. }) => {
+                    let entry = left_entries.pop().expect("The sibling has spare entries");
+                    children_keys[index - 1] = entry.0.clone();
+                    child_entries.insert(0, entry);
                 }
-                for page in page_locations.iter().enumerate() {
+                (&mut BTreeNode::Node { children_keys: ref mut left_keys, children_ptrs: ref mut left_ptrs },
+                 &mut BTreeNode::Node { children_keys: ref mut child_keys, children_ptrs: ref mut child_ptrs }) => {
+                    let separator = left_keys.pop().expect("The sibling has spare keys");
+                    child_keys.insert(0, mem::replace(&mut children_keys[index - 1], separator));
+                    child_ptrs.insert(0, left_ptrs.pop().expect("The sibling has spare children"));
+                }
+                _ => unreachable!("Siblings are at the same depth")
+            }
+            self.nodes[children_ptrs[index - 1]] = left;
+            self.nodes[children_ptrs[index]] = child;
+        }
+        self.nodes[parent] = parent_node;
+    }
 
+    /// Moves the first entry of the right sibling into the child at `index`.
+    fn borrow_from_right(&mut self, parent: NodeId, index: usize) {
+        let mut parent_node = self.take(parent);
+        if let BTreeNode::Node { ref mut children_keys, ref children_ptrs } = parent_node {
+            let mut child = self.take(children_ptrs[index]);
+            let mut right = self.take(children_ptrs[index + 1]);
+            match (&mut child, &mut right) {
+                (&mut BTreeNode::Leaf { page_locations: ref mut child_entries, .. },
+                 &mut BTreeNode::Leaf { page_locations: ref mut right_entries, .. }) => {
+                    child_entries.push(right_entries.remove(0));
+                    children_keys[index] = right_entries[0].0.clone();
+                }
+                (&mut BTreeNode::Node { children_keys: ref mut child_keys, children_ptrs: ref mut child_ptrs },
+                 &mut BTreeNode::Node { children_keys: ref mut right_keys, children_ptrs: ref mut right_ptrs }) => {
+                    let separator = right_keys.remove(0);
+                    child_keys.push(mem::replace(&mut children_keys[index], separator));
+                    child_ptrs.push(right_ptrs.remove(0));
                 }
+                _ => unreachable!("Siblings are at the same depth")
+            }
+            self.nodes[children_ptrs[index]] = child;
+            self.nodes[children_ptrs[index + 1]] = right;
+        }
+        self.nodes[parent] = parent_node;
+    }
 
+    /// Merges the child at `index + 1` into the child at `index`.
+    fn merge(&mut self, parent: NodeId, index: usize) {
+        let (separator, left_id, right_id) = match self.nodes[parent] {
+            BTreeNode::Node { ref mut children_keys, ref mut children_ptrs } => {
+                (children_keys.remove(index), children_ptrs[index], children_ptrs.remove(index + 1))
+            }
+            _ => unreachable!("Only interior nodes have children")
+        };
+        let right = self.take(right_id);
+        let mut following = None;
+        match (&mut self.nodes[left_id], right) {
+            (&mut BTreeNode::Leaf { ref mut page_locations, ref mut next, .. },
+             BTreeNode::Leaf { page_locations: right_entries, next: right_next, .. }) => {
+                page_locations.extend(right_entries);
+                *next = right_next;
+                following = right_next;
+            }
+            (&mut BTreeNode::Node { ref mut children_keys, ref mut children_ptrs },
+             BTreeNode::Node { children_keys: right_keys, children_ptrs: right_ptrs }) => {
+                children_keys.push(separator);
+                children_keys.extend(right_keys);
+                children_ptrs.extend(right_ptrs);
+            }
+            _ => unreachable!("Siblings are at the same depth")
+        }
+        if let Some(following) = following {
+            self.set_prev(following, Some(left_id));
+        }
+        self.release(right_id);
+    }
 
-                // Find the greater of the two, and make sure that the value stored at this leaf is the lesser of the two.
-//                let greater = if page_location < value {
-//                    value
-//                } else {
-//                    let old_page_location = page_location;
-//                    page_location = value;
-//                    old_page_location
-//                };
-//
-//                Some(Box::new(RefCell::new(BTree::Leaf {page_locations: greater })))
-            }
-        }
-    }
-
-//    fn first_open_spot(children: &[Box<BTree<T>>; 8]) -> Option<usize>  {
-//        children
-//            .iter()
-//            .enumerate()
-//            .filter_map(|x: (usize, &Box<BTree<T>>)| {
-//                if let BTree::None = **x.1 {
-//                    Some(x.0)
-//                } else {
-//                    None
-//                }
-//            })
-//            .next()
-//    }
-}
-/*trait Indexable<Index>
-    where Index: PartialEq
-{
-    fn get_index(&self) -> &Index;
-}*/
+    /// Iterates over every key and location in ascending order of keys.
+    pub fn iter(&self) -> Range<'_, T> {
+        self.range((Bound::Unbounded, Bound::Unbounded))
+    }
 
-enum KeyPtrGroup<'a, T>
-    where T: 'a
-{
-    NotLast {
-        left_ptr: &'a Box<RefCell<BTree<T>>>,
-        key: &'a T
-    },
-    Last {
-        left_ptr: &'a Box<RefCell<BTree<T>>>,
-        key: &'a T,
-        right_ptr: &'a Box<RefCell<BTree<T>>>,
+    /// Iterates over the keys within the bounds, along with their locations.
+    /// The iterator can be reversed to walk the keys in descending order.
+    pub fn range(&self, bounds: (Bound<&T>, Bound<&T>)) -> Range<'_, T> {
+        let (start, end) = bounds;
+        self.range_by(
+            |key| match start {
+                Bound::Included(start) => key < start,
+                Bound::Excluded(start) => key <= start,
+                Bound::Unbounded => false
+            },
+            |key| match end {
+                Bound::Included(end) => key > end,
+                Bound::Excluded(end) => key >= end,
+                Bound::Unbounded => false
+            }
+        )
+    }
+
+    /// Iterates over the keys that are neither below nor above the range, along with their locations.
+    ///
+    /// `is_below` must hold for some (possibly empty) run of the smallest keys and for no others,
+    /// and likewise `is_above` for a run of the largest keys.
+    /// This allows ranges to be described in terms of only part of a key,
+    /// such as the first column of a composite key.
+    pub fn range_by<B, A>(&self, is_below: B, is_above: A) -> Range<'_, T>
+        where B: Fn(&T) -> bool,
+              A: Fn(&T) -> bool
+    {
+        let front = self.lower_position(&is_below);
+        let back = self.upper_position(&is_above);
+        let (front, back) = match (front, back) {
+            (Some(front), Some(back)) if self.key_at(front) <= self.key_at(back) => (Some(front), Some(back)),
+            _ => (None, None)
+        };
+        Range {
+            tree: self,
+            front,
+            back
+        }
+    }
+
+    fn key_at(&self, position: (NodeId, usize)) -> &T {
+        &self.leaf_entries(position.0)[position.1].0
+    }
+
+    /// Walks down to the leaf where a run of matching keys at the start of the key space ends.
+    fn find_leaf_by<F: Fn(&T) -> bool>(&self, in_run: F) -> Option<NodeId> {
+        let mut id = self.root?;
+        loop {
+            match self.nodes[id] {
+                BTreeNode::Node { ref children_keys, ref children_ptrs } => {
+                    id = children_ptrs[children_keys.iter().take_while(|key| in_run(key)).count()];
+                }
+                _ => return Some(id)
+            }
+        }
+    }
+
+    /// The position of the first entry that isn't below the range.
+    fn lower_position<B: Fn(&T) -> bool>(&self, is_below: &B) -> Option<(NodeId, usize)> {
+        let leaf = self.find_leaf_by(|key| is_below(key))?;
+        let index = self.leaf_entries(leaf).iter().take_while(|entry| is_below(&entry.0)).count();
+        if index < self.leaf_entries(leaf).len() {
+            Some((leaf, index))
+        } else {
+            self.next_position((leaf, index))
+        }
+    }
+
+    /// The position of the last entry that isn't above the range.
+    fn upper_position<A: Fn(&T) -> bool>(&self, is_above: &A) -> Option<(NodeId, usize)> {
+        let leaf = self.find_leaf_by(|key| !is_above(key))?;
+        let count = self.leaf_entries(leaf).iter().take_while(|entry| !is_above(&entry.0)).count();
+        if count > 0 {
+            Some((leaf, count - 1))
+        } else {
+            self.prev_position((leaf, 0))
+        }
+    }
+
+    /// The position after the given one, following the link to the next leaf if needed.
+    fn next_position(&self, position: (NodeId, usize)) -> Option<(NodeId, usize)> {
+        let (mut leaf, mut index) = (position.0, position.1 + 1);
+        while index >= self.leaf_entries(leaf).len() {
+            leaf = self.leaf_links(leaf).1?;
+            index = 0;
+        }
+        Some((leaf, index))
+    }
+
+    /// The position before the given one, following the link to the previous leaf if needed.
+    fn prev_position(&self, position: (NodeId, usize)) -> Option<(NodeId, usize)> {
+        let (mut leaf, mut index) = position;
+        while index == 0 {
+            leaf = self.leaf_links(leaf).0?;
+            index = self.leaf_entries(leaf).len();
+        }
+        Some((leaf, index - 1))
     }
 }
 
-/// This is an iterator that groups the the keys and pointers together.
-struct BTreeNodeIterator<'a, T>
+/// A cursor over a range of keys in the BTree, walking the linked leaves.
+pub struct Range<'a, T>
     where T: 'a
 {
-    key_index: usize,
-    keys: &'a [Option<T>; B_TREE_WIDTH -1],
-    ptrs: &'a [Box<RefCell<BTree<T>>>; B_TREE_WIDTH]
+    tree: &'a BTree<T>,
+    /// The next position to be returned from the front.
+    front: Option<(NodeId, usize)>,
+    /// The next position to be returned from the back.
+    back: Option<(NodeId, usize)>
 }
 
-impl <'a, T> BTreeNodeIterator<'a, T> {
-    fn new(keys: &'a [Option<T>; B_TREE_WIDTH -1], ptrs: &'a [Box<RefCell<BTree<T>>>; B_TREE_WIDTH]) -> BTreeNodeIterator<'a, T> {
-        BTreeNodeIterator {
-            key_index: 0,
-            keys,
-            ptrs
+impl<'a, T> Iterator for Range<'a, T>
+    where T: Clone + Ord
+{
+    type Item = (&'a T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.front?;
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.front = self.tree.next_position(position);
         }
+        let entry = &self.tree.leaf_entries(position.0)[position.1];
+        Some((&entry.0, entry.1))
     }
 }
 
-impl<'a, T> Iterator for BTreeNodeIterator<'a, T>
-    where T: Clone
+impl<'a, T> DoubleEndedIterator for Range<'a, T>
+    where T: Clone + Ord
 {
-    type Item = KeyPtrGroup<'a, T>;
-    fn next(& mut self) -> Option<Self::Item> {
-
-        if self.key_index < B_TREE_WIDTH {
-            if let Some(ref key) =  self.keys[self.key_index] {
-                let left_ptr = &self.ptrs[self.key_index];
-                self.key_index += 1;
-
-                // This is _after_ the key index has been incremented
-                if self.key_index < B_TREE_WIDTH {
-                    if let None = self.keys[self.key_index] {
-                        // If the next key is invalid, return the Last variant
-                        let right_ptr = &self.ptrs[self.key_index];
-                        return Some(KeyPtrGroup::Last {
-                            left_ptr,
-                            key,
-                            right_ptr
-                        });
-                    } else {
-                        return Some(KeyPtrGroup::NotLast {
-                            left_ptr,
-                            key
-                        });
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let position = self.back?;
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.back = self.tree.prev_position(position);
+        }
+        let entry = &self.tree.leaf_entries(position.0)[position.1];
+        Some((&entry.0, entry.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// A small xorshift generator, so that the randomized tests are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+    }
+
+    impl<T: Clone + Ord + ::std::fmt::Debug> BTree<T> {
+        /// Checks the structural invariants of the tree, returning the depth of the subtree.
+        fn check_node(&self, id: NodeId, lower: Option<&T>, upper: Option<&T>, is_root: bool) -> usize {
+            match self.nodes[id] {
+                BTreeNode::Node { ref children_keys, ref children_ptrs } => {
+                    assert_eq!(children_keys.len() + 1, children_ptrs.len());
+                    assert!(children_ptrs.len() <= B_TREE_WIDTH);
+                    assert!(children_ptrs.len() >= if is_root { 2 } else { MIN_OCCUPANCY });
+                    assert!(children_keys.windows(2).all(|w| w[0] < w[1]));
+                    let depths: Vec<usize> = children_ptrs.iter()
+                        .enumerate()
+                        .map(|(i, child)| {
+                            let child_lower = if i == 0 { lower } else { Some(&children_keys[i - 1]) };
+                            let child_upper = if i == children_keys.len() { upper } else { Some(&children_keys[i]) };
+                            self.check_node(*child, child_lower, child_upper, false)
+                        })
+                        .collect();
+                    assert!(depths.iter().all(|d| *d == depths[0]), "Leaves must all be at the same depth");
+                    depths[0] + 1
+                }
+                BTreeNode::Leaf { ref page_locations, .. } => {
+                    assert!(page_locations.len() <= B_TREE_WIDTH);
+                    assert!(page_locations.len() >= if is_root { 1 } else { MIN_OCCUPANCY });
+                    assert!(page_locations.windows(2).all(|w| w[0].0 < w[1].0));
+                    for entry in page_locations {
+                        assert!(lower.is_none_or(|lower| lower <= &entry.0));
+                        assert!(upper.is_none_or(|upper| &entry.0 < upper));
                     }
+                    0
+                }
+                BTreeNode::Free => panic!("A free node is reachable from the root")
+            }
+        }
+
+        fn check_invariants(&self) {
+            if let Some(root) = self.root {
+                self.check_node(root, None, None, true);
+            }
+            // Walking the leaf links in either direction must visit every key exactly once, in order.
+            let forwards: Vec<T> = self.iter().map(|(key, _)| key.clone()).collect();
+            let mut backwards: Vec<T> = self.iter().rev().map(|(key, _)| key.clone()).collect();
+            backwards.reverse();
+            assert_eq!(forwards.len(), self.len);
+            assert!(forwards.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(forwards, backwards);
+        }
+    }
+
+    fn to_bound(kind: u64, key: &u64) -> Bound<&u64> {
+        match kind {
+            0 => Bound::Unbounded,
+            1 => Bound::Included(key),
+            _ => Bound::Excluded(key)
+        }
+    }
+
+    #[test]
+    fn insert_and_find() {
+        let mut tree = BTree::new();
+        for key in 0..1000 {
+            assert_eq!(tree.insert(key, key as usize * 10), None);
+        }
+        assert_eq!(tree.insert(500, 1), Some(5000));
+        assert_eq!(tree.find(&500), Some(1));
+        assert_eq!(tree.find(&999), Some(9990));
+        assert_eq!(tree.find(&1000), None);
+        assert_eq!(tree.len(), 1000);
+        tree.check_invariants();
+    }
+
+    #[test]
+    fn remove_everything() {
+        let mut tree = BTree::new();
+        for key in 0..500 {
+            tree.insert(key, key);
+        }
+        for key in (0..500).filter(|k| k % 2 == 0).chain((0..500).filter(|k| k % 2 == 1).rev()) {
+            assert_eq!(tree.remove(&key), Some(key));
+            tree.check_invariants();
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.root, None);
+        assert_eq!(tree.iter().next(), None);
+    }
+
+    #[test]
+    fn matches_std_btree_map() {
+        for seed in 1..20u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut tree: BTree<u64> = BTree::new();
+            let mut model: BTreeMap<u64, usize> = BTreeMap::new();
+            let key_space = 50 + rng.below(400);
+
+            for step in 0..2000 {
+                let key = rng.below(key_space);
+                if rng.below(3) == 0 {
+                    assert_eq!(tree.remove(&key), model.remove(&key));
                 } else {
-                    // If the next key index is equal to (it better not be greater than), return the Last variant.
-                    let right_ptr = &self.ptrs[self.key_index];
-                    return Some(KeyPtrGroup::Last {
-                        left_ptr,
-                        key,
-                        right_ptr
-                    });
+                    let value = rng.below(1_000_000) as usize;
+                    assert_eq!(tree.insert(key, value), model.insert(key, value));
+                }
+                assert_eq!(tree.find(&key), model.get(&key).cloned());
+                assert_eq!(tree.len(), model.len());
+
+                if step % 100 == 0 {
+                    tree.check_invariants();
+                    let low = rng.below(key_space);
+                    let high = low + rng.below(key_space);
+                    let bounds = (to_bound(rng.below(3), &low), to_bound(rng.below(3), &high));
+                    let expected: Vec<(u64, usize)> = if low == high && bounds.0 != Bound::Unbounded && bounds.1 != Bound::Unbounded
+                        && (bounds.0 == Bound::Excluded(&low) || bounds.1 == Bound::Excluded(&high)) {
+                        // std panics on ranges like (Excluded(x), Excluded(x)), which are simply empty.
+                        Vec::new()
+                    } else {
+                        model.range(bounds).map(|(k, v)| (*k, *v)).collect()
+                    };
+                    let actual: Vec<(u64, usize)> = tree.range(bounds).map(|(k, v)| (*k, v)).collect();
+                    assert_eq!(actual, expected);
+                    let mut reversed: Vec<(u64, usize)> = tree.range(bounds).rev().map(|(k, v)| (*k, v)).collect();
+                    reversed.reverse();
+                    assert_eq!(reversed, expected);
                 }
             }
+            tree.check_invariants();
+            let all: Vec<(u64, usize)> = tree.iter().map(|(k, v)| (*k, v)).collect();
+            let expected: Vec<(u64, usize)> = model.into_iter().collect();
+            assert_eq!(all, expected);
+        }
+    }
+
+    #[test]
+    fn cursor_meets_in_the_middle() {
+        let mut tree = BTree::new();
+        for key in 0..100u32 {
+            tree.insert(key, key as usize);
+        }
+        let mut range = tree.range((Bound::Included(&10), Bound::Excluded(&20)));
+        let mut seen = Vec::new();
+        loop {
+            match (range.next(), range.next_back()) {
+                (Some(front), Some(back)) => { seen.push(front.1); seen.push(back.1); }
+                (Some(front), None) => { seen.push(front.1); break }
+                _ => break
+            }
         }
-        return None
+        seen.sort();
+        assert_eq!(seen, (10..20).collect::<Vec<usize>>());
     }
 }
@@ -0,0 +1,745 @@
+use table::{self, Table, Tuple, PartialTuple, Key, Value};
+use schema::{Schema, Name, ReferentialAction, Constraint};
+use error::{DbError, DbResult};
+use sql::{self, parse, Statement, Select, Rows, Output, Explanation, plan, plan_select, execute};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+/// The name of the file in a database's directory that lists its tables.
+const CATALOG_FILE: &str = "catalog.json";
+
+/// A set of named tables.
+///
+/// A database either lives in memory, or in a directory holding a file for each table
+/// along with a catalog that records every table's name, file and schema.
+///
+/// Foreign keys span tables, so they are enforced here rather than by the tables themselves.
+/// Changes made directly to a `Table` bypass these checks.
+pub struct Database {
+    tables: BTreeMap<Name, Table>,
+    /// The directory the database is stored in, and the file within it that holds each table.
+    storage: Option<(PathBuf, BTreeMap<Name, String>)>
+}
+
+impl Default for Database {
+    fn default() -> Database {
+        Database::new()
+    }
+}
+
+/// What is stored in the catalog file.
+#[derive(Serialize, Deserialize)]
+struct Catalog {
+    tables: Vec<CatalogEntry>
+}
+
+#[derive(Serialize, Deserialize)]
+struct CatalogEntry {
+    name: Name,
+    /// The table's file, relative to the database's directory.
+    file: String,
+    schema: Schema
+}
+
+/// A column that references a column of some table.
+struct Reference {
+    /// The table containing the referencing column.
+    table: Name,
+    /// The referencing column.
+    column: Name,
+    /// The position of the referenced column in the referenced table's schema.
+    referenced_column: usize,
+    on_delete: ReferentialAction
+}
+
+impl Database {
+    /// Creates a database that only lives in memory.
+    pub fn new() -> Database {
+        Database {
+            tables: BTreeMap::new(),
+            storage: None
+        }
+    }
+
+    /// Opens the database stored in the directory, opening every table listed in its catalog.
+    /// If the directory doesn't have a catalog yet, an empty database is created there.
+    ///
+    /// The schema the catalog records for each table must be the one in the table's file.
+    pub fn open<P: AsRef<Path>>(directory: P) -> DbResult<Database> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+        let catalog: Catalog = match File::open(directory.join(CATALOG_FILE)) {
+            Ok(file) => serde_json::from_reader(file)
+                .map_err(|e| DbError::CorruptFile(format!("unreadable catalog: {}", e)))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Catalog { tables: Vec::new() },
+            Err(e) => return Err(e.into())
+        };
+        let mut tables = BTreeMap::new();
+        let mut files = BTreeMap::new();
+        for entry in catalog.tables {
+            let table = Table::open(directory.join(&entry.file))?;
+            if *table.schema() != entry.schema {
+                return Err(DbError::CorruptFile(format!("the catalog's schema for table {} doesn't match its file", entry.name)))
+            }
+            tables.insert(entry.name.clone(), table);
+            files.insert(entry.name, entry.file);
+        }
+        let database = Database {
+            tables,
+            storage: Some((directory, files))
+        };
+        database.write_catalog()?;
+        Ok(database)
+    }
+
+    /// Creates an empty table with the schema.
+    /// In a database stored in a directory, the table is given a new file there and recorded in the catalog.
+    ///
+    /// Every foreign key in the schema must reference a unique column of a table in the database,
+    /// or of the table itself.
+    pub fn create_table(&mut self, name: &str, schema: Schema) -> DbResult<()> {
+        self.check_new_table(name, &schema)?;
+        let table = match self.storage {
+            Some((ref directory, ref mut files)) => {
+                // Files are numbered, so that any name can be used for a table.
+                // A file left behind by a crash before the catalog was written is skipped over.
+                let file = (files.len()..)
+                    .map(|number| format!("table_{}.zdb", number))
+                    .find(|file| !files.values().any(|used| used == file) && !directory.join(file).exists())
+                    .expect("There is always an unused file name");
+                let table = Table::create(directory.join(&file), schema)?;
+                files.insert(name.into(), file);
+                table
+            }
+            None => Table::new(schema)?
+        };
+        self.tables.insert(name.into(), table);
+        self.write_catalog()
+    }
+
+    /// Removes the table and all of its rows, deleting its files if the database is stored in a directory.
+    /// A table can't be dropped while another table has a foreign key that references it.
+    pub fn drop_table(&mut self, name: &str) -> DbResult<()> {
+        if !self.tables.contains_key(name) {
+            return Err(DbError::UnknownTable(name.into()))
+        }
+        if let Some(reference) = self.references_to(name).into_iter().find(|reference| reference.table != name) {
+            return Err(DbError::TableStillReferenced { table: name.into(), by: reference.table })
+        }
+        self.tables.remove(name);
+        let file = match self.storage {
+            Some((ref directory, ref mut files)) => files.remove(name).map(|file| directory.join(file)),
+            None => None
+        };
+        // The catalog is written first, so that a crash can only leave behind files that nothing refers to.
+        self.write_catalog()?;
+        if let Some(file) = file {
+            match fs::remove_file(table::log_path(&file)) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+                result => result?
+            }
+            fs::remove_file(file)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the catalog to list the tables currently in the database.
+    /// The new catalog is written alongside the old one, and then moved over it, so that a crash leaves one or the other.
+    fn write_catalog(&self) -> DbResult<()> {
+        let (directory, files) = match self.storage {
+            Some((ref directory, ref files)) => (directory, files),
+            None => return Ok(())
+        };
+        let catalog = Catalog {
+            tables: self.tables
+                .iter()
+                .map(|(name, table)| CatalogEntry {
+                    name: name.clone(),
+                    file: files[name].clone(),
+                    schema: table.schema().clone()
+                })
+                .collect()
+        };
+        let bytes = serde_json::to_vec_pretty(&catalog).expect("The catalog should always be serializable");
+        let temporary = directory.join(format!("{}.tmp", CATALOG_FILE));
+        {
+            let mut file = File::create(&temporary)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+        }
+        fs::rename(temporary, directory.join(CATALOG_FILE))?;
+        Ok(())
+    }
+
+    /// The names of the tables in the database, in alphabetical order.
+    pub fn table_names(&self) -> impl Iterator<Item=&str> {
+        self.tables.keys().map(|name| name.as_str())
+    }
+
+    /// Makes sure that a table with the name and schema can be added to the database:
+    /// the name must not be in use, the schema must be valid,
+    /// and every foreign key must reference a unique column of the same type, of a table in the database or of the table itself.
+    fn check_new_table(&self, name: &str, schema: &Schema) -> DbResult<()> {
+        if self.tables.contains_key(name) {
+            return Err(DbError::TableAlreadyExists(name.into()))
+        }
+        schema.check()?;
+        for column in schema.columns.iter() {
+            if let Some(foreign_key) = column.foreign_key() {
+                let referenced_schema = if foreign_key.table == name {
+                    schema
+                } else {
+                    self.tables
+                        .get(&foreign_key.table)
+                        .ok_or_else(|| DbError::UnknownTable(foreign_key.table.clone()))?
+                        .schema()
+                };
+                let referenced_column = referenced_schema
+                    .column_index(&foreign_key.column)
+                    .ok_or_else(|| DbError::UnknownColumn(foreign_key.column.clone()))?;
+                if !referenced_schema.is_unique_column(referenced_column) {
+                    return Err(DbError::ReferencedColumnNotUnique {
+                        table: foreign_key.table.clone(),
+                        column: foreign_key.column.clone()
+                    })
+                }
+                let referenced_type = &referenced_schema.columns[referenced_column].db_type;
+                if !column.db_type.holds_same_values(referenced_type) {
+                    return Err(DbError::ForeignKeyTypeMismatch {
+                        column: column.name.clone(),
+                        expected: referenced_type.clone(),
+                        found: column.db_type.clone()
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+
+    /// Gets the table for modification. Changes made through it bypass the database's foreign key checks.
+    pub fn table_mut(&mut self, name: &str) -> Option<&mut Table> {
+        self.tables.get_mut(name)
+    }
+
+    /// Runs a query written in SQL, which must be a single `SELECT` statement.
+    /// The rows are read from the tables as the returned cursor is advanced.
+    pub fn query(&self, sql: &str) -> DbResult<Rows<'_>> {
+        match parse(sql)?[..] {
+            [Statement::Select(ref select)] => self.select(select),
+            _ => Err(DbError::NotAQuery)
+        }
+    }
+
+    /// Runs a parsed `SELECT` statement, choosing how to read its rows with `plan_select`.
+    pub fn select(&self, select: &Select) -> DbResult<Rows<'_>> {
+        let plan = plan_select(self, select)?;
+        execute(self, &plan)
+    }
+
+    /// Runs every statement in the SQL text in turn, stopping at the first one that fails.
+    /// The statements before it keep their effects.
+    pub fn execute(&mut self, sql: &str) -> DbResult<Vec<Output>> {
+        parse(sql)?
+            .iter()
+            .map(|statement| self.execute_statement(statement))
+            .collect()
+    }
+
+    /// Runs a parsed statement.
+    ///
+    /// An insertion, update or deletion changes either every row it is given or selects, or none of them.
+    /// Unlike `insert_tuple`, an insertion doesn't replace rows: a row whose key is already in use is an error.
+    pub fn execute_statement(&mut self, statement: &Statement) -> DbResult<Output> {
+        match *statement {
+            Statement::CreateTable { ref name, ref schema } => self.create_table(name, schema.clone()).map(|_| Output::Done),
+            Statement::DropTable { ref name } => self.drop_table(name).map(|_| Output::Done),
+            Statement::Insert { ref table, ref columns, ref rows } => {
+                let positions = match *columns {
+                    Some(ref columns) => {
+                        let names: Vec<&str> = columns.iter().map(|name| name.as_str()).collect();
+                        self.table_or_err(table)?.schema().column_indexes(&names)?
+                    }
+                    None => (0..self.table_or_err(table)?.schema().columns().len()).collect()
+                };
+                let width = self.table_or_err(table)?.schema().columns().len();
+                let mut tuples = Vec::with_capacity(rows.len());
+                for row in rows {
+                    if row.len() != positions.len() {
+                        return Err(DbError::WrongNumberOfValues { expected: positions.len(), found: row.len() })
+                    }
+                    let mut tuple: PartialTuple = vec!(None; width);
+                    for (&position, value) in positions.iter().zip(row) {
+                        tuple[position] = value.clone();
+                    }
+                    tuples.push(tuple);
+                }
+                self.insert_tuples(table, tuples).map(|tuples| Output::Changed(tuples.len()))
+            }
+            Statement::Select(ref select) => {
+                let rows = self.select(select)?;
+                let columns = rows.columns().to_vec();
+                Ok(Output::Rows { columns, tuples: rows.collect::<DbResult<Vec<Tuple>>>()? })
+            }
+            Statement::Update { .. } | Statement::Delete { .. } => {
+                let plan = plan(self, statement)?;
+                sql::executor::run(self, &plan).map(Output::Changed)
+            }
+            Statement::Explain { analyze: false, ref statement } => self.explain(statement).map(Output::Explanation),
+            Statement::Explain { analyze: true, ref statement } => self.explain_analyze(statement).map(Output::Explanation),
+        }
+    }
+
+    /// Describes how a `SELECT`, `UPDATE` or `DELETE` statement would be carried out, without running it.
+    pub fn explain(&self, statement: &Statement) -> DbResult<Explanation> {
+        let plan = plan(self, statement)?;
+        sql::explain(self, &plan, None)
+    }
+
+    /// Runs a `SELECT`, `UPDATE` or `DELETE` statement, describing how it was carried out
+    /// along with the number of rows each step produced and the time it took.
+    /// The changes made by an `UPDATE` or `DELETE` are kept.
+    pub fn explain_analyze(&mut self, statement: &Statement) -> DbResult<Explanation> {
+        let plan = plan(self, statement)?;
+        sql::executor::analyze(self, &plan)
+    }
+
+    fn table_or_err(&self, name: &str) -> DbResult<&Table> {
+        self.tables.get(name).ok_or_else(|| DbError::UnknownTable(name.into()))
+    }
+
+    fn table_mut_or_err(&mut self, name: &str) -> DbResult<&mut Table> {
+        self.tables.get_mut(name).ok_or_else(|| DbError::UnknownTable(name.into()))
+    }
+
+    /// Inserts the tuple into the table, replacing any tuple with the same key.
+    pub fn insert_tuple(&mut self, table: &str, tuple: Tuple) -> DbResult<()> {
+        self.insert_partial_tuple(table, tuple.into_iter().map(Some).collect())
+            .map(|_| ())
+    }
+
+    /// Inserts a tuple in which values may be left out of `Serial` columns,
+    /// returning the completed tuple.
+    pub fn insert_partial_tuple(&mut self, table: &str, tuple: PartialTuple) -> DbResult<Tuple> {
+        let tuple = self.table_or_err(table)?.complete_tuple(tuple)?;
+        let tuple = self.table_or_err(table)?.schema().coerce_tuple(tuple)?;
+        self.check_foreign_keys(table, &tuple, &[])?;
+        self.check_replaced_tuple(table, &tuple)?;
+        self.table_mut_or_err(table)?.insert_tuple(tuple.clone())?;
+        Ok(tuple)
+    }
+
+    /// Inserts tuples in which values may be left out of `Serial` columns, returning the completed tuples.
+    /// Unlike `insert_partial_tuple`, a tuple whose key is already in the table, or in an earlier tuple, is an error.
+    /// Every tuple is checked before any of them are written, so either all of them are inserted or none are.
+    pub(crate) fn insert_tuples(&mut self, table: &str, tuples: Vec<PartialTuple>) -> DbResult<Vec<Tuple>> {
+        let tuples = {
+            let table = self.table_or_err(table)?;
+            table.complete_tuples(tuples)?
+                .into_iter()
+                .map(|tuple| table.schema().coerce_tuple(tuple))
+                .collect::<DbResult<Vec<Tuple>>>()?
+        };
+        let mut keys = BTreeSet::new();
+        for (index, tuple) in tuples.iter().enumerate() {
+            let existing = self.table_or_err(table)?;
+            let key = existing.schema().key_from_tuple(tuple);
+            if existing.find_tuple(key.clone())?.is_some() || !keys.insert(key.clone()) {
+                return Err(DbError::DuplicateKey { key: key.0 })
+            }
+            // A row may reference the rows inserted along with it.
+            self.check_foreign_keys(table, tuple, &tuples[..index])?;
+            existing.check_tuple(tuple)?;
+        }
+        self.check_unique_among(table, &tuples)?;
+        let table = self.table_mut_or_err(table)?;
+        for tuple in &tuples {
+            table.insert_tuple(tuple.clone())?;
+        }
+        Ok(tuples)
+    }
+
+    /// Replaces the tuple in the table that has the same key as the provided one.
+    pub fn update_tuple(&mut self, table: &str, tuple: Tuple) -> DbResult<()> {
+        let tuple = self.table_or_err(table)?.schema().coerce_tuple(tuple)?;
+        self.check_foreign_keys(table, &tuple, &[])?;
+        self.check_replaced_tuple(table, &tuple)?;
+        self.table_mut_or_err(table)?.update_tuple(tuple)
+    }
+
+    /// Replaces the tuples in the table that have the same keys as the provided ones, in the way `update_tuple` does.
+    /// Every tuple is checked before any of them are written, so either all of them are replaced or none are.
+    pub(crate) fn update_tuples(&mut self, table: &str, tuples: Vec<Tuple>) -> DbResult<()> {
+        let tuples = {
+            let schema = self.table_or_err(table)?.schema();
+            tuples.into_iter().map(|tuple| schema.coerce_tuple(tuple)).collect::<DbResult<Vec<Tuple>>>()?
+        };
+        for tuple in &tuples {
+            self.check_foreign_keys(table, tuple, &[])?;
+            self.check_replaced_tuple(table, tuple)?;
+            self.table_or_err(table)?.check_tuple(tuple)?;
+        }
+        self.check_unique_among(table, &tuples)?;
+        let table = self.table_mut_or_err(table)?;
+        for tuple in tuples {
+            table.update_tuple(tuple)?;
+        }
+        Ok(())
+    }
+
+    /// Makes sure that tuples that were each checked against the rows already in the table don't clash with one another
+    /// in any column with a `Unique` constraint.
+    fn check_unique_among(&self, table: &str, tuples: &[Tuple]) -> DbResult<()> {
+        let schema = self.table_or_err(table)?.schema();
+        for (index, column) in schema.columns().iter().enumerate() {
+            if !column.has_constraint(&Constraint::Unique) {
+                continue
+            }
+            let mut values = BTreeSet::new();
+            for value in tuples.iter().map(|tuple| &tuple[index]).filter(|value| **value != Value::Null) {
+                if !values.insert(value) {
+                    return Err(DbError::UniqueViolation { column: column.name.clone(), value: value.clone() })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the tuple with the key from the table,
+    /// along with any tuples that reference it through a foreign key with `ReferentialAction::Cascade`.
+    ///
+    /// Nothing is deleted if any of those tuples is referenced through a foreign key with `ReferentialAction::Restrict`.
+    pub fn delete_tuple<K: Into<Key>>(&mut self, table: &str, key: K) -> DbResult<()> {
+        self.delete_tuples(table, vec!(key.into()))
+    }
+
+    /// Deletes the tuples with the keys from the table, in the way `delete_tuple` does.
+    /// Nothing is deleted unless every one of them can be.
+    pub(crate) fn delete_tuples(&mut self, table: &str, keys: Vec<Key>) -> DbResult<()> {
+        let mut deletions = Vec::new();
+        for key in keys {
+            // The tuple may already be deleted by a cascade from one of the others.
+            let already_deleted = deletions
+                .iter()
+                .any(|&(ref name, ref deleted)| name == table && *deleted == key);
+            if !already_deleted {
+                self.plan_deletion(table, key, &mut deletions)?;
+            }
+        }
+        for (table, key) in deletions {
+            self.table_mut_or_err(&table)?.delete_tuple(key)?;
+        }
+        Ok(())
+    }
+
+    /// Every column of every table that references a column of the table.
+    fn references_to(&self, table: &str) -> Vec<Reference> {
+        let referenced_schema = match self.tables.get(table) {
+            Some(referenced) => referenced.schema(),
+            None => return Vec::new()
+        };
+        let mut references = Vec::new();
+        for (name, referencing) in &self.tables {
+            for column in referencing.schema().columns.iter() {
+                if let Some(foreign_key) = column.foreign_key() {
+                    if foreign_key.table != table {
+                        continue
+                    }
+                    if let Some(referenced_column) = referenced_schema.column_index(&foreign_key.column) {
+                        references.push(Reference {
+                            table: name.clone(),
+                            column: column.name.clone(),
+                            referenced_column,
+                            on_delete: foreign_key.on_delete
+                        });
+                    }
+                }
+            }
+        }
+        references
+    }
+
+    /// The tuples that reference the value through the column.
+    fn referencing_tuples(&self, reference: &Reference, value: &Value) -> DbResult<Vec<Tuple>> {
+        if *value == Value::Null {
+            return Ok(Vec::new())
+        }
+        self.table_or_err(&reference.table)?.find_tuples_by(&reference.column, value)
+    }
+
+    /// Makes sure that every foreign key in the tuple references an existing row.
+    /// A tuple may reference itself, or any of the `pending` tuples that are being written to the same table along with it,
+    /// and a null foreign key doesn't reference anything.
+    fn check_foreign_keys(&self, table: &str, tuple: &Tuple, pending: &[Tuple]) -> DbResult<()> {
+        let schema = self.table_or_err(table)?.schema();
+        for (column, value) in schema.columns.iter().zip(tuple) {
+            let foreign_key = match column.foreign_key() {
+                Some(foreign_key) if *value != Value::Null => foreign_key,
+                _ => continue
+            };
+            let referenced = self.table_or_err(&foreign_key.table)?;
+            if foreign_key.table == table {
+                let referenced_column = schema.column_index(&foreign_key.column);
+                let is_referenced = |other: &Tuple| referenced_column.and_then(|index| other.get(index)) == Some(value);
+                if is_referenced(tuple) || pending.iter().any(is_referenced) {
+                    continue
+                }
+            }
+            if referenced.find_tuples_by(&foreign_key.column, value)?.is_empty() {
+                return Err(DbError::ForeignKeyViolation { column: column.name.clone(), value: value.clone() })
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure that replacing the tuple with the same key doesn't change a value that other rows reference.
+    fn check_replaced_tuple(&self, table: &str, tuple: &Tuple) -> DbResult<()> {
+        let existing = {
+            let table = self.table_or_err(table)?;
+            match table.find_tuple(table.schema().key_from_tuple(tuple))? {
+                Some(existing) => existing,
+                None => return Ok(())
+            }
+        };
+        for reference in self.references_to(table) {
+            let old_value = &existing[reference.referenced_column];
+            if tuple.get(reference.referenced_column) == Some(old_value) {
+                continue
+            }
+            if !self.referencing_tuples(&reference, old_value)?.is_empty() {
+                return Err(DbError::RowStillReferenced { table: reference.table, column: reference.column })
+            }
+        }
+        Ok(())
+    }
+
+    /// Works out every tuple that needs to be deleted along with the tuple with the key,
+    /// adding them to `deletions` in the order they should be deleted.
+    fn plan_deletion(&self, table: &str, key: Key, deletions: &mut Vec<(Name, Key)>) -> DbResult<()> {
+        let tuple = match self.table_or_err(table)?.find_tuple(key.clone())? {
+            Some(tuple) => tuple,
+            None => return Ok(())
+        };
+        deletions.push((table.into(), key));
+        for reference in self.references_to(table) {
+            let referencing_schema = self.table_or_err(&reference.table)?.schema();
+            for referencing in self.referencing_tuples(&reference, &tuple[reference.referenced_column])? {
+                let referencing_key = referencing_schema.key_from_tuple(&referencing);
+                let already_deleted = deletions
+                    .iter()
+                    .any(|&(ref name, ref key)| *name == reference.table && *key == referencing_key);
+                if already_deleted {
+                    continue
+                }
+                match reference.on_delete {
+                    ReferentialAction::Restrict => {
+                        return Err(DbError::RowStillReferenced { table: reference.table.clone(), column: reference.column.clone() })
+                    }
+                    ReferentialAction::Cascade => {
+                        self.plan_deletion(&reference.table, referencing_key, deletions)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schema::{ColumnMetadata, DbType, ForeignKey};
+    use test_utils::temp_dir;
+
+    fn name(s: &str) -> Value {
+        Value::String(s.into())
+    }
+
+    fn database(on_delete: ReferentialAction) -> Database {
+        let users = Schema {
+            columns: Box::new([ColumnMetadata::new_index("USERNAME".into(), DbType::String { length: 10 })])
+        };
+        let references_user = |column: &str| {
+            ColumnMetadata::new(column.into(), DbType::String { length: 10 })
+                .with_foreign_key(ForeignKey::new("users".into(), "USERNAME".into(), on_delete))
+        };
+        let transfers = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                references_user("SRC_USER"),
+                references_user("DEST_USER")
+            ])
+        };
+        let mut database = Database::new();
+        database.create_table("users", users).unwrap();
+        database.create_table("transfers", transfers).unwrap();
+        database.insert_tuple("users", vec!(name("alice"))).unwrap();
+        database.insert_tuple("users", vec!(name("bob"))).unwrap();
+        database.insert_tuple("transfers", vec!(Value::Integer(1), name("alice"), name("bob"))).unwrap();
+        database
+    }
+
+    #[test]
+    fn references_must_exist() {
+        let mut database = database(ReferentialAction::Restrict);
+        match database.insert_tuple("transfers", vec!(Value::Integer(2), name("alice"), name("carol"))) {
+            Err(DbError::ForeignKeyViolation { ref column, .. }) if column == "DEST_USER" => {}
+            other => panic!("Expected a foreign key violation, got {:?}", other)
+        }
+        assert!(database.update_tuple("transfers", vec!(Value::Integer(1), name("carol"), name("bob"))).is_err());
+        assert!(database.table("transfers").unwrap().find_tuple(&Value::Integer(2)).unwrap().is_none());
+
+        // A foreign key has to reference a column that identifies rows.
+        let dangling = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("USER".into(), DbType::String { length: 10 })
+                    .with_foreign_key(ForeignKey::new("transfers".into(), "SRC_USER".into(), ReferentialAction::Restrict))
+            ])
+        };
+        match database.create_table("dangling", dangling) {
+            Err(DbError::ReferencedColumnNotUnique { .. }) => {}
+            other => panic!("Expected the foreign key to be rejected, got {:?}", other)
+        }
+
+        // It also has to hold the same kind of values as the column it references.
+        let references = |db_type: DbType| Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("USER".into(), db_type)
+                    .with_foreign_key(ForeignKey::new("users".into(), "USERNAME".into(), ReferentialAction::Restrict))
+            ])
+        };
+        match database.create_table("mismatched", references(DbType::Integer)) {
+            Err(DbError::ForeignKeyTypeMismatch { ref column, expected: DbType::String { length: 10 }, found: DbType::Integer }) if column == "USER" => {}
+            other => panic!("Expected the foreign key to be rejected, got {:?}", other)
+        }
+        assert!(database.table("mismatched").is_none());
+        database.create_table("longer", references(DbType::Text)).unwrap();
+        database.insert_tuple("longer", vec!(Value::Integer(1), name("alice"))).unwrap();
+    }
+
+    #[test]
+    fn sql_inserts_are_all_or_nothing() {
+        let mut database = Database::new();
+        database.execute("
+            CREATE TABLE parts (ID SERIAL PRIMARY KEY, NAME TEXT NOT NULL UNIQUE, PARENT INT REFERENCES parts (ID));
+            INSERT INTO parts (NAME, PARENT) VALUES ('car', NULL), ('wheel', 1), ('tyre', 2)
+        ").unwrap();
+        let names = |database: &Database| database
+            .query("SELECT NAME FROM parts")
+            .unwrap()
+            .map(|tuple| tuple.unwrap().remove(0))
+            .collect::<Vec<Value>>();
+        let before = names(&database);
+
+        let key = |id: i32| vec!(Value::Integer(id));
+        match database.execute("INSERT INTO parts VALUES (4, 'door', 1), (1, 'bus', NULL)") {
+            Err(DbError::DuplicateKey { key: ref duplicate }) if *duplicate == key(1) => {}
+            other => panic!("Expected a duplicate key, got {:?}", other)
+        }
+        match database.execute("INSERT INTO parts VALUES (4, 'door', 1), (4, 'seat', 1)") {
+            Err(DbError::DuplicateKey { key: ref duplicate }) if *duplicate == key(4) => {}
+            other => panic!("Expected a duplicate key, got {:?}", other)
+        }
+        match database.execute("INSERT INTO parts VALUES (4, 'door', 1), (5, 'door', 1)") {
+            Err(DbError::UniqueViolation { ref column, .. }) if column == "NAME" => {}
+            other => panic!("Expected a unique violation, got {:?}", other)
+        }
+        match database.execute("INSERT INTO parts VALUES (4, 'door', 1), (5, 'seat', 9)") {
+            Err(DbError::ForeignKeyViolation { ref column, .. }) if column == "PARENT" => {}
+            other => panic!("Expected a foreign key violation, got {:?}", other)
+        }
+        match database.execute("INSERT INTO parts VALUES (4, 'door', 1), (5, 6, 1)") {
+            Err(DbError::TypeMismatch { ref column, .. }) if column == "NAME" => {}
+            other => panic!("Expected a type mismatch, got {:?}", other)
+        }
+        assert_eq!(names(&database), before);
+
+        // A row can reference a row inserted along with it, and serial numbers carry on from one row to the next.
+        database.execute("INSERT INTO parts (NAME, PARENT) VALUES ('door', 1), ('handle', 4)").unwrap();
+        assert_eq!(database.table("parts").unwrap().find_tuple(&Value::Integer(5)).unwrap().unwrap()[1], name("handle"));
+    }
+
+    #[test]
+    fn restrict_prevents_deletion() {
+        let mut database = database(ReferentialAction::Restrict);
+        match database.delete_tuple("users", &name("bob")) {
+            Err(DbError::RowStillReferenced { ref table, .. }) if table == "transfers" => {}
+            other => panic!("Expected the deletion to be restricted, got {:?}", other)
+        }
+        assert!(database.table("users").unwrap().find_tuple(&name("bob")).unwrap().is_some());
+
+        database.delete_tuple("transfers", &Value::Integer(1)).unwrap();
+        database.delete_tuple("users", &name("bob")).unwrap();
+        assert!(database.table("users").unwrap().find_tuple(&name("bob")).unwrap().is_none());
+    }
+
+    #[test]
+    fn cascade_deletes_referencing_rows() {
+        let mut database = database(ReferentialAction::Cascade);
+        database.insert_tuple("transfers", vec!(Value::Integer(2), name("bob"), name("bob"))).unwrap();
+        database.delete_tuple("users", &name("alice")).unwrap();
+        let transfers = database.table("transfers").unwrap();
+        assert!(transfers.find_tuple(&Value::Integer(1)).unwrap().is_none());
+        assert!(transfers.find_tuple(&Value::Integer(2)).unwrap().is_some());
+    }
+
+    #[test]
+    fn catalog_survives_reopening() {
+        let directory = temp_dir("catalog").join("database");
+        {
+            let mut database = Database::open(&directory).unwrap();
+            let users = Schema::new(vec!(ColumnMetadata::new_index("USERNAME".into(), DbType::Text)));
+            let sessions = Schema::new(vec!(
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("USERNAME".into(), DbType::Text)
+                    .with_foreign_key(ForeignKey::new("users".into(), "USERNAME".into(), ReferentialAction::Cascade))
+            ));
+            database.create_table("users", users.clone()).unwrap();
+            database.create_table("sessions", sessions).unwrap();
+            database.create_table("old users", users.clone()).unwrap();
+            match database.create_table("users", users) {
+                Err(DbError::TableAlreadyExists(ref table)) if table == "users" => {}
+                other => panic!("Expected the table to already exist, got {:?}", other)
+            }
+            database.insert_tuple("users", vec!(name("alice"))).unwrap();
+            database.insert_tuple("sessions", vec!(Value::Integer(1), name("alice"))).unwrap();
+
+            match database.drop_table("users") {
+                Err(DbError::TableStillReferenced { ref by, .. }) if by == "sessions" => {}
+                other => panic!("Expected the table to be referenced, got {:?}", other)
+            }
+            database.drop_table("old users").unwrap();
+            assert!(database.drop_table("old users").is_err());
+        }
+
+        let mut database = Database::open(&directory).unwrap();
+        assert_eq!(database.table_names().collect::<Vec<&str>>(), vec!("sessions", "users"));
+        assert_eq!(database.table("sessions").unwrap().schema().columns()[1].name(), "USERNAME");
+        // Foreign keys are still enforced after reopening.
+        database.delete_tuple("users", &name("alice")).unwrap();
+        assert!(database.table("sessions").unwrap().find_tuple(&Value::Integer(1)).unwrap().is_none());
+        // The dropped table's files are gone, and its name can be used again.
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 5);
+        database.create_table("old users", Schema::new(vec!(ColumnMetadata::new_index("ID".into(), DbType::Integer)))).unwrap();
+        assert!(database.table("old users").unwrap().find_tuple(&Value::Integer(1)).unwrap().is_none());
+        drop(database);
+
+        // A catalog that has been edited by hand no longer describes the tables.
+        let catalog = fs::read_to_string(directory.join(CATALOG_FILE)).unwrap();
+        fs::write(directory.join(CATALOG_FILE), catalog.replace("USERNAME", "USER_NAME")).unwrap();
+        match Database::open(&directory) {
+            Err(DbError::CorruptFile(ref reason)) if reason.contains("sessions") || reason.contains("users") => {}
+            other => panic!("Expected the catalog not to match the tables, got {:?}", other.map(|_| ()))
+        }
+    }
+}
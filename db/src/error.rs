@@ -0,0 +1,131 @@
+use table::Value;
+use schema::DbType;
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+
+pub type DbResult<T> = Result<T, DbError>;
+
+/// The errors that can be produced by the database.
+#[derive(Debug)]
+pub enum DbError {
+    /// The underlying file could not be read or written.
+    Io(io::Error),
+    /// A file on disk did not have the layout the database expected.
+    CorruptFile(String),
+    /// The row can't be placed in a single page.
+    RowTooLarge { size: usize, max: usize },
+    /// The table's metadata can't be placed in the header page.
+    MetadataTooLarge { size: usize, max: usize },
+    /// The table doesn't have a column with this name.
+    UnknownColumn(String),
+    /// The column already has an index.
+    IndexAlreadyExists(String),
+    /// The column doesn't have a secondary index.
+    NoSuchIndex(String),
+    /// The index enforces a constraint on the column, so it can't be dropped.
+    IndexRequiredByConstraint(String),
+    /// No value was provided for a column that has a `NotNull` constraint.
+    NotNullViolation { column: String },
+    /// No value was provided for a column, and there is nothing to fill it with.
+    MissingValue { column: String },
+    /// A row with this key is already in the table.
+    DuplicateKey { key: Vec<Value> },
+    /// Another row already has this value in a column that has a `Unique` constraint.
+    UniqueViolation { column: String, value: Value },
+    /// The next number for a `Serial` column doesn't fit in the column's type.
+    SerialOutOfRange { column: String },
+    /// The database doesn't have a table with this name.
+    UnknownTable(String),
+    /// The database already has a table with this name.
+    TableAlreadyExists(String),
+    /// A foreign key references a column whose values don't identify a single row.
+    ReferencedColumnNotUnique { table: String, column: String },
+    /// A foreign key column's type holds different values than the type of the column it references.
+    ForeignKeyTypeMismatch { column: String, expected: DbType, found: DbType },
+    /// No row in the referenced table has the value given for a foreign key column.
+    ForeignKeyViolation { column: String, value: Value },
+    /// The row is referenced by rows of another table, so it can't be removed or have its referenced value changed.
+    RowStillReferenced { table: String, column: String },
+    /// The table is referenced by a foreign key of another table, so it can't be dropped.
+    TableStillReferenced { table: String, by: String },
+    /// The schema doesn't have any columns that make up a key.
+    NoKeyColumns,
+    /// A tuple has a different number of values than the schema has columns.
+    WrongNumberOfValues { expected: usize, found: usize },
+    /// The value can't be stored in the column, which has a different type.
+    TypeMismatch { column: String, expected: DbType, found: Value },
+    /// The string is longer than the column allows.
+    StringTooLong { column: String, length: usize, max: usize },
+    /// The number can't be represented exactly in the column.
+    NumericOverflow { column: String, value: Value },
+    /// The column's type can't be used.
+    InvalidColumnType { column: String, reason: String },
+    /// The value is of the column's type, but can't be stored in it.
+    InvalidValue { column: String, reason: String },
+    /// An expression refers to values in a way that doesn't fit their types.
+    InvalidExpression(String),
+    /// The SQL text couldn't be parsed. Lines and columns are counted from 1.
+    SyntaxError { line: usize, column: usize, message: String },
+    /// The SQL text was expected to be a single `SELECT` statement.
+    NotAQuery,
+    /// Only `SELECT`, `UPDATE` and `DELETE` statements have plans that can be explained.
+    NotPlannable,
+    /// A query names a column that more than one of its tables has, or refers to two tables by the same name.
+    AmbiguousName(String),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DbError::Io(ref e) => write!(f, "io error: {}", e),
+            DbError::CorruptFile(ref reason) => write!(f, "corrupt file: {}", reason),
+            DbError::RowTooLarge { size, max } => write!(f, "row of {} bytes exceeds the maximum of {} bytes", size, max),
+            DbError::MetadataTooLarge { size, max } => write!(f, "metadata of {} bytes exceeds the maximum of {} bytes", size, max),
+            DbError::UnknownColumn(ref name) => write!(f, "no column named {}", name),
+            DbError::IndexAlreadyExists(ref name) => write!(f, "column {} is already indexed", name),
+            DbError::NoSuchIndex(ref name) => write!(f, "column {} has no secondary index", name),
+            DbError::IndexRequiredByConstraint(ref name) => write!(f, "the index on column {} enforces a constraint", name),
+            DbError::NotNullViolation { ref column } => write!(f, "column {} may not be null", column),
+            DbError::MissingValue { ref column } => write!(f, "no value was provided for column {}", column),
+            DbError::DuplicateKey { ref key } => write!(f, "a row with the key {:?} already exists", key),
+            DbError::UniqueViolation { ref column, ref value } => write!(f, "column {} already contains {:?}", column, value),
+            DbError::SerialOutOfRange { ref column } => write!(f, "serial column {} has run out of values", column),
+            DbError::UnknownTable(ref name) => write!(f, "no table named {}", name),
+            DbError::TableAlreadyExists(ref name) => write!(f, "table {} already exists", name),
+            DbError::ReferencedColumnNotUnique { ref table, ref column } => write!(f, "column {} of table {} can't be referenced, as its values aren't unique", column, table),
+            DbError::ForeignKeyTypeMismatch { ref column, ref expected, ref found } => write!(f, "column {} has type {:?}, but references a column of type {:?}", column, found, expected),
+            DbError::ForeignKeyViolation { ref column, ref value } => write!(f, "column {} references {:?}, which doesn't exist", column, value),
+            DbError::RowStillReferenced { ref table, ref column } => write!(f, "the row is still referenced by column {} of table {}", column, table),
+            DbError::TableStillReferenced { ref table, ref by } => write!(f, "table {} is referenced by table {}", table, by),
+            DbError::NoKeyColumns => write!(f, "the schema has no key columns"),
+            DbError::WrongNumberOfValues { expected, found } => write!(f, "expected {} values, but found {}", expected, found),
+            DbError::TypeMismatch { ref column, ref expected, ref found } => write!(f, "column {} expects a value of type {:?}, but found {:?}", column, expected, found),
+            DbError::StringTooLong { ref column, length, max } => write!(f, "string of {} bytes exceeds the maximum of {} bytes for column {}", length, max, column),
+            DbError::NumericOverflow { ref column, ref value } => write!(f, "{:?} can't be represented exactly in column {}", value, column),
+            DbError::InvalidColumnType { ref column, ref reason } => write!(f, "column {} has an invalid type: {}", column, reason),
+            DbError::InvalidValue { ref column, ref reason } => write!(f, "invalid value for column {}: {}", column, reason),
+            DbError::InvalidExpression(ref reason) => write!(f, "invalid expression: {}", reason),
+            DbError::SyntaxError { line, column, ref message } => write!(f, "syntax error at line {}, column {}: {}", line, column, message),
+            DbError::NotAQuery => write!(f, "a query must be a single SELECT statement"),
+            DbError::NotPlannable => write!(f, "only SELECT, UPDATE and DELETE statements can be planned"),
+            DbError::AmbiguousName(ref name) => write!(f, "{} could refer to more than one table or column", name),
+        }
+    }
+}
+
+impl Error for DbError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DbError::Io(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(e: io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
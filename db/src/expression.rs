@@ -0,0 +1,767 @@
+//! Conditions on the values in a row.
+//!
+//! An `Expression` names columns rather than referring to their positions, so it can be written without a table at hand,
+//! and sent or stored as it is. Before it can be used it is compiled against a `Schema` into a `Predicate`,
+//! which checks that the columns exist and that the values they are compared with are of compatible types.
+//! A predicate reads only the columns it refers to out of an encoded row.
+//!
+//! Nulls follow SQL's three-valued logic: comparing anything with null gives an unknown result,
+//! `NOT` of an unknown result is still unknown, and a row only matches when the whole condition is true.
+
+use table::{Value, Tuple, RowDecoder};
+use schema::{Schema, DbType, Name};
+use numeric::{Double, Decimal};
+use error::{DbError, DbResult};
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::mem::discriminant;
+use std::ops::Bound;
+use std::str::FromStr;
+
+/// A condition, or a value that a condition is made out of.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Expression {
+    /// The value of the named column.
+    Column(Name),
+    Literal(Value),
+    Compare(Box<Expression>, Comparison, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    /// Whether the value is null. Unlike a comparison, this is never unknown.
+    IsNull(Box<Expression>),
+    /// Whether the string matches the pattern, in which `%` stands for any number of characters and `_` for exactly one.
+    Like(Box<Expression>, String),
+    /// Whether the value is equal to any of the values in the list.
+    In(Box<Expression>, Vec<Expression>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// The comparison that holds with its operands swapped around.
+    fn flipped(&self) -> Comparison {
+        match *self {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+            Comparison::Greater => Comparison::Less,
+            Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+            other => other
+        }
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match *self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl From<Value> for Expression {
+    fn from(value: Value) -> Expression {
+        Expression::Literal(value)
+    }
+}
+
+impl Expression {
+    pub fn column(name: &str) -> Expression {
+        Expression::Column(name.into())
+    }
+
+    fn compare<E: Into<Expression>>(self, comparison: Comparison, other: E) -> Expression {
+        Expression::Compare(Box::new(self), comparison, Box::new(other.into()))
+    }
+
+    pub fn equal<E: Into<Expression>>(self, other: E) -> Expression {
+        self.compare(Comparison::Equal, other)
+    }
+
+    pub fn not_equal<E: Into<Expression>>(self, other: E) -> Expression {
+        self.compare(Comparison::NotEqual, other)
+    }
+
+    pub fn less<E: Into<Expression>>(self, other: E) -> Expression {
+        self.compare(Comparison::Less, other)
+    }
+
+    pub fn less_or_equal<E: Into<Expression>>(self, other: E) -> Expression {
+        self.compare(Comparison::LessOrEqual, other)
+    }
+
+    pub fn greater<E: Into<Expression>>(self, other: E) -> Expression {
+        self.compare(Comparison::Greater, other)
+    }
+
+    pub fn greater_or_equal<E: Into<Expression>>(self, other: E) -> Expression {
+        self.compare(Comparison::GreaterOrEqual, other)
+    }
+
+    pub fn and(self, other: Expression) -> Expression {
+        Expression::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Expression) -> Expression {
+        Expression::Or(Box::new(self), Box::new(other))
+    }
+
+    pub fn negate(self) -> Expression {
+        Expression::Not(Box::new(self))
+    }
+
+    pub fn is_null(self) -> Expression {
+        Expression::IsNull(Box::new(self))
+    }
+
+    pub fn like(self, pattern: &str) -> Expression {
+        Expression::Like(Box::new(self), pattern.into())
+    }
+
+    pub fn is_in(self, list: Vec<Expression>) -> Expression {
+        Expression::In(Box::new(self), list)
+    }
+
+    /// The conditions that must all be true for the expression to be true.
+    pub(crate) fn conjuncts(&self) -> Vec<&Expression> {
+        match *self {
+            Expression::And(ref left, ref right) => {
+                let mut conjuncts = left.conjuncts();
+                conjuncts.extend(right.conjuncts());
+                conjuncts
+            }
+            ref other => vec!(other)
+        }
+    }
+
+    /// The same expression, with each column's name replaced by what `rename` gives for it.
+    pub(crate) fn rename_columns<F: FnMut(&str) -> DbResult<Name>>(&self, rename: &mut F) -> DbResult<Expression> {
+        let boxed = |expression: &Expression, rename: &mut F| expression.rename_columns(rename).map(Box::new);
+        Ok(match *self {
+            Expression::Column(ref name) => Expression::Column(rename(name)?),
+            Expression::Literal(ref value) => Expression::Literal(value.clone()),
+            Expression::Compare(ref left, comparison, ref right) => Expression::Compare(boxed(left, rename)?, comparison, boxed(right, rename)?),
+            Expression::And(ref left, ref right) => Expression::And(boxed(left, rename)?, boxed(right, rename)?),
+            Expression::Or(ref left, ref right) => Expression::Or(boxed(left, rename)?, boxed(right, rename)?),
+            Expression::Not(ref operand) => Expression::Not(boxed(operand, rename)?),
+            Expression::IsNull(ref operand) => Expression::IsNull(boxed(operand, rename)?),
+            Expression::Like(ref operand, ref pattern) => Expression::Like(boxed(operand, rename)?, pattern.clone()),
+            Expression::In(ref operand, ref list) => Expression::In(
+                boxed(operand, rename)?,
+                list.iter().map(|item| item.rename_columns(rename)).collect::<DbResult<Vec<Expression>>>()?
+            ),
+        })
+    }
+
+    /// The range of values that the condition allows in the column, as far as its comparisons of the column with literals show.
+    /// Only comparisons joined by `AND` at the top of the condition are considered,
+    /// so every row that matches the condition has a value in the range, but not every value in the range matches.
+    ///
+    /// The bounds are converted to the column's type, so that they can be used to look the column up in an index.
+    /// Returns `None` if the condition doesn't narrow down the column's values.
+    pub fn column_range(&self, schema: &Schema, column: usize) -> Option<(Bound<Value>, Bound<Value>)> {
+        let metadata = &schema.columns[column];
+        let mut range = (Bound::Unbounded, Bound::Unbounded);
+        let mut narrowed = false;
+        for conjunct in self.conjuncts() {
+            let (comparison, value) = match *conjunct {
+                Expression::Compare(ref left, comparison, ref right) => match (&**left, &**right) {
+                    (Expression::Column(name), Expression::Literal(value)) if *name == metadata.name => (comparison, value),
+                    (Expression::Literal(value), Expression::Column(name)) if *name == metadata.name => (comparison.flipped(), value),
+                    _ => continue
+                },
+                _ => continue
+            };
+            // A value that can't be stored in the column, including null, can't be looked up in its index.
+            let value = match metadata.coerce(value.clone()) {
+                Ok(Value::Null) | Err(_) => continue,
+                Ok(value) => value
+            };
+            let (start, end) = range;
+            range = match comparison {
+                Comparison::Equal => (tighter(start, Bound::Included(value.clone()), Ordering::Greater), tighter(end, Bound::Included(value), Ordering::Less)),
+                Comparison::Less => (start, tighter(end, Bound::Excluded(value), Ordering::Less)),
+                Comparison::LessOrEqual => (start, tighter(end, Bound::Included(value), Ordering::Less)),
+                Comparison::Greater => (tighter(start, Bound::Excluded(value), Ordering::Greater), end),
+                Comparison::GreaterOrEqual => (tighter(start, Bound::Included(value), Ordering::Greater), end),
+                Comparison::NotEqual => (start, end)
+            };
+            narrowed = narrowed || comparison != Comparison::NotEqual;
+        }
+        if narrowed { Some(range) } else { None }
+    }
+
+    /// Checks the expression against the schema, and prepares it to be evaluated against the schema's rows.
+    /// The expression must be a condition: something that is true, false or unknown.
+    pub fn compile(&self, schema: &Schema) -> DbResult<Predicate> {
+        let mut columns = Vec::new();
+        let (condition, kind) = bind(self, schema, &mut columns)?;
+        expect_condition(kind)?;
+        Ok(Predicate {
+            extract: Box::new(schema.generate_extract_columns_fn(&columns)),
+            condition,
+            columns
+        })
+    }
+}
+
+impl Expression {
+    /// How tightly the expression binds when written in SQL, from `OR` at the loosest to operands at the tightest.
+    fn precedence(&self) -> u8 {
+        match *self {
+            Expression::Or(..) => 1,
+            Expression::And(..) => 2,
+            Expression::Not(..) => 3,
+            Expression::Compare(..) | Expression::IsNull(..) | Expression::Like(..) | Expression::In(..) => 4,
+            Expression::Column(_) | Expression::Literal(_) => 5
+        }
+    }
+
+    /// Writes the expression, in parentheses if it binds more loosely than its surroundings require.
+    fn fmt_within(&self, f: &mut fmt::Formatter, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+/// Writes the expression the way it would be written in a SQL `WHERE` clause.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expression::Column(ref name) => write!(f, "{}", name),
+            Expression::Literal(ref value) => write!(f, "{}", value),
+            Expression::Compare(ref left, comparison, ref right) => {
+                left.fmt_within(f, 5)?;
+                write!(f, " {} ", comparison)?;
+                right.fmt_within(f, 5)
+            }
+            Expression::And(ref left, ref right) => {
+                left.fmt_within(f, 2)?;
+                write!(f, " AND ")?;
+                right.fmt_within(f, 2)
+            }
+            Expression::Or(ref left, ref right) => write!(f, "{} OR {}", left, right),
+            Expression::Not(ref inner) => {
+                write!(f, "NOT ")?;
+                inner.fmt_within(f, 3)
+            }
+            Expression::IsNull(ref inner) => {
+                inner.fmt_within(f, 5)?;
+                write!(f, " IS NULL")
+            }
+            Expression::Like(ref inner, ref pattern) => {
+                inner.fmt_within(f, 5)?;
+                write!(f, " LIKE {}", Value::String(pattern.clone()))
+            }
+            Expression::In(ref inner, ref list) => {
+                inner.fmt_within(f, 5)?;
+                write!(f, " IN (")?;
+                for (index, item) in list.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = match *self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "<>",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">="
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+/// An expression that has been checked against a schema, and can be evaluated against its rows.
+pub struct Predicate {
+    condition: Compiled,
+    /// The schema positions of the columns the condition refers to.
+    columns: Vec<usize>,
+    /// Reads the values of those columns out of a row.
+    extract: RowDecoder<'static>
+}
+
+impl Predicate {
+    /// Whether the condition is true for the encoded row, decoding only the columns it refers to.
+    pub fn matches_row(&self, row: &[u8]) -> DbResult<bool> {
+        let values = (self.extract)(row)?;
+        Ok(truth(&self.condition.evaluate(&values)) == Some(true))
+    }
+
+    /// Whether the condition is true for a tuple that has every column in the schema.
+    pub fn matches(&self, tuple: &Tuple) -> bool {
+        let values: Vec<Value> = self.columns
+            .iter()
+            .map(|column| tuple.get(*column).cloned().unwrap_or(Value::Null))
+            .collect();
+        truth(&self.condition.evaluate(&values)) == Some(true)
+    }
+}
+
+/// The broad types that values can have, within which values can be compared with one another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Boolean,
+    Number,
+    Text,
+    Date,
+    Time,
+    Timestamp,
+    Uuid,
+    Bytes,
+}
+
+fn kind_of_type(db_type: &DbType) -> Kind {
+    match *db_type {
+        DbType::Integer | DbType::BigInt | DbType::Double | DbType::Decimal { .. } => Kind::Number,
+        DbType::String { .. } | DbType::Text => Kind::Text,
+        DbType::Boolean => Kind::Boolean,
+        DbType::Date => Kind::Date,
+        DbType::Time => Kind::Time,
+        DbType::Timestamp => Kind::Timestamp,
+        DbType::Uuid => Kind::Uuid,
+        DbType::Bytes => Kind::Bytes,
+    }
+}
+
+/// The kind of the value, or `None` for null, which can stand in for any kind.
+fn kind_of_value(value: &Value) -> Option<Kind> {
+    match *value {
+        Value::Null => None,
+        Value::Integer(_) | Value::BigInt(_) | Value::Double(_) | Value::Decimal(_) => Some(Kind::Number),
+        Value::String(_) => Some(Kind::Text),
+        Value::Boolean(_) => Some(Kind::Boolean),
+        Value::Date(_) => Some(Kind::Date),
+        Value::Time(_) => Some(Kind::Time),
+        Value::Timestamp(_) => Some(Kind::Timestamp),
+        Value::Uuid(_) => Some(Kind::Uuid),
+        Value::Bytes(_) => Some(Kind::Bytes),
+    }
+}
+
+/// An expression with its columns replaced by their positions among the values read out of a row.
+enum Compiled {
+    Slot(usize),
+    Literal(Value),
+    Compare(Box<Compiled>, Comparison, Box<Compiled>),
+    And(Box<Compiled>, Box<Compiled>),
+    Or(Box<Compiled>, Box<Compiled>),
+    Not(Box<Compiled>),
+    IsNull(Box<Compiled>),
+    Like(Box<Compiled>, Vec<char>),
+    In(Box<Compiled>, Vec<Compiled>),
+}
+
+fn expect_condition(kind: Option<Kind>) -> DbResult<()> {
+    match kind {
+        Some(Kind::Boolean) | None => Ok(()),
+        Some(kind) => Err(DbError::InvalidExpression(format!("expected a condition, found a {:?} value", kind)))
+    }
+}
+
+/// Resolves the expression's columns against the schema, recording each column's position in `columns`,
+/// and works out the kind of value the expression produces.
+fn bind(expression: &Expression, schema: &Schema, columns: &mut Vec<usize>) -> DbResult<(Compiled, Option<Kind>)> {
+    let bound = match *expression {
+        Expression::Column(ref name) => {
+            let index = schema.column_index(name)
+                .ok_or_else(|| DbError::UnknownColumn(name.clone()))?;
+            let slot = match columns.iter().position(|column| *column == index) {
+                Some(slot) => slot,
+                None => {
+                    columns.push(index);
+                    columns.len() - 1
+                }
+            };
+            return Ok((Compiled::Slot(slot), Some(kind_of_type(&schema.columns[index].db_type))))
+        }
+        Expression::Literal(ref value) => return Ok((Compiled::Literal(value.clone()), kind_of_value(value))),
+        Expression::Compare(ref left, comparison, ref right) => {
+            let (left, right) = unify(bind(left, schema, columns)?, bind(right, schema, columns)?)?;
+            Compiled::Compare(Box::new(left), comparison, Box::new(right))
+        }
+        Expression::And(ref left, ref right) | Expression::Or(ref left, ref right) => {
+            let (left, left_kind) = bind(left, schema, columns)?;
+            let (right, right_kind) = bind(right, schema, columns)?;
+            expect_condition(left_kind)?;
+            expect_condition(right_kind)?;
+            match *expression {
+                Expression::And(..) => Compiled::And(Box::new(left), Box::new(right)),
+                _ => Compiled::Or(Box::new(left), Box::new(right))
+            }
+        }
+        Expression::Not(ref operand) => {
+            let (operand, kind) = bind(operand, schema, columns)?;
+            expect_condition(kind)?;
+            Compiled::Not(Box::new(operand))
+        }
+        Expression::IsNull(ref operand) => Compiled::IsNull(Box::new(bind(operand, schema, columns)?.0)),
+        Expression::Like(ref operand, ref pattern) => {
+            let (operand, kind) = bind(operand, schema, columns)?;
+            match kind {
+                Some(Kind::Text) | None => {}
+                Some(kind) => return Err(DbError::InvalidExpression(format!("LIKE needs a string, found a {:?} value", kind)))
+            }
+            Compiled::Like(Box::new(operand), pattern.chars().collect())
+        }
+        Expression::In(ref operand, ref list) => {
+            let (mut operand, kind) = bind(operand, schema, columns)?;
+            let mut items = Vec::with_capacity(list.len());
+            for item in list {
+                let (unified_operand, item) = unify((operand, kind), bind(item, schema, columns)?)?;
+                operand = unified_operand;
+                items.push(item);
+            }
+            Compiled::In(Box::new(operand), items)
+        }
+    };
+    Ok((bound, Some(Kind::Boolean)))
+}
+
+/// Makes sure that the two sides of a comparison can be compared.
+/// A string literal compared with a date, time, timestamp or UUID is parsed into one.
+fn unify(left: (Compiled, Option<Kind>), right: (Compiled, Option<Kind>)) -> DbResult<(Compiled, Compiled)> {
+    match (left, right) {
+        ((left, Some(left_kind)), (right, Some(right_kind))) if left_kind != right_kind => {
+            if let Some(right) = parse_literal(&right, right_kind, left_kind)? {
+                return Ok((left, right))
+            }
+            if let Some(left) = parse_literal(&left, left_kind, right_kind)? {
+                return Ok((left, right))
+            }
+            Err(DbError::InvalidExpression(format!("can't compare a {:?} value with a {:?} value", left_kind, right_kind)))
+        }
+        ((left, _), (right, _)) => Ok((left, right))
+    }
+}
+
+/// Parses a string literal into a value of the kind it is compared with,
+/// returning `None` if the expression isn't a string literal, or that kind isn't written as a string.
+fn parse_literal(compiled: &Compiled, kind: Kind, target: Kind) -> DbResult<Option<Compiled>> {
+    let s = match (compiled, kind) {
+        (&Compiled::Literal(Value::String(ref s)), Kind::Text) => s,
+        _ => return Ok(None)
+    };
+    let value = match target {
+        Kind::Date => Value::Date(parse(s, target)?),
+        Kind::Time => Value::Time(parse(s, target)?),
+        Kind::Timestamp => Value::Timestamp(parse(s, target)?),
+        Kind::Uuid => Value::Uuid(parse(s, target)?),
+        _ => return Ok(None)
+    };
+    Ok(Some(Compiled::Literal(value)))
+}
+
+fn parse<T: FromStr>(s: &str, target: Kind) -> DbResult<T> {
+    s.parse().map_err(|_| DbError::InvalidExpression(format!("{:?} is not a {:?} value", s, target)))
+}
+
+/// Whether a value is a true or false condition, or `None` if it is unknown.
+fn truth(value: &Value) -> Option<bool> {
+    match *value {
+        Value::Boolean(truth) => Some(truth),
+        _ => None
+    }
+}
+
+fn from_truth(truth: Option<bool>) -> Value {
+    truth.map_or(Value::Null, Value::Boolean)
+}
+
+impl Compiled {
+    fn evaluate<'a>(&'a self, values: &'a [Value]) -> Cow<'a, Value> {
+        let result = match *self {
+            Compiled::Slot(slot) => return Cow::Borrowed(&values[slot]),
+            Compiled::Literal(ref value) => return Cow::Borrowed(value),
+            Compiled::Compare(ref left, comparison, ref right) => {
+                compare(&left.evaluate(values), &right.evaluate(values)).map(|ordering| comparison.holds(ordering))
+            }
+            Compiled::And(ref left, ref right) => {
+                match (truth(&left.evaluate(values)), truth(&right.evaluate(values))) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None
+                }
+            }
+            Compiled::Or(ref left, ref right) => {
+                match (truth(&left.evaluate(values)), truth(&right.evaluate(values))) {
+                    (Some(true), _) | (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None
+                }
+            }
+            Compiled::Not(ref operand) => truth(&operand.evaluate(values)).map(|truth| !truth),
+            Compiled::IsNull(ref operand) => Some(*operand.evaluate(values) == Value::Null),
+            Compiled::Like(ref operand, ref pattern) => {
+                match *operand.evaluate(values) {
+                    Value::String(ref s) => Some(like(&s.chars().collect::<Vec<char>>(), pattern)),
+                    _ => None
+                }
+            }
+            Compiled::In(ref operand, ref list) => {
+                let operand = operand.evaluate(values);
+                let mut unknown = false;
+                let mut found = false;
+                for item in list {
+                    match compare(&operand, &item.evaluate(values)) {
+                        Some(Ordering::Equal) => found = true,
+                        Some(_) => {}
+                        None => unknown = true
+                    }
+                }
+                // Failing to find the value is unknown if it might have been equal to a null.
+                if found { Some(true) } else if unknown { None } else { Some(false) }
+            }
+        };
+        Cow::Owned(from_truth(result))
+    }
+}
+
+/// Picks whichever of the two bounds allows fewer values.
+/// For a lower bound the tighter one is `Ordering::Greater`, and for an upper bound it is `Ordering::Less`.
+fn tighter(a: Bound<Value>, b: Bound<Value>, tighter: Ordering) -> Bound<Value> {
+    let ordering = match (&a, &b) {
+        (&Bound::Unbounded, _) => return b,
+        (_, &Bound::Unbounded) => return a,
+        (&Bound::Included(ref x), &Bound::Included(ref y))
+        | (&Bound::Included(ref x), &Bound::Excluded(ref y))
+        | (&Bound::Excluded(ref x), &Bound::Included(ref y))
+        | (&Bound::Excluded(ref x), &Bound::Excluded(ref y)) => x.cmp(y)
+    };
+    match (ordering, &a) {
+        // An excluded bound is tighter than an included bound on the same value.
+        (Ordering::Equal, &Bound::Excluded(_)) => a,
+        (Ordering::Equal, _) => b,
+        (ordering, _) if ordering == tighter => a,
+        _ => b
+    }
+}
+
+/// Compares two values of the same kind, or `None` if either is null.
+/// Numbers of different types are compared by the numbers they represent.
+pub fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (&Value::Null, _) | (_, &Value::Null) => None,
+        _ if discriminant(left) == discriminant(right) => Some(left.cmp(right)),
+        _ => match (as_decimal(left), as_decimal(right)) {
+            (Some(left), Some(right)) => Some(left.cmp(&right)),
+            _ => match (as_double(left), as_double(right)) {
+                (Some(left), Some(right)) => Some(Double(left).cmp(&Double(right))),
+                _ => None
+            }
+        }
+    }
+}
+
+fn as_decimal(value: &Value) -> Option<Decimal> {
+    match *value {
+        Value::Integer(integer) => Some(Decimal::from(i64::from(integer))),
+        Value::BigInt(integer) => Some(Decimal::from(integer)),
+        Value::Decimal(decimal) => Some(decimal),
+        _ => None
+    }
+}
+
+fn as_double(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Integer(integer) => Some(f64::from(integer)),
+        Value::BigInt(integer) => Some(integer as f64),
+        Value::Double(double) => Some(double.0),
+        Value::Decimal(decimal) => Some(decimal.units() as f64 / 10f64.powi(i32::from(decimal.scale()))),
+        _ => None
+    }
+}
+
+/// Whether the text matches the `LIKE` pattern.
+///
+/// Only the last `%` seen is ever backtracked to: if what follows it fails to match, it takes one more character of the text.
+/// Backtracking to an earlier `%` can't help, as the later one can already take any text the earlier one could have.
+/// This takes time proportional to the product of the lengths, however many `%`s the pattern has.
+fn like(text: &[char], pattern: &[char]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    // The position in the pattern just after the last `%`, and the position in the text that it has taken characters up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(&'%') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if c == '_' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((after_percent, taken)) => {
+                    p = after_percent;
+                    t = taken + 1;
+                    backtrack = Some((after_percent, t));
+                }
+                None => return false
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schema::ColumnMetadata;
+    use table::tuple_to_row;
+    use serde_json;
+
+    fn accounts_schema() -> Schema {
+        Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("NAME".into(), DbType::Text),
+                ColumnMetadata::new("BALANCE".into(), DbType::Decimal { precision: 10, scale: 2 }),
+                ColumnMetadata::new("OPENED".into(), DbType::Date)
+            ])
+        }
+    }
+
+    fn account(id: i32, name: Option<&str>, balance: &str, opened: &str) -> Tuple {
+        vec!(
+            Value::Integer(id),
+            name.map_or(Value::Null, |name| Value::String(name.into())),
+            Value::Decimal(balance.parse::<Decimal>().unwrap().rescale(2).unwrap()),
+            Value::Date(opened.parse().unwrap())
+        )
+    }
+
+    fn string(s: &str) -> Expression {
+        Value::String(s.into()).into()
+    }
+
+    #[test]
+    fn conditions_are_evaluated_on_encoded_rows() {
+        let schema = accounts_schema();
+        let rows: Vec<Vec<u8>> = vec!(
+            account(1, Some("alice"), "-10.50", "2023-05-01"),
+            account(2, Some("bob"), "0", "2024-01-01"),
+            account(3, None, "250.25", "2024-02-29"),
+            account(4, Some("alfred"), "7", "2022-12-31")
+        ).into_iter().map(|tuple| tuple_to_row(tuple, &schema).unwrap()).collect();
+        let matching = |condition: Expression| -> Vec<usize> {
+            let predicate = condition.compile(&schema).unwrap();
+            (0..rows.len()).filter(|index| predicate.matches_row(&rows[*index]).unwrap()).map(|index| index + 1).collect()
+        };
+
+        assert_eq!(matching(Expression::column("BALANCE").less(Value::Integer(0))), vec!(1));
+        assert_eq!(matching(Expression::column("OPENED").greater_or_equal(string("2024-01-01"))), vec!(2, 3));
+        assert_eq!(matching(Expression::column("NAME").like("al%")), vec!(1, 4));
+        assert_eq!(matching(Expression::column("NAME").like("_o_")), vec!(2));
+        assert_eq!(matching(Expression::column("NAME").is_null()), vec!(3));
+        // A null name makes the comparison unknown, and so does its negation.
+        assert_eq!(matching(Expression::column("NAME").equal(string("bob")).negate()), vec!(1, 4));
+        assert_eq!(matching(Expression::column("NAME").equal(string("bob")).or(Expression::column("ID").equal(Value::Integer(3)))), vec!(2, 3));
+        assert_eq!(matching(Expression::column("ID").is_in(vec!(Value::Integer(4).into(), Value::BigInt(2).into()))), vec!(2, 4));
+        assert_eq!(matching(Expression::column("ID").is_in(vec!(Value::Integer(4).into(), Value::Null.into())).negate()), Vec::<usize>::new());
+        assert_eq!(matching(
+            Expression::column("BALANCE").greater(Value::Double(Double(0.5)))
+                .and(Expression::column("NAME").is_null().negate())
+        ), vec!(4));
+    }
+
+    #[test]
+    fn like_patterns_are_matched_without_backtracking_blowup() {
+        let matches = |text: &str, pattern: &str| like(&text.chars().collect::<Vec<char>>(), &pattern.chars().collect::<Vec<char>>());
+        let cases = [
+            ("", "", true), ("", "%", true), ("", "_", false), ("abc", "abc", true), ("abc", "ab", false), ("abc", "a_c", true),
+            ("abc", "%c", true), ("abc", "a%", true), ("abc", "%b%", true), ("abc", "%%%", true), ("abc", "%d%", false),
+            ("abcbc", "a%bc", true), ("abcbd", "a%bc", false), ("aXbYc", "a%b%c", true), ("ab", "a%_b", false),
+        ];
+        for &(text, pattern, expected) in cases.iter() {
+            assert_eq!(matches(text, pattern), expected, "{:?} LIKE {:?}", text, pattern);
+        }
+        // Each `%` used to multiply the work by the length of the text, so this took longer than anyone would wait.
+        let text = "a".repeat(40);
+        assert!(!matches(&text, "%a%a%a%a%a%a%a%a%a%a%a%ab"));
+        assert!(!matches(&"a".repeat(10_000), &format!("{}b", "%a".repeat(1_000))));
+    }
+
+    #[test]
+    fn expressions_are_written_as_sql() {
+        let name = || Expression::column("NAME");
+        let condition = name().equal(string("O'Brien")).or(name().like("a%"))
+            .and(Expression::column("ID").is_in(vec!(Value::Integer(1).into(), Value::Null.into())).negate())
+            .and(name().is_null().or(Expression::column("ID").greater(Value::BigInt(2))).negate());
+        assert_eq!(condition.to_string(), "(NAME = 'O''Brien' OR NAME LIKE 'a%') AND NOT ID IN (1, NULL) AND NOT (NAME IS NULL OR ID > 2)");
+    }
+
+    #[test]
+    fn ranges_are_narrowed_by_comparisons() {
+        let schema = accounts_schema();
+        let integer = |i: i32| Value::Integer(i);
+        let id = || Expression::column("ID");
+        let condition = id().greater(integer(3))
+            .and(Expression::from(integer(10)).greater_or_equal(id()))
+            .and(id().greater_or_equal(integer(3)))
+            .and(id().less(integer(10)))
+            .and(id().not_equal(integer(5)));
+        assert_eq!(condition.column_range(&schema, 0), Some((Bound::Excluded(integer(3)), Bound::Excluded(integer(10)))));
+        assert_eq!(id().equal(integer(7)).column_range(&schema, 0), Some((Bound::Included(integer(7)), Bound::Included(integer(7)))));
+        // Conditions that don't have to hold for every matching row can't narrow the range.
+        assert_eq!(id().equal(integer(7)).or(id().equal(integer(8))).column_range(&schema, 0), None);
+        assert_eq!(id().equal(Value::Null).column_range(&schema, 0), None);
+        // Literals are converted to the column's type.
+        let balance = Expression::column("BALANCE").greater_or_equal(integer(100)).column_range(&schema, 2);
+        assert_eq!(balance, Some((Bound::Included(Value::Decimal("100.00".parse().unwrap())), Bound::Unbounded)));
+    }
+
+    #[test]
+    fn expressions_are_type_checked() {
+        let schema = accounts_schema();
+        let invalid = |condition: Expression| match condition.compile(&schema) {
+            Err(DbError::InvalidExpression(_)) => {}
+            Err(other) => panic!("Expected an invalid expression, got {:?}", other),
+            Ok(_) => panic!("Expected an invalid expression")
+        };
+        invalid(Expression::column("NAME").less(Value::Integer(3)));
+        invalid(Expression::column("OPENED").equal(string("yesterday")));
+        invalid(Expression::column("BALANCE").like("1%"));
+        invalid(Expression::column("ID"));
+        invalid(Expression::column("ID").equal(Value::Integer(1)).and(Expression::column("NAME")));
+        invalid(Expression::column("ID").is_in(vec!(Value::Integer(1).into(), string("2"))));
+        match Expression::column("MISSING").is_null().compile(&schema) {
+            Err(DbError::UnknownColumn(ref column)) if column == "MISSING" => {}
+            _ => panic!("Expected an unknown column")
+        }
+
+        let condition = Expression::column("OPENED").less(string("2024-01-01")).and(Expression::column("NAME").like("a%"));
+        let date = |s: &str| Value::Date(s.parse().unwrap());
+        assert_eq!(condition.column_range(&schema, 3), Some((Bound::Unbounded, Bound::Excluded(date("2024-01-01")))));
+        assert_eq!(condition.column_range(&schema, 1), None);
+        let json = serde_json::to_string(&condition).unwrap();
+        assert_eq!(serde_json::from_str::<Expression>(&json).unwrap(), condition);
+        assert!(condition.compile(&schema).unwrap().matches(&account(1, Some("alice"), "1", "2023-01-01")));
+    }
+}
@@ -0,0 +1,87 @@
+use btree::{self, BTree};
+use table::{Key, Value};
+
+use std::ops::Bound;
+
+/// An index over a column other than the table's key.
+///
+/// Many rows may share the same value in the column,
+/// so every entry is keyed by the column's value followed by the row's key,
+/// and maps to the row's location in the table's pages.
+pub struct SecondaryIndex {
+    /// The position of the indexed column in the schema.
+    pub(crate) column: usize,
+    entries: BTree<Vec<Value>>
+}
+
+impl SecondaryIndex {
+    pub fn new(column: usize) -> SecondaryIndex {
+        SecondaryIndex {
+            column,
+            entries: BTree::new()
+        }
+    }
+
+    fn entry_key(value: Value, key: Key) -> Vec<Value> {
+        let mut entry_key = Vec::with_capacity(key.0.len() + 1);
+        entry_key.push(value);
+        entry_key.extend(key.0);
+        entry_key
+    }
+
+    pub fn insert(&mut self, value: Value, key: Key, location: usize) {
+        self.entries.insert(Self::entry_key(value, key), location);
+    }
+
+    pub fn remove(&mut self, value: Value, key: Key) {
+        self.entries.remove(&Self::entry_key(value, key));
+    }
+
+    /// Gets the locations of every row whose value in the column falls within the bounds,
+    /// in ascending order of the column's value.
+    pub fn range(&self, start: Bound<&Value>, end: Bound<&Value>) -> IndexRange<'_> {
+        IndexRange(leading_value_range(&self.entries, start, end))
+    }
+}
+
+/// Ranges over the entries of the tree whose first value falls within the bounds,
+/// regardless of the values that follow it.
+pub fn leading_value_range<'a, K>(tree: &'a BTree<K>, start: Bound<&Value>, end: Bound<&Value>) -> btree::Range<'a, K>
+    where K: AsRef<[Value]> + Clone + Ord
+{
+    tree.range_by(
+        |key| {
+            let leading = &key.as_ref()[0];
+            match start {
+                Bound::Included(start) => leading < start,
+                Bound::Excluded(start) => leading <= start,
+                Bound::Unbounded => false
+            }
+        },
+        |key| {
+            let leading = &key.as_ref()[0];
+            match end {
+                Bound::Included(end) => leading > end,
+                Bound::Excluded(end) => leading >= end,
+                Bound::Unbounded => false
+            }
+        }
+    )
+}
+
+/// The locations of the rows found by walking a secondary index.
+pub struct IndexRange<'a>(btree::Range<'a, Vec<Value>>);
+
+impl<'a> Iterator for IndexRange<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.0.next().map(|(_, location)| location)
+    }
+}
+
+impl<'a> DoubleEndedIterator for IndexRange<'a> {
+    fn next_back(&mut self) -> Option<usize> {
+        self.0.next_back().map(|(_, location)| location)
+    }
+}
@@ -8,13 +8,30 @@ extern crate serde_json;
 extern crate serde_derive;
 
 //mod table;
-mod schema;
-//mod btree;
+pub mod schema;
+mod btree;
 mod row;
 
 //mod table;
 //mod table_lazy;
-mod table;
+pub mod table;
+mod page;
+mod overflow;
+mod pager;
+pub mod error;
+mod wal;
+mod index;
+pub mod database;
+pub mod expression;
+pub mod numeric;
+pub mod temporal;
+pub mod uuid;
+pub mod sql;
+
+#[cfg(test)]
+mod test_utils;
+
+pub use database::Database;
 
 use std::mem::transmute;
 use std::slice::Iter;
@@ -0,0 +1,278 @@
+//! Numbers that aren't plain integers.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// The most significant digits a `Decimal` can have.
+pub const MAX_DECIMAL_PRECISION: u8 = 38;
+
+/// A 64 bit floating point number.
+///
+/// Doubles are ordered by IEEE 754 `totalOrder`, so that they can be used as keys:
+/// negative NaNs sort before every number, positive NaNs sort after every number, and `-0.0` sorts before `0.0`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Double(pub f64);
+
+impl PartialEq for Double {
+    fn eq(&self, other: &Double) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Double {}
+
+impl PartialOrd for Double {
+    fn partial_cmp(&self, other: &Double) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Double {
+    fn cmp(&self, other: &Double) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Doubles are equal only when their bits are, so hashing the bits agrees with equality.
+impl Hash for Double {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+/// An exact decimal number, made up of an integer number of units that are each `10^-scale`.
+///
+/// Decimals with different scales are compared by the numbers they represent, so `1.5` is equal to `1.50`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(try_from = "DecimalParts")]
+pub struct Decimal {
+    units: i128,
+    scale: u8
+}
+
+/// A decimal as it is deserialized, before its scale has been checked.
+#[derive(Deserialize)]
+struct DecimalParts {
+    units: i128,
+    scale: u8
+}
+
+impl TryFrom<DecimalParts> for Decimal {
+    type Error = String;
+
+    fn try_from(parts: DecimalParts) -> Result<Decimal, String> {
+        Decimal::new(parts.units, parts.scale)
+            .ok_or_else(|| format!("a decimal's scale can't be above {}", MAX_DECIMAL_PRECISION))
+    }
+}
+
+impl Decimal {
+    /// Creates the decimal `units * 10^-scale`.
+    /// Returns `None` if the scale is above `MAX_DECIMAL_PRECISION`.
+    pub fn new(units: i128, scale: u8) -> Option<Decimal> {
+        if scale > MAX_DECIMAL_PRECISION {
+            None
+        } else {
+            Some(Decimal { units, scale })
+        }
+    }
+
+    pub fn units(&self) -> i128 {
+        self.units
+    }
+
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    /// The same number with a different scale.
+    /// Returns `None` if the number has more fractional digits than the scale allows, or doesn't fit.
+    pub fn rescale(&self, scale: u8) -> Option<Decimal> {
+        if scale > MAX_DECIMAL_PRECISION {
+            return None
+        }
+        let units = if scale >= self.scale {
+            self.units.checked_mul(10i128.pow(u32::from(scale - self.scale)))?
+        } else {
+            let divisor = 10i128.pow(u32::from(self.scale - scale));
+            if self.units % divisor != 0 {
+                return None
+            }
+            self.units / divisor
+        };
+        Some(Decimal { units, scale })
+    }
+
+    /// The number of significant digits needed to write out the units.
+    pub fn digits(&self) -> u8 {
+        let mut remaining = self.units;
+        let mut digits = 0;
+        while remaining != 0 {
+            remaining /= 10;
+            digits += 1;
+        }
+        digits
+    }
+
+    /// Brings both decimals to the larger of their scales.
+    fn aligned(&self, other: &Decimal) -> Option<(i128, i128, u8)> {
+        let scale = ::std::cmp::max(self.scale, other.scale);
+        Some((self.rescale(scale)?.units, other.rescale(scale)?.units, scale))
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let (left, right, scale) = self.aligned(other)?;
+        Some(Decimal { units: left.checked_add(right)?, scale })
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        let (left, right, scale) = self.aligned(other)?;
+        Some(Decimal { units: left.checked_sub(right)?, scale })
+    }
+
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Decimal> {
+        Decimal::new(self.units.checked_mul(other.units)?, self.scale.checked_add(other.scale)?)
+    }
+
+    /// Splits the number into its integer part and its fractional part,
+    /// with the fractional part expressed in units of `10^-scale`.
+    fn split(&self, scale: u8) -> (i128, i128) {
+        let unit = 10i128.pow(u32::from(self.scale));
+        let fraction = (self.units % unit) * 10i128.pow(u32::from(scale - self.scale));
+        (self.units / unit, fraction)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(integer: i64) -> Decimal {
+        Decimal { units: i128::from(integer), scale: 0 }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        // Rescaling both sides could overflow, but the integer and fractional parts never do.
+        let scale = ::std::cmp::max(self.scale, other.scale);
+        self.split(scale).cmp(&other.split(scale))
+    }
+}
+
+/// Equal decimals have the same units once trailing zeros are dropped, so that is what is hashed.
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let (mut units, mut scale) = (self.units, self.scale);
+        while scale > 0 && units % 10 == 0 {
+            units /= 10;
+            scale -= 1;
+        }
+        (units, scale).hash(state)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (integer, fraction) = self.split(self.scale);
+        let sign = if self.units < 0 { "-" } else { "" };
+        if self.scale == 0 {
+            write!(f, "{}{}", sign, integer.unsigned_abs())
+        } else {
+            write!(f, "{}{}.{:0width$}", sign, integer.unsigned_abs(), fraction.unsigned_abs(), width = self.scale as usize)
+        }
+    }
+}
+
+/// The string wasn't a decimal number, like `-12.50`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseDecimalError;
+
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Decimal, ParseDecimalError> {
+        let (negative, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s)
+        };
+        let (integer, fraction) = match digits.find('.') {
+            Some(point) => (&digits[..point], &digits[point + 1..]),
+            None => (digits, "")
+        };
+        let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if integer.is_empty() || !all_digits(integer) || !all_digits(fraction) || fraction.len() > MAX_DECIMAL_PRECISION as usize {
+            return Err(ParseDecimalError)
+        }
+        let mut units: i128 = 0;
+        for byte in integer.bytes().chain(fraction.bytes()) {
+            units = units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add(i128::from(byte - b'0')))
+                .ok_or(ParseDecimalError)?;
+        }
+        let units = if negative { -units } else { units };
+        Ok(Decimal { units, scale: fraction.len() as u8 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn decimals_compare_by_value() {
+        assert_eq!(decimal("1.5"), decimal("1.50"));
+        assert!(decimal("-1.5") < decimal("-1.25"));
+        assert!(decimal("-0.5") < decimal("0.25"));
+        assert!(decimal("2") > decimal("1.99999"));
+        let mut sorted = vec!(decimal("10"), decimal("-3.1"), decimal("0.001"), decimal("-3.05"), decimal("0"));
+        sorted.sort();
+        assert_eq!(sorted, vec!(decimal("-3.1"), decimal("-3.05"), decimal("0"), decimal("0.001"), decimal("10")));
+    }
+
+    #[test]
+    fn decimal_arithmetic_is_exact() {
+        assert_eq!(decimal("0.1").checked_add(&decimal("0.2")).unwrap().to_string(), "0.3");
+        assert_eq!(decimal("10.00").checked_sub(&decimal("10.01")).unwrap().to_string(), "-0.01");
+        assert_eq!(decimal("1.5").checked_mul(&decimal("-1.5")).unwrap().to_string(), "-2.25");
+        assert_eq!(decimal("1.25").rescale(4).unwrap().to_string(), "1.2500");
+        assert_eq!(decimal("1.25").rescale(1), None);
+        assert_eq!(decimal("-0.05").to_string(), "-0.05");
+        assert!(Decimal::new(i128::max_value(), 0).unwrap().checked_add(&decimal("1")).is_none());
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn extreme_decimals_are_written_out() {
+        assert_eq!(Decimal::new(i128::MIN, 0).unwrap().to_string(), i128::MIN.to_string());
+        assert_eq!(Decimal::new(i128::MIN, 38).unwrap().to_string(), "-1.70141183460469231731687303715884105728");
+    }
+
+    #[test]
+    fn deserialized_decimals_have_a_valid_scale() {
+        let decimal: Decimal = serde_json::from_str(r#"{"units":-125,"scale":2}"#).unwrap();
+        assert_eq!(decimal, "-1.25".parse().unwrap());
+        assert!(serde_json::from_str::<Decimal>(r#"{"units":1,"scale":39}"#).is_err());
+    }
+}
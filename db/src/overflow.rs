@@ -0,0 +1,139 @@
+//! Records that are too large to fit in a data page.
+//!
+//! Such a record is split into chunks, each stored in an overflow page that links to the page holding the next chunk.
+//! The record's slot in the data page holds a pointer to the start of the chain instead of the record itself:
+//!
+//! ```text
+//! +--------+---------------+------------+
+//! | marker | record length | first page |
+//! | u8     | u32           | u32        |
+//! +--------+---------------+------------+
+//! ```
+//!
+//! The marker takes the place of the row format version, and is never used as a version.
+//! Both integers are little-endian.
+
+use page::{Page, OVERFLOW_CAPACITY};
+use pager::Pager;
+use error::{DbError, DbResult};
+
+use std::borrow::Cow;
+
+const POINTER_MARKER: u8 = 0xff;
+const POINTER_SIZE: usize = 9;
+
+/// Where a record that has been moved into overflow pages can be found.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverflowPointer {
+    pub length: usize,
+    pub first_page: usize
+}
+
+impl OverflowPointer {
+    /// Reads the pointer out of a record from a data page, if the record is a pointer.
+    pub fn from_record(record: &[u8]) -> DbResult<Option<OverflowPointer>> {
+        if record.first() != Some(&POINTER_MARKER) {
+            return Ok(None)
+        }
+        if record.len() != POINTER_SIZE {
+            return Err(DbError::CorruptFile("overflow pointer has the wrong length".into()))
+        }
+        let mut length = [0u8; 4];
+        length.copy_from_slice(&record[1..5]);
+        let mut first_page = [0u8; 4];
+        first_page.copy_from_slice(&record[5..9]);
+        Ok(Some(OverflowPointer {
+            length: u32::from_le_bytes(length) as usize,
+            first_page: u32::from_le_bytes(first_page) as usize
+        }))
+    }
+
+    pub fn to_record(self) -> Vec<u8> {
+        let mut record = Vec::with_capacity(POINTER_SIZE);
+        record.push(POINTER_MARKER);
+        record.extend_from_slice(&(self.length as u32).to_le_bytes());
+        record.extend_from_slice(&(self.first_page as u32).to_le_bytes());
+        record
+    }
+}
+
+/// Writes the record into a new chain of overflow pages, returning a pointer to it.
+pub fn write_chain(pager: &mut Pager, record: &[u8]) -> OverflowPointer {
+    // The chain is written back to front, so that each page knows where the next one is.
+    let mut next = None;
+    for chunk in record.chunks(OVERFLOW_CAPACITY).rev() {
+        next = Some(pager.allocate(Page::new_overflow(chunk, next)));
+    }
+    OverflowPointer {
+        length: record.len(),
+        first_page: next.expect("An overflowing record is never empty")
+    }
+}
+
+/// The numbers of the pages in the chain, in order.
+/// A chain that loops back on itself or leads to a page that isn't an overflow page is an error.
+pub fn chain_pages(pager: &Pager, pointer: OverflowPointer) -> DbResult<Vec<usize>> {
+    let mut pages = Vec::new();
+    let mut next = Some(pointer.first_page);
+    while let Some(page_number) = next {
+        if pages.len() >= pager.num_pages() {
+            return Err(DbError::CorruptFile("overflow chain loops back on itself".into()))
+        }
+        let page = pager.page(page_number)
+            .ok_or_else(|| DbError::CorruptFile(format!("overflow page {} is missing", page_number)))?;
+        next = page.overflow_chunk()?.1;
+        pages.push(page_number);
+    }
+    Ok(pages)
+}
+
+/// Reassembles the record held by a chain.
+pub fn read_chain(pager: &Pager, pointer: OverflowPointer) -> DbResult<Vec<u8>> {
+    let mut record = Vec::with_capacity(pointer.length);
+    for page_number in chain_pages(pager, pointer)? {
+        let page = pager.page(page_number).expect("The chain's pages were found to exist");
+        record.extend_from_slice(page.overflow_chunk()?.0);
+    }
+    if record.len() != pointer.length {
+        return Err(DbError::CorruptFile(format!("overflow chain holds {} bytes rather than {}", record.len(), pointer.length)))
+    }
+    Ok(record)
+}
+
+/// Frees every page in the chain.
+pub fn free_chain(pager: &mut Pager, pointer: OverflowPointer) -> DbResult<()> {
+    for page_number in chain_pages(pager, pointer)? {
+        pager.free(page_number);
+    }
+    Ok(())
+}
+
+/// Gets the full record for a record read from a data page, following the chain if the record is a pointer.
+pub fn resolve<'a>(pager: &Pager, record: &'a [u8]) -> DbResult<Cow<'a, [u8]>> {
+    match OverflowPointer::from_record(record)? {
+        Some(pointer) => read_chain(pager, pointer).map(Cow::Owned),
+        None => Ok(Cow::Borrowed(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_round_trip_and_are_reused() {
+        let mut pager = Pager::in_memory();
+        pager.allocate(Page::new_header(b"{}").unwrap());
+        let record: Vec<u8> = (0..OVERFLOW_CAPACITY * 2 + 10).map(|i| i as u8).collect();
+        let pointer = write_chain(&mut pager, &record);
+        assert_eq!(OverflowPointer::from_record(&pointer.to_record()).unwrap(), Some(pointer));
+        assert_eq!(chain_pages(&pager, pointer).unwrap().len(), 3);
+        assert_eq!(read_chain(&pager, pointer).unwrap(), record);
+
+        free_chain(&mut pager, pointer).unwrap();
+        assert!(read_chain(&pager, pointer).is_err());
+        let smaller = write_chain(&mut pager, &record[..OVERFLOW_CAPACITY + 1]);
+        assert_eq!(pager.num_pages(), 4);
+        assert_eq!(read_chain(&pager, smaller).unwrap(), &record[..OVERFLOW_CAPACITY + 1]);
+    }
+}
@@ -0,0 +1,468 @@
+//! Fixed size pages that rows are packed into.
+//!
+//! Every data page is a slotted page:
+//!
+//! ```text
+//! +-----------+----------+-------------+----------------------+ ... +------------------+
+//! | page type | reserved | slot count  | start of record area | ... | records, growing |
+//! | u8        | u8       | u16         | u16                  |     | towards the head |
+//! +-----------+----------+-------------+----------------------+ ... +------------------+
+//! ```
+//!
+//! The header is followed by the slot directory, 4 bytes per slot (offset `u16`, length `u16`).
+//! An offset of 0 marks a slot that is free to be reused.
+//! All integers in the page header and slot directory are little-endian.
+//!
+//! The first page of every file is instead a header page:
+//! the page type, the `ZEPPELIN` magic bytes, a `u16` format version,
+//! then a `u32` length followed by that many bytes of metadata describing the file's contents.
+//!
+//! Records too large for a data page are kept in a chain of overflow pages, described in the `overflow` module.
+//! Overflow pages that are no longer needed become free pages, which are reused before the file is grown.
+
+use error::{DbError, DbResult};
+
+pub const PAGE_SIZE: usize = 8000;
+
+const PAGE_TYPE_OFFSET: usize = 0;
+const SLOT_COUNT_OFFSET: usize = 2;
+const RECORDS_START_OFFSET: usize = 4;
+const PAGE_HEADER_SIZE: usize = 6;
+const SLOT_SIZE: usize = 4;
+
+const MAGIC: &[u8; 8] = b"ZEPPELIN";
+/// Version 2 added a format version to the start of every row, version 3 added a null bitmap to every row,
+/// and version 4 added overflow pages.
+const FORMAT_VERSION: u16 = 4;
+/// The oldest version that can still be read. Each version since only added to what the one before it could hold,
+/// so the pages of an older file are read as they are, and rows carry their own format version.
+const OLDEST_READABLE_VERSION: u16 = 2;
+/// The version in which rows gained a null bitmap.
+pub const NULL_BITMAP_VERSION: u16 = 3;
+const MAGIC_OFFSET: usize = 1;
+const VERSION_OFFSET: usize = 9;
+const METADATA_LENGTH_OFFSET: usize = 11;
+const METADATA_OFFSET: usize = 15;
+
+const NEXT_PAGE_OFFSET: usize = 2;
+const CHUNK_LENGTH_OFFSET: usize = 6;
+const OVERFLOW_HEADER_SIZE: usize = 8;
+
+/// The most metadata that can be stored in a header page.
+pub const MAX_METADATA_SIZE: usize = PAGE_SIZE - METADATA_OFFSET;
+
+/// The largest record that can fit into an otherwise empty page.
+pub const MAX_RECORD_SIZE: usize = PAGE_SIZE - PAGE_HEADER_SIZE - SLOT_SIZE;
+
+/// The number of bytes of a record that each overflow page holds.
+pub const OVERFLOW_CAPACITY: usize = PAGE_SIZE - OVERFLOW_HEADER_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageType {
+    /// The first page of a file, describing what the file contains.
+    Header,
+    /// A slotted page holding rows.
+    Data,
+    /// A page holding part of a record that is too large for a data page.
+    Overflow,
+    /// A page that holds nothing, and can be reused.
+    Free,
+}
+
+impl PageType {
+    fn from_byte(byte: u8) -> Option<PageType> {
+        match byte {
+            0 => Some(PageType::Header),
+            1 => Some(PageType::Data),
+            2 => Some(PageType::Overflow),
+            3 => Some(PageType::Free),
+            _ => None
+        }
+    }
+    fn to_byte(self) -> u8 {
+        match self {
+            PageType::Header => 0,
+            PageType::Data => 1,
+            PageType::Overflow => 2,
+            PageType::Free => 3,
+        }
+    }
+}
+
+/// The position of a record: the page it lives in and its slot within that page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RowLocation {
+    pub page: usize,
+    pub slot: u16
+}
+
+/// Locations are packed into a single number, with the slot in the lowest 16 bits,
+/// so that they can be stored in the leaves of a `BTree`.
+impl From<RowLocation> for usize {
+    fn from(location: RowLocation) -> usize {
+        (location.page << 16) | location.slot as usize
+    }
+}
+
+impl From<usize> for RowLocation {
+    fn from(packed: usize) -> RowLocation {
+        RowLocation {
+            page: packed >> 16,
+            slot: (packed & 0xFFFF) as u16
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Page(Box<[u8; PAGE_SIZE]>);
+
+impl Page {
+    /// Creates an empty data page.
+    pub fn new_data() -> Page {
+        let mut page = Page(Box::new([0u8; PAGE_SIZE]));
+        page.0[PAGE_TYPE_OFFSET] = PageType::Data.to_byte();
+        page.set_slot_count(0);
+        page.set_records_start(PAGE_SIZE);
+        page
+    }
+
+    /// Creates an overflow page holding a chunk of a record, and the number of the page holding the next chunk.
+    /// The chunk can be at most `OVERFLOW_CAPACITY` bytes long.
+    pub fn new_overflow(chunk: &[u8], next: Option<usize>) -> Page {
+        let mut page = Page(Box::new([0u8; PAGE_SIZE]));
+        page.0[PAGE_TYPE_OFFSET] = PageType::Overflow.to_byte();
+        // Page 0 is always the header page, so it can mark the end of the chain.
+        let next = next.unwrap_or(0) as u32;
+        page.0[NEXT_PAGE_OFFSET..CHUNK_LENGTH_OFFSET].copy_from_slice(&next.to_le_bytes());
+        page.write_u16(CHUNK_LENGTH_OFFSET, chunk.len());
+        page.0[OVERFLOW_HEADER_SIZE..OVERFLOW_HEADER_SIZE + chunk.len()].copy_from_slice(chunk);
+        page
+    }
+
+    /// Creates a page that holds nothing.
+    pub fn new_free() -> Page {
+        let mut page = Page(Box::new([0u8; PAGE_SIZE]));
+        page.0[PAGE_TYPE_OFFSET] = PageType::Free.to_byte();
+        page
+    }
+
+    /// Reads the chunk of a record held by an overflow page, along with the number of the page holding the next chunk.
+    pub fn overflow_chunk(&self) -> DbResult<(&[u8], Option<usize>)> {
+        if self.page_type() != Some(PageType::Overflow) {
+            return Err(DbError::CorruptFile("expected an overflow page".into()))
+        }
+        let mut next_bytes = [0u8; 4];
+        next_bytes.copy_from_slice(&self.0[NEXT_PAGE_OFFSET..CHUNK_LENGTH_OFFSET]);
+        let next = match u32::from_le_bytes(next_bytes) {
+            0 => None,
+            next => Some(next as usize)
+        };
+        let length = self.read_u16(CHUNK_LENGTH_OFFSET);
+        if length > OVERFLOW_CAPACITY {
+            return Err(DbError::CorruptFile("overflow chunk length is out of bounds".into()))
+        }
+        Ok((&self.0[OVERFLOW_HEADER_SIZE..OVERFLOW_HEADER_SIZE + length], next))
+    }
+
+    /// Creates a header page holding the provided metadata.
+    pub fn new_header(metadata: &[u8]) -> DbResult<Page> {
+        if metadata.len() > MAX_METADATA_SIZE {
+            return Err(DbError::MetadataTooLarge { size: metadata.len(), max: MAX_METADATA_SIZE })
+        }
+        let mut page = Page(Box::new([0u8; PAGE_SIZE]));
+        page.0[PAGE_TYPE_OFFSET] = PageType::Header.to_byte();
+        page.0[MAGIC_OFFSET..VERSION_OFFSET].copy_from_slice(MAGIC);
+        page.write_u16(VERSION_OFFSET, FORMAT_VERSION as usize);
+        page.0[METADATA_LENGTH_OFFSET..METADATA_OFFSET].copy_from_slice(&(metadata.len() as u32).to_le_bytes());
+        page.0[METADATA_OFFSET..METADATA_OFFSET + metadata.len()].copy_from_slice(metadata);
+        Ok(page)
+    }
+
+    /// The format version of the file a header page starts, checking that the page really is a header this version can read.
+    pub fn header_version(&self) -> DbResult<u16> {
+        if self.page_type() != Some(PageType::Header) || self.0[MAGIC_OFFSET..VERSION_OFFSET] != MAGIC[..] {
+            return Err(DbError::CorruptFile("missing header page".into()))
+        }
+        let version = self.read_u16(VERSION_OFFSET) as u16;
+        if !(OLDEST_READABLE_VERSION..=FORMAT_VERSION).contains(&version) {
+            return Err(DbError::CorruptFile(format!("unsupported format version {}", version)))
+        }
+        Ok(version)
+    }
+
+    /// A copy of the header page that claims to be of another format version, to test reading older files.
+    #[cfg(test)]
+    pub fn with_header_version(&self, version: u16) -> Page {
+        let mut page = Page(self.0.clone());
+        page.write_u16(VERSION_OFFSET, version as usize);
+        page
+    }
+
+    /// Reads the metadata out of a header page, checking that the page really is a header this version can read.
+    pub fn header_metadata(&self) -> DbResult<&[u8]> {
+        self.header_version()?;
+        let mut length_bytes = [0u8; 4];
+        length_bytes.copy_from_slice(&self.0[METADATA_LENGTH_OFFSET..METADATA_OFFSET]);
+        let length = u32::from_le_bytes(length_bytes) as usize;
+        if length > MAX_METADATA_SIZE {
+            return Err(DbError::CorruptFile("header metadata length is out of bounds".into()))
+        }
+        Ok(&self.0[METADATA_OFFSET..METADATA_OFFSET + length])
+    }
+
+    /// Creates a page out of raw bytes read from disk.
+    pub fn from_bytes(bytes: &[u8]) -> Page {
+        let mut page = Page(Box::new([0u8; PAGE_SIZE]));
+        page.0.copy_from_slice(&bytes[..PAGE_SIZE]);
+        page
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    pub fn page_type(&self) -> Option<PageType> {
+        PageType::from_byte(self.0[PAGE_TYPE_OFFSET])
+    }
+
+    fn read_u16(&self, offset: usize) -> usize {
+        u16::from_le_bytes([self.0[offset], self.0[offset + 1]]) as usize
+    }
+
+    fn write_u16(&mut self, offset: usize, value: usize) {
+        let bytes = (value as u16).to_le_bytes();
+        self.0[offset] = bytes[0];
+        self.0[offset + 1] = bytes[1];
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.read_u16(SLOT_COUNT_OFFSET)
+    }
+
+    fn set_slot_count(&mut self, count: usize) {
+        self.write_u16(SLOT_COUNT_OFFSET, count)
+    }
+
+    fn records_start(&self) -> usize {
+        self.read_u16(RECORDS_START_OFFSET)
+    }
+
+    fn set_records_start(&mut self, start: usize) {
+        self.write_u16(RECORDS_START_OFFSET, start)
+    }
+
+    fn slot(&self, slot: usize) -> (usize, usize) {
+        let slot_offset = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        (self.read_u16(slot_offset), self.read_u16(slot_offset + 2))
+    }
+
+    fn set_slot(&mut self, slot: usize, offset: usize, length: usize) {
+        let slot_offset = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        self.write_u16(slot_offset, offset);
+        self.write_u16(slot_offset + 2, length);
+    }
+
+    /// Gets the record stored in the slot, if the slot is occupied.
+    /// A slot that points outside the page's records, as only a corrupt page's can, holds nothing.
+    pub fn get(&self, slot: u16) -> Option<&[u8]> {
+        let slot = slot as usize;
+        let directory_end = PAGE_HEADER_SIZE + self.slot_count() * SLOT_SIZE;
+        if slot >= self.slot_count() || directory_end > PAGE_SIZE {
+            return None
+        }
+        match self.slot(slot) {
+            (0, _) => None,
+            (offset, _) if offset < directory_end => None,
+            (offset, length) => match offset.checked_add(length) {
+                Some(end) if end <= PAGE_SIZE => Some(&self.0[offset..end]),
+                _ => None
+            }
+        }
+    }
+
+    /// Iterates over every occupied slot in the page.
+    pub fn records(&self) -> impl Iterator<Item=(u16, &[u8])> {
+        (0..self.slot_count() as u16)
+            .filter_map(move |slot| self.get(slot).map(|record| (slot, record)))
+    }
+
+    fn first_free_slot(&self) -> Option<usize> {
+        (0..self.slot_count()).find(|slot| self.slot(*slot).0 == 0)
+    }
+
+    /// The number of bytes that are not used by live records or the slot directory.
+    /// Some of these may be fragmented, requiring a compaction before they can be used.
+    pub fn free_space(&self) -> usize {
+        let used_by_records: usize = self.records().map(|(_, record)| record.len()).sum();
+        PAGE_SIZE - PAGE_HEADER_SIZE - self.slot_count() * SLOT_SIZE - used_by_records
+    }
+
+    /// Whether a record of the given size could be inserted into this page.
+    pub fn can_fit(&self, record_size: usize) -> bool {
+        let new_slot_size = if self.first_free_slot().is_some() { 0 } else { SLOT_SIZE };
+        self.free_space() >= record_size + new_slot_size
+    }
+
+    /// Inserts the record into the page, returning the slot it was placed in.
+    /// If the record won't fit, None is returned and the page is left untouched.
+    pub fn insert(&mut self, record: &[u8]) -> Option<u16> {
+        if record.is_empty() || !self.can_fit(record.len()) {
+            return None
+        }
+        let (slot, directory_end) = match self.first_free_slot() {
+            Some(slot) => (slot, PAGE_HEADER_SIZE + self.slot_count() * SLOT_SIZE),
+            None => (self.slot_count(), PAGE_HEADER_SIZE + (self.slot_count() + 1) * SLOT_SIZE)
+        };
+        if self.records_start() < directory_end + record.len() {
+            self.compact();
+        }
+
+        let offset = self.records_start() - record.len();
+        self.0[offset..offset + record.len()].copy_from_slice(record);
+        self.set_records_start(offset);
+        if slot == self.slot_count() {
+            self.set_slot_count(slot + 1);
+        }
+        self.set_slot(slot, offset, record.len());
+        Some(slot as u16)
+    }
+
+    /// Frees the slot. The space used by the record will be reclaimed when the page is next compacted.
+    pub fn delete(&mut self, slot: u16) -> bool {
+        if self.get(slot).is_none() {
+            return false
+        }
+        self.set_slot(slot as usize, 0, 0);
+        // Trailing free slots can be dropped from the directory entirely.
+        let mut count = self.slot_count();
+        while count > 0 && self.slot(count - 1).0 == 0 {
+            count -= 1;
+        }
+        self.set_slot_count(count);
+        if count == 0 {
+            self.set_records_start(PAGE_SIZE);
+        }
+        true
+    }
+
+    /// Replaces the record in the slot, keeping the slot number stable.
+    /// Returns false without modifying the page if the new record won't fit.
+    pub fn replace(&mut self, slot: u16, record: &[u8]) -> bool {
+        let old_length = match self.get(slot) {
+            Some(old) => old.len(),
+            None => return false
+        };
+        if record.len() <= old_length {
+            let (offset, _) = self.slot(slot as usize);
+            self.0[offset..offset + record.len()].copy_from_slice(record);
+            self.set_slot(slot as usize, offset, record.len());
+            return true
+        }
+        if self.free_space() + old_length < record.len() {
+            return false
+        }
+        // Free the old record, then make room at the front of the record area.
+        self.set_slot(slot as usize, 0, 0);
+        let directory_end = PAGE_HEADER_SIZE + self.slot_count() * SLOT_SIZE;
+        if self.records_start() < directory_end + record.len() {
+            self.compact();
+        }
+        let offset = self.records_start() - record.len();
+        self.0[offset..offset + record.len()].copy_from_slice(record);
+        self.set_records_start(offset);
+        self.set_slot(slot as usize, offset, record.len());
+        true
+    }
+
+    /// Moves every live record to the end of the page, so that all free space is contiguous.
+    fn compact(&mut self) {
+        let records: Vec<(u16, Vec<u8>)> = self.records()
+            .map(|(slot, record)| (slot, record.to_vec()))
+            .collect();
+        let mut end = PAGE_SIZE;
+        for (slot, record) in records {
+            let offset = end - record.len();
+            self.0[offset..end].copy_from_slice(&record);
+            self.set_slot(slot as usize, offset, record.len());
+            end = offset;
+        }
+        self.set_records_start(end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_get_delete() {
+        let mut page = Page::new_data();
+        let a = page.insert(&[1, 2, 3]).unwrap();
+        let b = page.insert(&[4, 5]).unwrap();
+        assert_eq!(page.get(a), Some(&[1u8, 2, 3][..]));
+        assert_eq!(page.get(b), Some(&[4u8, 5][..]));
+
+        assert!(page.delete(a));
+        assert_eq!(page.get(a), None);
+        // The freed slot is reused.
+        let c = page.insert(&[6]).unwrap();
+        assert_eq!(a, c);
+        assert_eq!(page.get(b), Some(&[4u8, 5][..]));
+    }
+
+    #[test]
+    fn fills_up_and_compacts() {
+        let mut page = Page::new_data();
+        let record = [7u8; 100];
+        let mut slots = Vec::new();
+        while let Some(slot) = page.insert(&record) {
+            slots.push(slot);
+        }
+        assert_eq!(slots.len(), (PAGE_SIZE - PAGE_HEADER_SIZE) / (100 + SLOT_SIZE));
+
+        // Free every other record, then insert something that only fits after compaction.
+        for slot in slots.iter().step_by(2) {
+            page.delete(*slot);
+        }
+        let big = [9u8; 150];
+        let slot = page.insert(&big).unwrap();
+        assert_eq!(page.get(slot), Some(&big[..]));
+        for slot in slots.iter().skip(1).step_by(2) {
+            assert_eq!(page.get(*slot), Some(&record[..]));
+        }
+    }
+
+    #[test]
+    fn replace_grows_record() {
+        let mut page = Page::new_data();
+        let a = page.insert(&[1; 10]).unwrap();
+        let b = page.insert(&[2; 10]).unwrap();
+        assert!(page.replace(a, &[3; 20]));
+        assert_eq!(page.get(a), Some(&[3u8; 20][..]));
+        assert_eq!(page.get(b), Some(&[2u8; 10][..]));
+        assert!(!page.replace(b, &[4; PAGE_SIZE]));
+    }
+
+    #[test]
+    fn corrupt_slots_hold_nothing() {
+        let mut page = Page::new_data();
+        let a = page.insert(&[1, 2, 3]).unwrap();
+        let b = page.insert(&[4, 5]).unwrap();
+        page.set_slot(a as usize, PAGE_SIZE - 1, 2);
+        assert_eq!(page.get(a), None);
+        page.set_slot(a as usize, PAGE_HEADER_SIZE, 2);
+        assert_eq!(page.get(a), None);
+        assert_eq!(page.get(b), Some(&[4u8, 5][..]));
+        page.set_slot_count(PAGE_SIZE);
+        assert_eq!(page.get(b), None);
+    }
+
+    #[test]
+    fn overflow_chunks() {
+        let page = Page::new_overflow(&[5; OVERFLOW_CAPACITY], Some(3));
+        assert_eq!(page.overflow_chunk().unwrap(), (&[5u8; OVERFLOW_CAPACITY][..], Some(3)));
+        let last = Page::new_overflow(&[6, 7], None);
+        assert_eq!(last.overflow_chunk().unwrap(), (&[6u8, 7][..], None));
+        assert!(Page::new_data().overflow_chunk().is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use page::{Page, PageType, PAGE_SIZE};
+use error::{DbError, DbResult};
+use wal::checksum;
+
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+/// A page in the journal: its page number as a little-endian `u32`, followed by its bytes.
+const JOURNAL_ENTRY_SIZE: usize = 4 + PAGE_SIZE;
+
+/// Owns the pages of a table, and keeps them in sync with the file that backs them.
+///
+/// Every page of the file is held in memory.
+/// Modified pages are tracked, and are written back to the file when the pager is flushed.
+/// A pager that isn't backed by a file just keeps its pages in memory.
+///
+/// A flush is atomic. The modified pages are first written to a journal next to the file,
+/// followed by a checksum of them, and only once the journal is on disk are the pages written in place.
+/// If that is cut short by a crash, the journal is there to finish it when the file is next opened.
+/// A journal that is incomplete was never acted upon, so it is thrown away.
+///
+/// The pager keeps track of which pages are free, and of how much room each data page has,
+/// so that neither allocating a page nor finding room for a record has to look through every page.
+/// Pages should only be freed through `free`.
+pub struct Pager {
+    file: Option<File>,
+    journal: Option<PathBuf>,
+    pages: Vec<Page>,
+    dirty: BTreeSet<usize>,
+    free_pages: BTreeSet<usize>,
+    /// The data pages, as `(free space, page number)`, so they can be looked up by how much room they have.
+    space_map: BTreeSet<(usize, usize)>,
+    /// The free space each page was last entered into the space map with.
+    mapped_space: Vec<Option<usize>>,
+    /// Pages that may have changed since they were last entered into the space map.
+    unmapped: BTreeSet<usize>,
+    /// How many more writes a flush may make before it stops, as though the process had been killed.
+    #[cfg(test)]
+    pub crash_after_writes: Option<usize>
+}
+
+impl Pager {
+    fn with_pages(file: Option<(File, PathBuf)>, pages: Vec<Page>) -> Pager {
+        let free_pages = pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.page_type() == Some(PageType::Free))
+            .map(|(page_number, _)| page_number)
+            .collect();
+        let (file, journal) = match file {
+            Some((file, journal)) => (Some(file), Some(journal)),
+            None => (None, None)
+        };
+        Pager {
+            file,
+            journal,
+            dirty: BTreeSet::new(),
+            free_pages,
+            space_map: BTreeSet::new(),
+            mapped_space: vec!(None; pages.len()),
+            unmapped: (0..pages.len()).collect(),
+            pages,
+            #[cfg(test)]
+            crash_after_writes: None
+        }
+    }
+
+    /// Creates a pager that only lives in memory.
+    pub fn in_memory() -> Pager {
+        Pager::with_pages(None, Vec::new())
+    }
+
+    /// Creates a new, empty file for the pager.
+    /// This will fail if the file already exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> DbResult<Pager> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path.as_ref())?;
+        // A journal left behind by an earlier file of the same name must not be applied to this one.
+        let journal = journal_path(path.as_ref());
+        remove_journal(&journal)?;
+        Ok(Pager::with_pages(Some((file, journal)), Vec::new()))
+    }
+
+    /// Opens an existing file, reading every page into memory.
+    /// A flush that a crash cut short is finished first.
+    pub fn open<P: AsRef<Path>>(path: P) -> DbResult<Pager> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.as_ref())?;
+        let journal = journal_path(path.as_ref());
+        recover_journal(&mut file, &journal)?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        if bytes.len() % PAGE_SIZE != 0 {
+            return Err(DbError::CorruptFile(format!("file length {} is not a multiple of the page size", bytes.len())))
+        }
+        let pages = bytes
+            .chunks(PAGE_SIZE)
+            .map(Page::from_bytes)
+            .collect();
+        Ok(Pager::with_pages(Some((file, journal)), pages))
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn page(&self, page_number: usize) -> Option<&Page> {
+        self.pages.get(page_number)
+    }
+
+    /// Gets a page for modification, marking it to be written out on the next flush.
+    pub fn page_mut(&mut self, page_number: usize) -> Option<&mut Page> {
+        if page_number < self.pages.len() {
+            self.dirty.insert(page_number);
+            self.unmapped.insert(page_number);
+        }
+        self.pages.get_mut(page_number)
+    }
+
+    /// Stores the page in the first free page, or adds it to the end of the file if there are none,
+    /// returning its page number.
+    pub fn allocate(&mut self, page: Page) -> usize {
+        let page_number = match self.take_free_page() {
+            Some(page_number) => {
+                self.pages[page_number] = page;
+                page_number
+            }
+            None => {
+                self.pages.push(page);
+                self.mapped_space.push(None);
+                self.pages.len() - 1
+            }
+        };
+        self.dirty.insert(page_number);
+        self.unmapped.insert(page_number);
+        page_number
+    }
+
+    fn take_free_page(&mut self) -> Option<usize> {
+        while let Some(page_number) = self.free_pages.iter().next().cloned() {
+            self.free_pages.remove(&page_number);
+            // The page may have been overwritten through `page_mut` since it was freed.
+            if self.pages[page_number].page_type() == Some(PageType::Free) {
+                return Some(page_number)
+            }
+        }
+        None
+    }
+
+    /// Marks the page as free, so that it can be reused by the next allocation.
+    pub fn free(&mut self, page_number: usize) {
+        if let Some(page) = self.page_mut(page_number) {
+            *page = Page::new_free();
+            self.free_pages.insert(page_number);
+        }
+    }
+
+    /// Finds a data page that has room for a record of the given size, preferring the fullest such page.
+    pub fn find_page_with_space(&mut self, record_size: usize) -> Option<usize> {
+        self.update_space_map();
+        let pages = &self.pages;
+        // Whether a record fits also depends on whether the page has a slot to spare,
+        // so only the pages with barely enough room may have to be passed over.
+        self.space_map
+            .range((record_size, 0)..)
+            .map(|&(_, page_number)| page_number)
+            .find(|&page_number| pages[page_number].can_fit(record_size))
+    }
+
+    fn update_space_map(&mut self) {
+        for page_number in mem::take(&mut self.unmapped) {
+            if let Some(space) = self.mapped_space[page_number].take() {
+                self.space_map.remove(&(space, page_number));
+            }
+            let page = &self.pages[page_number];
+            if page.page_type() == Some(PageType::Data) {
+                let space = page.free_space();
+                self.space_map.insert((space, page_number));
+                self.mapped_space[page_number] = Some(space);
+            }
+        }
+    }
+
+    /// Writes every modified page back to the file, by way of the journal, and waits for it to reach the disk.
+    pub fn flush(&mut self) -> DbResult<()> {
+        if let Some(journal) = self.journal.clone() {
+            if !self.dirty.is_empty() {
+                self.write_journal(&journal)?;
+            }
+            for page_number in self.dirty.clone() {
+                self.make_write()?;
+                let file = self.file.as_mut().expect("A pager with a journal has a file");
+                file.seek(SeekFrom::Start((page_number * PAGE_SIZE) as u64))?;
+                file.write_all(self.pages[page_number].as_bytes())?;
+            }
+            self.file.as_mut().expect("A pager with a journal has a file").sync_all()?;
+            remove_journal(&journal)?;
+        }
+        self.dirty.clear();
+        Ok(())
+    }
+
+    /// Writes every modified page to the journal, and waits for it to reach the disk.
+    fn write_journal(&mut self, journal: &Path) -> DbResult<()> {
+        let mut bytes = Vec::with_capacity(self.dirty.len() * JOURNAL_ENTRY_SIZE + 4);
+        for page_number in &self.dirty {
+            bytes.extend_from_slice(&(*page_number as u32).to_le_bytes());
+            bytes.extend_from_slice(self.pages[*page_number].as_bytes());
+        }
+        let checksum = checksum(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        self.make_write()?;
+        let mut file = File::create(journal)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        sync_directory(journal)
+    }
+
+    /// Stops the flush with an error if it has made as many writes as the test allows.
+    #[cfg(test)]
+    fn make_write(&mut self) -> DbResult<()> {
+        match self.crash_after_writes {
+            Some(0) => Err(io::Error::other("the flush was stopped").into()),
+            Some(ref mut writes) => {
+                *writes -= 1;
+                Ok(())
+            }
+            None => Ok(())
+        }
+    }
+
+    #[cfg(not(test))]
+    fn make_write(&mut self) -> DbResult<()> {
+        Ok(())
+    }
+}
+
+/// The path of the journal belonging to the file at the given path.
+fn journal_path(path: &Path) -> PathBuf {
+    let mut journal = path.as_os_str().to_owned();
+    journal.push(".journal");
+    PathBuf::from(journal)
+}
+
+/// Reads the pages out of a journal, or returns `None` if the journal was never completely written.
+fn decode_journal(bytes: &[u8]) -> Option<Vec<(usize, &[u8])>> {
+    if bytes.len() < 4 || !(bytes.len() - 4).is_multiple_of(JOURNAL_ENTRY_SIZE) {
+        return None
+    }
+    let (entries, checksum_bytes) = bytes.split_at(bytes.len() - 4);
+    let mut expected = [0u8; 4];
+    expected.copy_from_slice(checksum_bytes);
+    if checksum(entries) != u32::from_le_bytes(expected) {
+        return None
+    }
+    Some(entries
+        .chunks(JOURNAL_ENTRY_SIZE)
+        .map(|entry| {
+            let mut page_number = [0u8; 4];
+            page_number.copy_from_slice(&entry[..4]);
+            (u32::from_le_bytes(page_number) as usize, &entry[4..])
+        })
+        .collect())
+}
+
+/// Finishes the flush recorded in the journal, if there is one, and removes the journal.
+fn recover_journal(file: &mut File, journal: &Path) -> DbResult<()> {
+    let bytes = match fs::read(journal) {
+        Ok(bytes) => bytes,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into())
+    };
+    if let Some(pages) = decode_journal(&bytes) {
+        for (page_number, bytes) in pages {
+            file.seek(SeekFrom::Start((page_number * PAGE_SIZE) as u64))?;
+            file.write_all(bytes)?;
+        }
+        file.sync_all()?;
+    }
+    remove_journal(journal)
+}
+
+/// Removes the journal, making sure that it stays removed, so that it is never applied over later flushes.
+fn remove_journal(journal: &Path) -> DbResult<()> {
+    match fs::remove_file(journal) {
+        Ok(()) => sync_directory(journal),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into())
+    }
+}
+
+/// Waits for the creation or removal of the file to reach the disk.
+fn sync_directory(path: &Path) -> DbResult<()> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new(".")
+    };
+    File::open(directory)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::temp_dir;
+
+    #[test]
+    fn tracks_free_pages_and_space() {
+        let mut pager = Pager::in_memory();
+        pager.allocate(Page::new_header(b"{}").unwrap());
+        assert_eq!(pager.find_page_with_space(10), None);
+        let roomy = pager.allocate(Page::new_data());
+        let full = pager.allocate(Page::new_data());
+        pager.page_mut(full).unwrap().insert(&[1; PAGE_SIZE / 2]).unwrap();
+        assert_eq!(pager.find_page_with_space(10), Some(full));
+        assert_eq!(pager.find_page_with_space(PAGE_SIZE / 2), Some(roomy));
+
+        pager.page_mut(roomy).unwrap().insert(&[2; PAGE_SIZE - 100]).unwrap();
+        assert_eq!(pager.find_page_with_space(PAGE_SIZE / 2), None);
+
+        pager.free(full);
+        assert_eq!(pager.find_page_with_space(200), None);
+        assert_eq!(pager.allocate(Page::new_data()), full);
+        assert_eq!(pager.find_page_with_space(200), Some(full));
+        assert_eq!(pager.allocate(Page::new_data()), 3);
+    }
+
+    #[test]
+    fn only_complete_journals_are_applied() {
+        let path = temp_dir("journal").join("table");
+        let journal = journal_path(&path);
+        let mut pager = Pager::create(&path).unwrap();
+        pager.allocate(Page::new_header(b"{}").unwrap());
+        let data = pager.allocate(Page::new_data());
+        pager.flush().unwrap();
+        assert!(!journal.exists());
+
+        pager.page_mut(data).unwrap().insert(&[1, 2, 3]).unwrap();
+        pager.crash_after_writes = Some(1);
+        assert!(pager.flush().is_err());
+        let complete = fs::read(&journal).unwrap();
+        fs::write(&journal, &complete[..complete.len() - 1]).unwrap();
+        assert_eq!(Pager::open(&path).unwrap().page(data).unwrap().get(0), None);
+        assert!(!journal.exists());
+
+        fs::write(&journal, &complete).unwrap();
+        assert_eq!(Pager::open(&path).unwrap().page(data).unwrap().get(0), Some(&[1u8, 2, 3][..]));
+        assert!(!journal.exists());
+    }
+}
@@ -1 +1,96 @@
+//! The format rows are stored in.
+//!
+//! Each record in a data page is a row prefixed by the version of the format it was written in:
+//!
+//! ```text
+//! +---------+-------------+----------+----------+-----+
+//! | version | null bitmap | column 0 | column 1 | ... |
+//! | u8      |             |          |          |     |
+//! +---------+-------------+----------+----------+-----+
+//! ```
+//!
+//! The null bitmap has one bit per column, rounded up to a whole number of bytes.
+//! Bit `i % 8` of byte `i / 8` is set when column `i` is null, in which case the column has no bytes in the row.
+//!
+//! The remaining columns follow one another in schema order, with no padding between them.
+//! Each column is laid out according to its type:
+//!
+//! * `Integer` - 4 bytes, a little-endian two's complement `i32`.
+//! * `BigInt` - 8 bytes, a little-endian two's complement `i64`.
+//! * `String { length }` - `length` bytes of UTF-8, padded with zero bytes after the end of the string.
+//!   The padding is removed when the string is read, so a string can't end with a zero byte.
+//! * `Text` - a little-endian `u32` byte length, followed by that many bytes of UTF-8.
+//! * `Boolean` - 1 byte, either 0 or 1.
+//! * `Double` - 8 bytes, the little-endian bits of an IEEE 754 `f64`.
+//! * `Decimal { scale, .. }` - 16 bytes, a little-endian two's complement `i128` count of `10^-scale` units.
+//! * `Date` - 4 bytes, a little-endian `i32` count of days since 1970-01-01.
+//! * `Time` - 8 bytes, a little-endian `i64` count of microseconds since midnight.
+//! * `Timestamp` - 8 bytes, a little-endian `i64` count of microseconds since 1970-01-01T00:00:00Z.
+//! * `Uuid` - 16 bytes, in the order they are written out.
+//! * `Bytes` - a little-endian `u32` byte length, followed by that many bytes.
+//!
+//! Version 1 had no null bitmap, as no column could be null. Version 1 rows are still read, as rows in which nothing is null.
+//!
+//! The write-ahead log stores rows without the version byte.
+
+use error::{DbError, DbResult};
+
+use std::borrow::Cow;
+use std::slice::Iter;
+
 pub type BoxedRow = Box<[u8]>;
+
+/// The version of the row format that rows are written in.
+pub const ROW_FORMAT_VERSION: u8 = 2;
+
+/// Prepends the format version to the row, producing the record that gets stored in a page.
+pub fn row_to_record(row: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(row.len() + 1);
+    record.push(ROW_FORMAT_VERSION);
+    record.extend_from_slice(row);
+    record
+}
+
+/// Gets the row out of a record read from a page, checking that it is in a format that can be read.
+/// A row in an older format is brought up to the current one, which takes a copy.
+pub fn record_to_row<'a>(record: &'a [u8], num_columns: usize) -> DbResult<Cow<'a, [u8]>> {
+    match record.first() {
+        Some(&ROW_FORMAT_VERSION) => Ok(Cow::Borrowed(&record[1..])),
+        Some(&1) => Ok(Cow::Owned(add_null_bitmap(&record[1..], num_columns))),
+        Some(version) => Err(DbError::CorruptFile(format!("unsupported row format version {}", version))),
+        None => Err(DbError::CorruptFile("empty record".into()))
+    }
+}
+
+/// Brings a version 1 row up to the current format, by giving it a null bitmap in which no column is null.
+pub fn add_null_bitmap(row: &[u8], num_columns: usize) -> Vec<u8> {
+    let mut upgraded = vec!(0u8; null_bitmap_size(num_columns));
+    upgraded.extend_from_slice(row);
+    upgraded
+}
+
+/// Takes the next `count` bytes of a row, failing if the row ends first.
+pub fn take_bytes<'a>(iter: &mut Iter<'a, u8>, count: usize) -> DbResult<&'a [u8]> {
+    let remaining = iter.as_slice();
+    if remaining.len() < count {
+        return Err(DbError::CorruptFile("row ended part way through a value".into()))
+    }
+    let (taken, rest) = remaining.split_at(count);
+    *iter = rest.iter();
+    Ok(taken)
+}
+
+/// The number of bytes in the null bitmap of a row with this many columns.
+pub fn null_bitmap_size(num_columns: usize) -> usize {
+    num_columns.div_ceil(8)
+}
+
+/// Whether the null bitmap marks the column as null.
+pub fn is_null(null_bitmap: &[u8], column: usize) -> bool {
+    null_bitmap[column / 8] & (1 << (column % 8)) != 0
+}
+
+/// Marks the column as null in the null bitmap.
+pub fn set_null(null_bitmap: &mut [u8], column: usize) {
+    null_bitmap[column / 8] |= 1 << (column % 8);
+}
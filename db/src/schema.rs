@@ -1,11 +1,15 @@
 use table::Value;
 use table::Tuple;
-use row::BoxedRow;
+use table::Key;
+use error::{DbError, DbResult};
+use numeric::{Decimal, MAX_DECIMAL_PRECISION};
+use row::{take_bytes, null_bitmap_size, is_null};
 pub type Name = String;
 use std::marker::PhantomData;
 use std::slice::Iter;
+use std::str::FromStr;
 
-#[derive( Clone, Serialize, Deserialize)]
+#[derive( Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ColumnMetadata {
     pub(crate) name: Name,
     pub(crate) db_type: DbType,
@@ -21,119 +25,424 @@ impl ColumnMetadata {
            db_type,
            attribute: None,
            constraints: Vec::new(),
-           is_index: false // Nothing prevents multiple columns from being indexes, but there should only be 1
+           is_index: false
        }
     }
+    /// Creates a column that is part of the table's key.
+    /// If more than one column is created this way, the key is made up of all of them,
+    /// ordered by their position in the schema.
     pub fn new_index(name: Name, db_type:DbType) -> ColumnMetadata {
          ColumnMetadata {
            name,
            db_type,
            attribute: None,
            constraints: Vec::new(),
-           is_index: true // Nothing prevents multiple columns from being indexes, but there should only be 1
+           is_index: true
        }
     }
+
+    /// Adds a constraint that values in this column must satisfy.
+    pub fn with_constraint(mut self, constraint: Constraint) -> ColumnMetadata {
+        if !self.has_constraint(&constraint) {
+            self.constraints.push(constraint);
+        }
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn db_type(&self) -> &DbType {
+        &self.db_type
+    }
+
+    pub fn has_constraint(&self, constraint: &Constraint) -> bool {
+        self.constraints.contains(constraint)
+    }
+
+    /// Whether the column may hold `Value::Null`.
+    /// Every column may, unless it has a `NotNull` constraint or is part of the table's key.
+    pub fn is_nullable(&self) -> bool {
+        !self.is_index && !self.has_constraint(&Constraint::NotNull)
+    }
+
+    /// Makes sure that the value can be stored in this column.
+    pub fn check_value(&self, value: &Value) -> DbResult<()> {
+        match (value, &self.db_type) {
+            (&Value::Null, _) if self.is_nullable() => Ok(()),
+            (&Value::Null, _) => Err(DbError::NotNullViolation { column: self.name.clone() }),
+            (&Value::Integer(_), &DbType::Integer)
+            | (&Value::BigInt(_), &DbType::BigInt)
+            | (&Value::Boolean(_), &DbType::Boolean)
+            | (&Value::Double(_), &DbType::Double)
+            | (&Value::Date(_), &DbType::Date)
+            | (&Value::Timestamp(_), &DbType::Timestamp)
+            | (&Value::Uuid(_), &DbType::Uuid) => Ok(()),
+            (&Value::Time(time), &DbType::Time) => {
+                if time.is_valid() {
+                    Ok(())
+                } else {
+                    Err(DbError::InvalidValue { column: self.name.clone(), reason: "the time is outside of a day".into() })
+                }
+            }
+            (&Value::Decimal(ref decimal), &DbType::Decimal { precision, scale }) => {
+                match decimal.rescale(scale) {
+                    Some(ref rescaled) if rescaled.digits() <= precision => Ok(()),
+                    _ => Err(DbError::NumericOverflow { column: self.name.clone(), value: value.clone() })
+                }
+            }
+            (&Value::String(ref s), &DbType::String { length }) => {
+                if s.len() > length as usize {
+                    Err(DbError::StringTooLong { column: self.name.clone(), length: s.len(), max: length as usize })
+                } else if s.ends_with('\0') {
+                    // Fixed length strings are padded out with null bytes, which are dropped again when they are read.
+                    Err(DbError::InvalidValue { column: self.name.clone(), reason: "the string ends in a null character".into() })
+                } else {
+                    Ok(())
+                }
+            }
+            (&Value::String(ref s), &DbType::Text) => {
+                if s.len() > u32::max_value() as usize {
+                    Err(DbError::StringTooLong { column: self.name.clone(), length: s.len(), max: u32::max_value() as usize })
+                } else {
+                    Ok(())
+                }
+            }
+            (&Value::Bytes(ref bytes), &DbType::Bytes) => {
+                if bytes.len() > u32::max_value() as usize {
+                    Err(DbError::InvalidValue { column: self.name.clone(), reason: format!("{} bytes is too long to store", bytes.len()) })
+                } else {
+                    Ok(())
+                }
+            }
+            (value, db_type) => Err(DbError::TypeMismatch {
+                column: self.name.clone(),
+                expected: db_type.clone(),
+                found: value.clone()
+            })
+        }
+    }
+
+    /// Converts the value to the column's type, where that can be done without losing information,
+    /// and makes sure that the result can be stored in the column.
+    ///
+    /// Integers can be stored in `BigInt` and `Decimal` columns,
+    /// decimals are brought to the column's scale,
+    /// strings in ISO-8601 format can be stored in `Date`, `Time` and `Timestamp` columns,
+    /// and hyphenated strings of hexadecimal digits can be stored in `Uuid` columns.
+    pub fn coerce(&self, value: Value) -> DbResult<Value> {
+        let value = match (value, &self.db_type) {
+            (Value::Integer(integer), &DbType::BigInt) => Value::BigInt(i64::from(integer)),
+            (Value::Integer(integer), &DbType::Decimal { scale, .. }) => self.rescale(Decimal::from(i64::from(integer)), scale)?,
+            (Value::BigInt(integer), &DbType::Decimal { scale, .. }) => self.rescale(Decimal::from(integer), scale)?,
+            (Value::Decimal(decimal), &DbType::Decimal { scale, .. }) => self.rescale(decimal, scale)?,
+            (Value::String(s), &DbType::Date) => Value::Date(self.parse(&s, "YYYY-MM-DD")?),
+            (Value::String(s), &DbType::Time) => Value::Time(self.parse(&s, "HH:MM:SS")?),
+            (Value::String(s), &DbType::Timestamp) => Value::Timestamp(self.parse(&s, "YYYY-MM-DDTHH:MM:SSZ")?),
+            (Value::String(s), &DbType::Uuid) => Value::Uuid(self.parse(&s, "XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX")?),
+            (value, _) => value
+        };
+        self.check_value(&value)?;
+        Ok(value)
+    }
+
+    fn parse<T: FromStr>(&self, s: &str, format: &str) -> DbResult<T> {
+        s.parse().map_err(|_| DbError::InvalidValue {
+            column: self.name.clone(),
+            reason: format!("{:?} is not in the format {}", s, format)
+        })
+    }
+
+    fn rescale(&self, decimal: Decimal, scale: u8) -> DbResult<Value> {
+        decimal.rescale(scale)
+            .map(Value::Decimal)
+            .ok_or_else(|| DbError::NumericOverflow { column: self.name.clone(), value: Value::Decimal(decimal) })
+    }
+
+    /// Makes the column reference a column of another table.
+    pub fn with_foreign_key(mut self, foreign_key: ForeignKey) -> ColumnMetadata {
+        self.attribute = Some(Attribute::ForeignKey(foreign_key));
+        self
+    }
+
+    pub fn foreign_key(&self) -> Option<&ForeignKey> {
+        match self.attribute {
+            Some(Attribute::ForeignKey(ref foreign_key)) => Some(foreign_key),
+            _ => None
+        }
+    }
 }
 
 // TODO, would it make sense to embed these inside of db_type??
-#[derive( Clone, Serialize, Deserialize)]
+#[derive( Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Constraint {
+    /// The column may not hold null values.
     NotNull,
+    /// No two rows may have the same value in the column.
     Unique,
+    /// If a value isn't provided for the column, the next number in an increasing sequence is used.
+    /// Only applies to `Integer` and `BigInt` columns.
     Serial,
 }
 
-#[derive( Clone, Serialize, Deserialize)]
+#[derive( Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Attribute {
     PrimaryKey,
-    ForeignKey,
+    /// Every value in the column must be present in a column of another table.
+    ForeignKey(ForeignKey),
+}
+
+/// A reference from a column to a column in another table (or the same table).
+/// The referenced column must hold unique values, so that each value identifies a single row.
+#[derive( Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub(crate) table: Name,
+    pub(crate) column: Name,
+    pub(crate) on_delete: ReferentialAction
+}
+
+impl ForeignKey {
+    pub fn new(table: Name, column: Name, on_delete: ReferentialAction) -> ForeignKey {
+        ForeignKey {
+            table,
+            column,
+            on_delete
+        }
+    }
+}
+
+/// What happens to referencing rows when the row they reference is deleted.
+#[derive( Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReferentialAction {
+    /// The referenced row can't be deleted while anything references it.
+    Restrict,
+    /// The referencing rows are deleted along with the referenced row.
+    Cascade,
 }
 
-#[derive( Clone, Serialize, Deserialize)]
+#[derive( Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Schema {
     pub(crate) columns: Box<[ColumnMetadata]>
 }
 
 impl Schema {
+    pub fn new(columns: Vec<ColumnMetadata>) -> Schema {
+        Schema {
+            columns: columns.into_boxed_slice()
+        }
+    }
+
+    pub fn columns(&self) -> &[ColumnMetadata] {
+        &self.columns
+    }
+
     /// This gets the number of bytes the _contents_ of a row conforming to this schema should take up.
     /// It does *NOT* account for any metadata bits that are associated with a row.
+    /// If any column varies in length, this is the smallest a row can be.
     fn row_contents_sized_bytes(&self) -> usize {
         self.columns
             .iter()
             .fold(0, |acc, column, | acc + column.db_type.size_bytes())
     }
+    /// The size of a row along with its null bitmap and the format version that precedes it in a page.
     pub fn row_and_metadata_sized_bytes(&self) -> usize {
-        self.row_contents_sized_bytes() + 1
+        self.row_contents_sized_bytes() + null_bitmap_size(self.columns.len()) + 1
     }
 
-    pub fn extract_index_value_from_row(&self, row: BoxedRow) -> Value {
-        let fun = self.generate_extract_index_value_from_row_fn();
-        let tuples: Tuple = (fun)(&*row).unwrap();
-        let index_number: usize = self.columns
+    /// The positions of the columns that make up the key, in the order they appear in the schema.
+    /// Any number of columns can be marked as an index; together, they form the key.
+    pub fn key_columns(&self) -> Vec<usize> {
+        self.columns
             .iter()
             .enumerate()
-            .find(|x| x.1.is_index)
-            .unwrap()
-            .0;
-        tuples.get(index_number).cloned().unwrap() // There better be an index
+            .filter(|x| x.1.is_index)
+            .map(|x| x.0)
+            .collect()
     }
 
+    /// Makes sure that the tuple has a value for every column, and that each value can be stored in its column.
+    /// The first problem found is reported, naming the column it was found in.
+    pub fn check_tuple(&self, tuple: &Tuple) -> DbResult<()> {
+        if tuple.len() != self.columns.len() {
+            return Err(DbError::WrongNumberOfValues { expected: self.columns.len(), found: tuple.len() })
+        }
+        self.columns
+            .iter()
+            .zip(tuple)
+            .map(|(column, value)| column.check_value(value))
+            .collect()
+    }
 
-
-    fn generate_extract_index_value_from_row_fn(&self) -> impl Fn(&Row) -> Option<Tuple> {
-        let index_column = self.columns
+    /// Converts each value in the tuple to its column's type, checking the tuple in the same way as `check_tuple`.
+    pub fn coerce_tuple(&self, tuple: Tuple) -> DbResult<Tuple> {
+        if tuple.len() != self.columns.len() {
+            return Err(DbError::WrongNumberOfValues { expected: self.columns.len(), found: tuple.len() })
+        }
+        self.columns
             .iter()
-            .find(|x| x.is_index == true)
-            .expect("There must be one column that is an index");
-        let index_db_type: DbType = index_column.db_type.clone();
+            .zip(tuple)
+            .map(|(column, value)| column.coerce(value))
+            .collect()
+    }
+
+    /// Picks the values of the key columns out of a tuple that has every column in the schema.
+    pub fn key_from_tuple(&self, tuple: &Tuple) -> Key {
+        Key(self.key_columns()
+            .into_iter()
+            .filter_map(|index| tuple.get(index).cloned())
+            .collect())
+    }
+
+    pub fn extract_key_from_row(&self, row: &Row) -> DbResult<Key> {
+        let fun = self.generate_extract_key_fn();
+        (fun)(row)
+    }
 
+    /// Makes sure that the schema can be used for a table:
+    /// there must be at least one column marked as an index to make up the key, and every column's type must be valid.
+    pub fn check(&self) -> DbResult<()> {
+        if !self.columns.iter().any(|column| column.is_index) {
+            return Err(DbError::NoKeyColumns)
+        }
+        for column in self.columns.iter() {
+            if let DbType::Decimal { precision, scale } = column.db_type {
+                if precision == 0 || precision > MAX_DECIMAL_PRECISION || scale > precision {
+                    return Err(DbError::InvalidColumnType {
+                        column: column.name.clone(),
+                        reason: format!("a decimal's precision must be between 1 and {}, and at least its scale", MAX_DECIMAL_PRECISION)
+                    })
+                }
+            }
+            let is_number = column.db_type == DbType::Integer || column.db_type == DbType::BigInt;
+            if column.has_constraint(&Constraint::Serial) && !is_number {
+                return Err(DbError::InvalidColumnType {
+                    column: column.name.clone(),
+                    reason: "only INT and BIGINT columns can be SERIAL".into()
+                })
+            }
+        }
+        Ok(())
+    }
 
+    /// Create a function that reads the values of the key columns out of a row,
+    /// skipping over the bytes of every other column.
+    pub fn generate_extract_key_fn(&self) -> impl Fn(&Row) -> DbResult<Key> {
+        let num_key_columns = self.key_columns().len();
         let extractors: Vec<Extractor> = self.columns
             .iter()
             .map(|schema_column| {
                 let db_type = schema_column.db_type.clone();
-                // If the columns list contains this type, then create a function to get the type from the row
-                if index_db_type == schema_column.db_type {
-
-                    let f = move |iter: &mut Iter<u8>| -> Option<Value> {
-                        Some(Value::from_bytestream(&db_type, iter))
-                    };
-                    Extractor {
-                        row_extractor_fn: Box::new(f)
-                    }
+                // If the column is part of the key, then create a function to get the value from the row
+                if schema_column.is_index {
+                    Extractor::value(db_type)
                 } else { // otherwise, just read the bytes, and return none
-                    let f = move |iter: &mut Iter<u8>| -> Option<Value> {
-                        let _: Vec<&u8> = iter.take(db_type.size_bytes()).collect();
-                        None
-                    };
-                    Extractor {
-                        row_extractor_fn: Box::new(f)
-                    }
+                    Extractor::skip(db_type)
                 }
             })
             .collect();
 
-        Self::generate_specialized_row_to_tuple_fn(extractors,1)
+        let row_to_tuple = Self::generate_specialized_row_to_tuple_fn(extractors, num_key_columns);
+        move |row: &Row| {
+            let values = row_to_tuple(row)?;
+            if values.is_empty() {
+                Err(DbError::NoKeyColumns)
+            } else {
+                Ok(Key(values))
+            }
+        }
     }
 
+    /// Whether each row has a different value in the column,
+    /// either because it is the table's only key column or because it has a `Unique` constraint.
+    pub fn is_unique_column(&self, column_index: usize) -> bool {
+        let key_columns = self.key_columns();
+        (key_columns.len() == 1 && key_columns[0] == column_index)
+            || self.columns[column_index].has_constraint(&Constraint::Unique)
+    }
 
-    /// Based on the schema of a table, create a function that can extract tuples from rows.
-    /// This will read every byte-sequence into a Value, so that the resulting tuple can be used
-    /// with a conditional statement.
-    pub fn generate_general_row_to_tuple_fn(&self) -> impl Fn(&Row) -> Option<Tuple> {
+    /// Gets the position of the column with the given name.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.name == name)
+    }
+
+    /// Gets the positions of the named columns, in the order they are named.
+    pub fn column_indexes(&self, names: &[&str]) -> DbResult<Vec<usize>> {
+        names
+            .iter()
+            .map(|name| self.column_index(name).ok_or_else(|| DbError::UnknownColumn((*name).into())))
+            .collect()
+    }
+
+    /// Create a function that reads the value of a single column out of a row,
+    /// skipping over the bytes of every other column.
+    pub fn generate_extract_column_value_fn(&self, column_index: usize) -> impl Fn(&Row) -> DbResult<Value> {
         let extractors: Vec<Extractor> = self.columns
             .iter()
-            .map(|schema_column| {
+            .enumerate()
+            .map(|(index, schema_column)| {
                 let db_type = schema_column.db_type.clone();
-                let f = move |iter: &mut Iter<u8>| -> Option<Value> {
-                    Some(Value::from_bytestream(&db_type, iter))
-                };
-                Extractor {
-                    row_extractor_fn: Box::new(f)
+                if index == column_index {
+                    Extractor::value(db_type)
+                } else {
+                    Extractor::skip(db_type)
                 }
             })
             .collect();
 
+        let row_to_tuple = Self::generate_specialized_row_to_tuple_fn(extractors, 1);
+        move |row: &Row| {
+            row_to_tuple(row)?
+                .pop()
+                .ok_or_else(|| DbError::UnknownColumn(format!("#{}", column_index)))
+        }
+    }
+
+    /// Create a function that reads the values of the given columns out of a row, in the order they are given,
+    /// skipping over the bytes of every other column.
+    pub fn generate_extract_columns_fn(&self, columns: &[usize]) -> impl Fn(&Row) -> DbResult<Tuple> {
+        let extractors: Vec<Extractor> = self.columns
+            .iter()
+            .enumerate()
+            .map(|(index, schema_column)| {
+                let db_type = schema_column.db_type.clone();
+                if columns.contains(&index) {
+                    Extractor::value(db_type)
+                } else {
+                    Extractor::skip(db_type)
+                }
+            })
+            .collect();
+
+        // The values are read in schema order, and then rearranged into the order that was asked for.
+        let mut in_schema_order = columns.to_vec();
+        in_schema_order.sort();
+        in_schema_order.dedup();
+        let positions: Vec<usize> = columns
+            .iter()
+            .map(|column| in_schema_order.binary_search(column).expect("Every column was added"))
+            .collect();
+        let row_to_tuple = Self::generate_specialized_row_to_tuple_fn(extractors, in_schema_order.len());
+        move |row: &Row| {
+            let values = row_to_tuple(row)?;
+            positions
+                .iter()
+                .map(|position| values.get(*position).cloned().ok_or_else(|| DbError::UnknownColumn(format!("#{}", in_schema_order[*position]))))
+                .collect()
+        }
+    }
+
+    /// Based on the schema of a table, create a function that can extract tuples from rows.
+    /// This will read every byte-sequence into a Value, so that the resulting tuple can be used
+    /// with a conditional statement.
+    pub fn generate_general_row_to_tuple_fn(&self) -> impl Fn(&Row) -> DbResult<Tuple> {
+        let extractors: Vec<Extractor> = self.columns
+            .iter()
+            .map(|schema_column| Extractor::value(schema_column.db_type.clone()))
+            .collect();
+
         Self::generate_specialized_row_to_tuple_fn(extractors, self.columns.len())
     }
 
@@ -144,16 +453,18 @@ impl Schema {
     /// the bytes away. This _should_ be more efficient than using the general case,
     /// because if the get function doesn't have conditionals, then it can avoid a filtering step later
     /// by only getting the values it will need to return from the rows.
-    fn generate_specialized_row_to_tuple_fn(extractors: Vec<Extractor>, num_columns: usize) -> impl Fn(&Row) -> Option<Tuple> {
+    fn generate_specialized_row_to_tuple_fn(extractors: Vec<Extractor>, num_columns: usize) -> impl Fn(&Row) -> DbResult<Tuple> {
         let cl = move |row: &Row| {
             let mut tuple: Tuple = Vec::with_capacity(num_columns);
             let mut byte_iterator: Iter<u8> = row.iter();
-            for extractor in &extractors {
-                if let Some(value) = (extractor.row_extractor_fn)(&mut byte_iterator) {
+            let null_bitmap = take_bytes(&mut byte_iterator, null_bitmap_size(extractors.len()))?;
+            for (index, extractor) in extractors.iter().enumerate() {
+                let is_null = is_null(null_bitmap, index);
+                if let Some(value) = (extractor.row_extractor_fn)(&mut byte_iterator, is_null)? {
                     tuple.push(value)
                 }
             }
-            return Some(tuple);
+            return Ok(tuple);
         };
         cl
     }
@@ -166,17 +477,58 @@ impl Schema {
 pub enum DbType {
     Integer,
     BigInt,
-    String{ length: u32 }
+    /// A string that is padded out to a fixed number of bytes.
+    String{ length: u32 },
+    /// A string that takes up only as many bytes as it needs, with no limit on its length.
+    Text,
+    Boolean,
+    /// A 64 bit floating point number.
+    Double,
+    /// An exact number with up to `precision` significant digits, `scale` of which are after the decimal point.
+    Decimal { precision: u8, scale: u8 },
+    /// A calendar date.
+    Date,
+    /// A time of day, to the microsecond.
+    Time,
+    /// A moment in UTC, to the microsecond.
+    Timestamp,
+    /// A 128 bit universally unique identifier.
+    Uuid,
+    /// Binary data that takes up only as many bytes as it needs.
+    Bytes
 }
 
 
 impl DbType {
+    /// The number of bytes a value of this type takes up in a row.
+    /// For types whose values vary in length, this is the fewest bytes a value can take up.
     pub fn size_bytes(&self) -> usize {
         use self::DbType::*;
         match *self {
             Integer => 4,
             BigInt => 8,
-            String{ length } => length as usize
+            String{ length } => length as usize,
+            Text => 4,
+            Boolean => 1,
+            Double => 8,
+            Decimal { .. } => 16,
+            Date => 4,
+            Time => 8,
+            Timestamp => 8,
+            Uuid => 16,
+            Bytes => 4
+        }
+    }
+
+    /// Whether the values of the two types are the same kind of value, which can be compared with each other.
+    /// Strings of any length can be compared, as can decimals of any precision and scale.
+    pub fn holds_same_values(&self, other: &DbType) -> bool {
+        match (self, other) {
+            (&DbType::String { .. }, &DbType::String { .. })
+            | (&DbType::String { .. }, &DbType::Text)
+            | (&DbType::Text, &DbType::String { .. })
+            | (&DbType::Decimal { .. }, &DbType::Decimal { .. }) => true,
+            _ => self == other
         }
     }
 }
@@ -194,9 +546,40 @@ impl From<Value> for DbType {
 */
 
 
-type RowExtractorClosure = Fn(&mut Iter<u8>) -> Option<Value>;
+/// Reads a column from the front of the bytes, given whether the row's null bitmap marks the column as null.
+/// A null column has no bytes in the row.
+type RowExtractorClosure = Fn(&mut Iter<u8>, bool) -> DbResult<Option<Value>>;
 type Row = [u8];
 struct Extractor
 {
     pub row_extractor_fn: Box<RowExtractorClosure>
 }
+
+impl Extractor {
+    /// Creates an extractor that reads the column's value.
+    fn value(db_type: DbType) -> Extractor {
+        let f = move |iter: &mut Iter<u8>, is_null: bool| -> DbResult<Option<Value>> {
+            if is_null {
+                Ok(Some(Value::Null))
+            } else {
+                Value::from_bytestream(&db_type, iter).map(Some)
+            }
+        };
+        Extractor {
+            row_extractor_fn: Box::new(f)
+        }
+    }
+
+    /// Creates an extractor that just reads the bytes of the column, and returns none.
+    fn skip(db_type: DbType) -> Extractor {
+        let f = move |iter: &mut Iter<u8>, is_null: bool| -> DbResult<Option<Value>> {
+            if !is_null {
+                Value::skip_bytestream(&db_type, iter)?;
+            }
+            Ok(None)
+        };
+        Extractor {
+            row_extractor_fn: Box::new(f)
+        }
+    }
+}
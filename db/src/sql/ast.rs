@@ -0,0 +1,81 @@
+//! The statements that SQL text is parsed into.
+
+use schema::{Schema, Name};
+use table::Value;
+use expression::Expression;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    CreateTable { name: Name, schema: Schema },
+    DropTable { name: Name },
+    /// Adds rows to a table. If the columns aren't named, each row has a value for every column, in the schema's order.
+    /// A value of `None` stands for `DEFAULT`, which leaves the column to be filled in the way a missing value would be.
+    Insert { table: Name, columns: Option<Vec<Name>>, rows: Vec<Vec<Option<Value>>> },
+    Select(Select),
+    Update { table: Name, assignments: Vec<(Name, Value)>, condition: Option<Expression> },
+    Delete { table: Name, condition: Option<Expression> },
+    /// Describes how a `SELECT`, `UPDATE` or `DELETE` is carried out.
+    /// With `ANALYZE`, the statement is run, and the rows and time taken by each step are reported.
+    Explain { analyze: bool, statement: Box<Statement> },
+}
+
+/// A query's columns may be named on their own, or qualified by the name their table is referred to by, as in `u.NAME`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Select {
+    pub columns: SelectColumns,
+    pub from: TableReference,
+    /// The tables joined to the first, in the order they are joined.
+    pub joins: Vec<Join>,
+    pub condition: Option<Expression>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u64>
+}
+
+/// A table named in a `FROM` clause, which may be given another name to refer to it by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableReference {
+    pub table: Name,
+    pub alias: Option<Name>
+}
+
+impl TableReference {
+    pub fn new(table: &str) -> TableReference {
+        TableReference { table: table.into(), alias: None }
+    }
+
+    /// The name that the query refers to the table by.
+    pub fn name(&self) -> &str {
+        self.alias.as_ref().unwrap_or(&self.table)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table: TableReference,
+    /// The `ON` condition, which a cross join doesn't have.
+    pub condition: Option<Expression>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JoinKind {
+    /// Only the pairs of rows for which the condition is true.
+    Inner,
+    /// Like an inner join, but a row from the left that has no match is kept, with nulls for the right table's columns.
+    LeftOuter,
+    /// Every pair of rows.
+    Cross,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SelectColumns {
+    /// `*`, every column in the schema's order.
+    All,
+    Named(Vec<Name>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderBy {
+    pub column: Name,
+    pub descending: bool
+}
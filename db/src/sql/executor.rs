@@ -0,0 +1,603 @@
+//! Carries out plans, producing their rows one at a time.
+//!
+//! Each operator is an iterator that pulls rows from the operators below it only when it is asked for one,
+//! so a query with a limit stops reading the table once it has enough rows.
+//! Sorting is the exception, as it has to see every row before it can produce the first.
+//! Joins read all of their right input when they are first asked for a row, but pull their left input one row at a time,
+//! so a joined row comes out as soon as the left row it starts with has been read.
+//!
+//! Updates and deletions read all of the rows they change before changing any of them,
+//! as the tables can't be changed while they are being read.
+
+use sql::ast::JoinKind;
+use sql::planner::{Plan, SortKey};
+use sql::explain::{explain, Explanation, OperatorStats};
+use database::Database;
+use schema::{ColumnMetadata, Name};
+use table::{Table, Key, Tuple, Value};
+use expression::Predicate;
+use numeric::Decimal;
+use error::{DbError, DbResult};
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Bound;
+use std::rc::Rc;
+use std::time::Instant;
+use std::vec;
+
+/// An operator that has been set up to produce rows.
+type Operator<'a> = Box<dyn Iterator<Item = DbResult<Tuple>> + 'a>;
+
+/// Where the statistics of each operator are gathered while a plan runs,
+/// in the order that `explain` describes the operators in.
+type Profile = Vec<Rc<Cell<OperatorStats>>>;
+
+/// What running a statement produced.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Output {
+    /// The rows selected by a query.
+    Rows { columns: Vec<Name>, tuples: Vec<Tuple> },
+    /// The number of rows that were inserted, updated or deleted.
+    Changed(usize),
+    /// A table was created or dropped.
+    Done,
+    Explanation(Explanation),
+}
+
+/// Writes rows as a line of column names followed by a line for each row, with the values separated by tabs.
+/// Newlines, tabs and backslashes in values are written as `\n`, `\r`, `\t` and `\\`, so every row stays on its own line.
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Output::Rows { ref columns, ref tuples } => {
+                write!(f, "{}", columns.join("\t"))?;
+                for tuple in tuples {
+                    let values: Vec<String> = tuple.iter().map(|value| escape(&value.to_string())).collect();
+                    write!(f, "\n{}", values.join("\t"))?;
+                }
+                Ok(())
+            }
+            Output::Changed(1) => write!(f, "1 row"),
+            Output::Changed(count) => write!(f, "{} rows", count),
+            Output::Done => write!(f, "done"),
+            Output::Explanation(ref explanation) => write!(f, "{}", explanation)
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\\' => escaped.push_str("\\\\"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// The rows produced by a query, along with the names of their columns.
+pub struct Rows<'a> {
+    columns: Vec<Name>,
+    tuples: Operator<'a>
+}
+
+impl<'a> Rows<'a> {
+    pub fn columns(&self) -> &[Name] {
+        &self.columns
+    }
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = DbResult<Tuple>;
+
+    fn next(&mut self) -> Option<DbResult<Tuple>> {
+        self.tuples.next()
+    }
+}
+
+/// Sets up the plan's operators to read from the database's tables.
+/// No rows are read until they are asked for.
+pub fn execute<'a>(database: &'a Database, plan: &Plan) -> DbResult<Rows<'a>> {
+    let columns = plan.schema(database)?
+        .columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect();
+    Ok(Rows { columns, tuples: open(database, plan, None)? })
+}
+
+/// Runs a plan to completion, returning the number of rows it produced,
+/// or for an update or a deletion, the number of rows it changed.
+pub(crate) fn run(database: &mut Database, plan: &Plan) -> DbResult<usize> {
+    run_profiled(database, plan, None)
+}
+
+/// Runs a plan to completion, measuring each of its operators.
+pub(crate) fn analyze(database: &mut Database, plan: &Plan) -> DbResult<Explanation> {
+    let mut profile = Vec::new();
+    run_profiled(database, plan, Some(&mut profile))?;
+    let stats: Vec<OperatorStats> = profile.iter().map(|stats| stats.get()).collect();
+    explain(database, plan, Some(&stats))
+}
+
+fn run_profiled(database: &mut Database, plan: &Plan, mut profile: Option<&mut Profile>) -> DbResult<usize> {
+    let (input, table) = match *plan {
+        Plan::Update { ref input, ref table, .. } | Plan::Delete { ref input, ref table } => (input, table),
+        _ => {
+            let mut count = 0;
+            for tuple in open(database, plan, profile)? {
+                tuple?;
+                count += 1;
+            }
+            return Ok(count)
+        }
+    };
+    let stats = add_to_profile(&mut profile);
+    let started = Instant::now();
+    let tuples = open(database, input, profile)?.collect::<DbResult<Vec<Tuple>>>()?;
+    let count = tuples.len();
+    match *plan {
+        Plan::Update { ref assignments, .. } => {
+            let tuples = tuples
+                .into_iter()
+                .map(|mut tuple| {
+                    for &(column, ref value) in assignments {
+                        tuple[column] = value.clone();
+                    }
+                    tuple
+                })
+                .collect();
+            database.update_tuples(table, tuples)?;
+        }
+        _ => {
+            let schema = input.schema(database)?;
+            let keys: Vec<Key> = tuples.iter().map(|tuple| schema.key_from_tuple(tuple)).collect();
+            database.delete_tuples(table, keys)?;
+        }
+    }
+    if let Some(stats) = stats {
+        stats.set(OperatorStats { rows: count as u64, time: started.elapsed() });
+    }
+    Ok(count)
+}
+
+/// Makes room in the profile, if there is one, for the statistics of the next operator.
+fn add_to_profile(profile: &mut Option<&mut Profile>) -> Option<Rc<Cell<OperatorStats>>> {
+    profile.as_mut().map(|profile| {
+        let stats = Rc::new(Cell::new(OperatorStats::default()));
+        profile.push(stats.clone());
+        stats
+    })
+}
+
+fn open<'a>(database: &'a Database, plan: &Plan, mut profile: Option<&mut Profile>) -> DbResult<Operator<'a>> {
+    let stats = add_to_profile(&mut profile);
+    let operator = open_operator(database, plan, profile)?;
+    Ok(match stats {
+        Some(stats) => Box::new(Measured { input: operator, stats }),
+        None => operator
+    })
+}
+
+fn open_operator<'a>(database: &'a Database, plan: &Plan, mut profile: Option<&mut Profile>) -> DbResult<Operator<'a>> {
+    let table = |name: &str| database.table(name).ok_or_else(|| DbError::UnknownTable(name.into()));
+    Ok(match *plan {
+        Plan::KeyLookup { table: ref name, ref key, .. } => Box::new(table(name)?.find_tuple(key.clone())?.map(Ok).into_iter()),
+        Plan::KeyRangeScan { table: ref name, ref start, ref end, reverse, .. } => {
+            let scan = table(name)?.scan((start.clone(), end.clone()));
+            if reverse { Box::new(scan.rev()) } else { Box::new(scan) }
+        }
+        Plan::IndexRangeScan { table: ref name, ref column, ref start, ref end, reverse, .. } => {
+            // Nulls sort first, and the range came from comparisons that never hold for null, so the scan starts after them.
+            let start = match *start {
+                Bound::Unbounded => Bound::Excluded(Value::Null),
+                ref start => start.clone()
+            };
+            let scan = table(name)?.index_scan(column, (start, end.clone()))?;
+            if reverse { Box::new(scan.rev()) } else { Box::new(scan) }
+        }
+        Plan::FullScan { table: ref name, reverse, .. } => {
+            let scan = table(name)?.scan(..);
+            if reverse { Box::new(scan.rev()) } else { Box::new(scan) }
+        }
+        Plan::Filter { ref input, ref condition } => {
+            let predicate = condition.compile(&input.schema(database)?)?;
+            Box::new(open(database, input, profile)?.filter(move |tuple| tuple.as_ref().map_or(true, |tuple| predicate.matches(tuple))))
+        }
+        Plan::Sort { ref input, ref order } => {
+            Box::new(Sort { input: Some(open(database, input, profile)?), order: order.clone(), sorted: Vec::new().into_iter() })
+        }
+        Plan::Limit { ref input, limit } => Box::new(open(database, input, profile)?.take(limit as usize)),
+        Plan::Project { ref input, ref columns } => {
+            let columns = columns.clone();
+            Box::new(open(database, input, profile)?.map(move |tuple| tuple.map(|tuple| columns.iter().map(|&column| tuple[column].clone()).collect())))
+        }
+        Plan::Update { .. } | Plan::Delete { .. } => return Err(DbError::NotAQuery),
+        Plan::NestedLoopJoin { ref left, ref right, kind, ref condition } => {
+            let condition = match *condition {
+                Some(ref condition) => Some(condition.compile(&plan.schema(database)?)?),
+                None => None
+            };
+            let left_rows = open(database, left, reborrow(&mut profile))?;
+            let right_rows = open(database, right, profile)?;
+            Box::new(Join::new(left_rows, Matches::All { input: Some(right_rows), rows: Vec::new() }, right.schema(database)?.columns().len(), kind, condition))
+        }
+        Plan::IndexNestedLoopJoin { ref left, table: ref name, ref key, kind, ref condition, .. } => {
+            let condition = condition.compile(&plan.schema(database)?)?;
+            let table = table(name)?;
+            let key_columns = table.schema().key_columns().into_iter().map(|column| table.schema().columns()[column].clone()).collect();
+            let matches = Matches::Lookup { table, left_columns: key.clone(), key_columns };
+            Box::new(Join::new(open(database, left, profile)?, matches, table.schema().columns().len(), kind, Some(condition)))
+        }
+        Plan::HashJoin { ref left, ref right, ref left_columns, ref right_columns, kind, ref condition } => {
+            let condition = condition.compile(&plan.schema(database)?)?;
+            let left_rows = open(database, left, reborrow(&mut profile))?;
+            let matches = Matches::Hashed {
+                input: Some(open(database, right, profile)?),
+                left_columns: left_columns.clone(),
+                right_columns: right_columns.clone(),
+                rows: HashMap::new()
+            };
+            Box::new(Join::new(left_rows, matches, right.schema(database)?.columns().len(), kind, Some(condition)))
+        }
+    })
+}
+
+/// Lends out the profile, if there is one, so that it can be passed to more than one input.
+fn reborrow<'p>(profile: &'p mut Option<&mut Profile>) -> Option<&'p mut Profile> {
+    profile.as_mut().map(|profile| &mut **profile)
+}
+
+/// Passes on the rows of its input, counting them and timing how long it takes to get each one.
+struct Measured<'a> {
+    input: Operator<'a>,
+    stats: Rc<Cell<OperatorStats>>
+}
+
+impl<'a> Iterator for Measured<'a> {
+    type Item = DbResult<Tuple>;
+
+    fn next(&mut self) -> Option<DbResult<Tuple>> {
+        let started = Instant::now();
+        let next = self.input.next();
+        let mut stats = self.stats.get();
+        stats.time += started.elapsed();
+        if let Some(Ok(_)) = next {
+            stats.rows += 1;
+        }
+        self.stats.set(stats);
+        next
+    }
+}
+
+/// Reads all of its input the first time it is asked for a row, and then hands the rows out in order.
+struct Sort<'a> {
+    /// The input, until it has been read.
+    input: Option<Operator<'a>>,
+    order: Vec<SortKey>,
+    sorted: vec::IntoIter<Tuple>
+}
+
+impl<'a> Iterator for Sort<'a> {
+    type Item = DbResult<Tuple>;
+
+    fn next(&mut self) -> Option<DbResult<Tuple>> {
+        if let Some(input) = self.input.take() {
+            let mut tuples = match input.collect::<DbResult<Vec<Tuple>>>() {
+                Ok(tuples) => tuples,
+                Err(e) => return Some(Err(e))
+            };
+            let order = &self.order;
+            tuples.sort_by(|a, b| {
+                order.iter()
+                    .map(|key| {
+                        let ordering = a[key.column].cmp(&b[key.column]);
+                        if key.descending { ordering.reverse() } else { ordering }
+                    })
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            });
+            self.sorted = tuples.into_iter();
+        }
+        self.sorted.next().map(Ok)
+    }
+}
+
+/// Pairs each row of its left input with the rows that `Matches` finds for it, keeping the pairs for which the condition holds.
+struct Join<'a> {
+    left: Operator<'a>,
+    matches: Matches<'a>,
+    /// The number of columns in a right row, which is the number of nulls a left row that has no match is padded with.
+    right_width: usize,
+    kind: JoinKind,
+    condition: Option<Predicate>,
+    /// The joined rows of the last left row, that have yet to be handed out.
+    joined: vec::IntoIter<Tuple>
+}
+
+impl<'a> Join<'a> {
+    fn new(left: Operator<'a>, matches: Matches<'a>, right_width: usize, kind: JoinKind, condition: Option<Predicate>) -> Join<'a> {
+        Join { left, matches, right_width, kind, condition, joined: Vec::new().into_iter() }
+    }
+}
+
+impl<'a> Iterator for Join<'a> {
+    type Item = DbResult<Tuple>;
+
+    fn next(&mut self) -> Option<DbResult<Tuple>> {
+        loop {
+            if let Some(tuple) = self.joined.next() {
+                return Some(Ok(tuple))
+            }
+            let left = match self.left.next()? {
+                Ok(left) => left,
+                Err(e) => return Some(Err(e))
+            };
+            let candidates = match self.matches.find(&left) {
+                Ok(candidates) => candidates,
+                Err(e) => return Some(Err(e))
+            };
+            let condition = &self.condition;
+            let mut joined: Vec<Tuple> = candidates
+                .into_iter()
+                .map(|right| left.iter().cloned().chain(right).collect())
+                .filter(|tuple| condition.as_ref().is_none_or(|condition| condition.matches(tuple)))
+                .collect();
+            if joined.is_empty() && self.kind == JoinKind::LeftOuter {
+                let mut padded = left;
+                padded.resize(padded.len() + self.right_width, Value::Null);
+                joined.push(padded);
+            }
+            self.joined = joined.into_iter();
+        }
+    }
+}
+
+/// Finds the right rows that might be joined to a left row.
+enum Matches<'a> {
+    /// Every row of the right input, which is read the first time rows are asked for.
+    All { input: Option<Operator<'a>>, rows: Vec<Tuple> },
+    /// The row of the table whose key is the left row's values in the columns at these positions.
+    Lookup { table: &'a Table, left_columns: Vec<usize>, key_columns: Vec<ColumnMetadata> },
+    /// The right rows whose values in the right columns equal the left row's values in the left columns,
+    /// from a hash table of the right input that is built the first time rows are asked for.
+    Hashed { input: Option<Operator<'a>>, left_columns: Vec<usize>, right_columns: Vec<usize>, rows: HashMap<Vec<Value>, Vec<Tuple>> },
+}
+
+impl<'a> Matches<'a> {
+    fn find(&mut self, left: &Tuple) -> DbResult<Vec<Tuple>> {
+        match *self {
+            Matches::All { ref mut input, ref mut rows } => {
+                if let Some(input) = input.take() {
+                    *rows = input.collect::<DbResult<Vec<Tuple>>>()?;
+                }
+                Ok(rows.clone())
+            }
+            Matches::Lookup { table, ref left_columns, ref key_columns } => {
+                // A value that can't be stored in a key column, including null, can't be equal to any key.
+                let key = left_columns.iter()
+                    .zip(key_columns)
+                    .map(|(&column, metadata)| metadata.coerce(left[column].clone()).ok())
+                    .collect::<Option<Vec<Value>>>();
+                match key {
+                    Some(key) => Ok(table.find_tuple(Key(key))?.into_iter().collect()),
+                    None => Ok(Vec::new())
+                }
+            }
+            Matches::Hashed { ref mut input, ref left_columns, ref right_columns, ref mut rows } => {
+                if let Some(input) = input.take() {
+                    for tuple in input {
+                        let tuple = tuple?;
+                        if let Some(key) = hash_key(&tuple, right_columns) {
+                            rows.entry(key).or_insert_with(Vec::new).push(tuple);
+                        }
+                    }
+                }
+                Ok(hash_key(left, left_columns).and_then(|key| rows.get(&key)).cloned().unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// The row's values in the columns, in a form in which values that are equal are hashed alike.
+/// Integers become decimals, so that they can be matched with decimals and integers of other sizes.
+/// Returns `None` if any of the values is null, as null is never equal to anything.
+fn hash_key(tuple: &Tuple, columns: &[usize]) -> Option<Vec<Value>> {
+    columns.iter()
+        .map(|&column| match tuple[column] {
+            Value::Null => None,
+            Value::Integer(integer) => Some(Value::Decimal(Decimal::from(i64::from(integer)))),
+            Value::BigInt(integer) => Some(Value::Decimal(Decimal::from(integer))),
+            ref value => Some(value.clone())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schema::{Schema, ColumnMetadata, DbType};
+
+    fn people() -> Database {
+        let mut database = Database::new();
+        database.create_table("people", Schema::new(vec!(
+            ColumnMetadata::new_index("ID".into(), DbType::Integer),
+            ColumnMetadata::new("NAME".into(), DbType::Text),
+            ColumnMetadata::new("AGE".into(), DbType::Integer)
+        ))).unwrap();
+        database.table_mut("people").unwrap().create_index("AGE").unwrap();
+        let people = [("ann", 41), ("bob", 17), ("cy", 41), ("dee", 30), ("eve", 17)];
+        for (id, &(name, age)) in people.iter().enumerate() {
+            database.insert_tuple("people", vec!(Value::Integer(id as i32 + 1), Value::String(name.into()), Value::Integer(age))).unwrap();
+        }
+        database.insert_tuple("people", vec!(Value::Integer(6), Value::String("fay".into()), Value::Null)).unwrap();
+        database
+    }
+
+    fn names(database: &Database, sql: &str) -> Vec<String> {
+        let rows = database.query(sql).unwrap();
+        let name_column = rows.columns().iter().position(|column| column == "NAME").unwrap();
+        rows.map(|tuple| match tuple.unwrap()[name_column] {
+            Value::String(ref name) => name.clone(),
+            ref other => panic!("expected a name, got {:?}", other)
+        }).collect()
+    }
+
+    #[test]
+    fn queries_produce_their_rows() {
+        let database = people();
+        assert_eq!(names(&database, "SELECT * FROM people WHERE ID = 4"), vec!("dee"));
+        assert_eq!(names(&database, "SELECT * FROM people WHERE ID = 9"), Vec::<String>::new());
+        assert_eq!(names(&database, "SELECT NAME FROM people WHERE ID >= 2 AND ID < 5 ORDER BY ID DESC"), vec!("dee", "cy", "bob"));
+        assert_eq!(names(&database, "SELECT NAME FROM people WHERE AGE = 41"), vec!("ann", "cy"));
+        assert_eq!(names(&database, "SELECT NAME FROM people WHERE AGE <= 30 ORDER BY AGE DESC, NAME DESC"), vec!("dee", "eve", "bob"));
+        assert_eq!(names(&database, "SELECT * FROM people WHERE NAME LIKE '%e%' OR AGE IS NULL"), vec!("dee", "eve", "fay"));
+        // Nulls sort first.
+        assert_eq!(names(&database, "SELECT NAME FROM people ORDER BY AGE, ID DESC LIMIT 3"), vec!("fay", "eve", "bob"));
+
+        let rows = database.query("SELECT AGE, NAME FROM people WHERE ID = 1").unwrap();
+        assert_eq!(rows.columns(), ["AGE", "NAME"]);
+        assert_eq!(rows.collect::<DbResult<Vec<Tuple>>>().unwrap(), vec!(vec!(Value::Integer(41), Value::String("ann".into()))));
+    }
+
+    #[test]
+    fn plans_can_be_executed_directly() {
+        let database = people();
+        let plan = Plan::Limit { input: Box::new(Plan::FullScan { table: "people".into(), alias: None, reverse: false }), limit: 2 };
+        let mut rows = execute(&database, &plan).unwrap();
+        assert_eq!(rows.next().unwrap().unwrap()[0], Value::Integer(1));
+        assert_eq!(rows.next().unwrap().unwrap()[0], Value::Integer(2));
+        assert!(rows.next().is_none());
+
+        match database.query("DELETE FROM people") {
+            Err(DbError::NotAQuery) => {}
+            Err(other) => panic!("expected the statement to be rejected, got {:?}", other),
+            Ok(_) => panic!("expected the statement to be rejected")
+        };
+    }
+
+    fn explanation(database: &mut Database, sql: &str) -> Explanation {
+        match database.execute(sql).unwrap().pop() {
+            Some(Output::Explanation(explanation)) => explanation,
+            other => panic!("expected an explanation, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn plans_are_explained() {
+        let mut database = people();
+        let query = "SELECT NAME FROM people WHERE AGE <= 30 ORDER BY NAME LIMIT 2";
+        let explained = explanation(&mut database, &format!("EXPLAIN {}", query));
+        assert_eq!(explained.to_string(), "\
+Project (NAME)
+  Limit 2
+    Sort (NAME)
+      Filter (AGE <= 30)
+        Index Range Scan on people using AGE (AGE <= 30)");
+        assert!(explained.operators().iter().all(|operator| operator.stats.is_none()));
+
+        let analyzed = explanation(&mut database, &format!("EXPLAIN ANALYZE {}", query));
+        let rows: Vec<u64> = analyzed.operators().iter().map(|operator| operator.stats.unwrap().rows).collect();
+        // The sort reads all three rows, but is only asked for two of them.
+        assert_eq!(rows, vec!(2, 2, 2, 3, 3));
+        assert!(analyzed.to_string().starts_with("Project (NAME)  (rows: 2, time: "));
+
+        // Analyzing an update or a deletion carries it out.
+        let analyzed = explanation(&mut database, "EXPLAIN ANALYZE DELETE FROM people WHERE ID >= 5 AND NAME <> 'eve'");
+        let descriptions: Vec<(&str, u64)> = analyzed.operators()
+            .iter()
+            .map(|operator| (&operator.description[..], operator.stats.unwrap().rows))
+            .collect();
+        assert_eq!(descriptions, vec!(
+            ("Delete from people", 1),
+            ("Filter (ID >= 5 AND NAME <> 'eve')", 1),
+            ("Key Range Scan on people (ID >= 5)", 2)
+        ));
+        assert_eq!(names(&database, "SELECT * FROM people WHERE ID >= 5"), vec!("eve"));
+        let explained = explanation(&mut database, "EXPLAIN UPDATE people SET NAME = 'x' WHERE ID = 1");
+        assert_eq!(explained.to_string(), "Update people (NAME = 'x')\n  Filter (ID = 1)\n    Key Lookup on people (ID = 1)");
+        assert_eq!(names(&database, "SELECT * FROM people WHERE ID = 1"), vec!("ann"));
+    }
+
+    #[test]
+    fn statements_are_executed() {
+        let mut database = Database::new();
+        let outputs = database.execute("
+            CREATE TABLE items (ID SERIAL PRIMARY KEY, NAME TEXT NOT NULL UNIQUE, PRICE DECIMAL(6, 2));
+            INSERT INTO items (NAME, PRICE) VALUES ('pen', 1.5), ('ink', 12);
+            INSERT INTO items VALUES (DEFAULT, 'pad', NULL);
+            UPDATE items SET PRICE = 2 WHERE PRICE IS NULL OR PRICE < 2;
+            SELECT * FROM items ORDER BY PRICE DESC, NAME
+        ").unwrap();
+        assert_eq!(outputs[..4], [Output::Done, Output::Changed(2), Output::Changed(1), Output::Changed(2)]);
+        assert_eq!(outputs[4].to_string(), "ID\tNAME\tPRICE\n2\t'ink'\t12.00\n3\t'pad'\t2.00\n1\t'pen'\t2.00");
+
+        // Either every selected row is updated or none are.
+        match database.execute("UPDATE items SET NAME = 'same' WHERE ID > 1") {
+            Err(DbError::UniqueViolation { ref column, .. }) if column == "NAME" => {}
+            other => panic!("expected a unique violation, got {:?}", other)
+        }
+        assert_eq!(names(&database, "SELECT NAME FROM items"), vec!("pen", "ink", "pad"));
+        assert_eq!(database.execute("DELETE FROM items WHERE NAME LIKE 'p%'; DROP TABLE items").unwrap(), vec!(Output::Changed(2), Output::Done));
+
+        let awkward = Output::Rows { columns: vec!("NOTE".into()), tuples: vec!(vec!(Value::String("a\tb\r\nc\\n".into()))) };
+        assert_eq!(awkward.to_string(), "NOTE\n'a\\tb\\r\\nc\\\\n'");
+    }
+
+    #[test]
+    fn joins_combine_the_rows_of_tables() {
+        let mut database = Database::new();
+        database.execute("
+            CREATE TABLE users (USERNAME VARCHAR(10) PRIMARY KEY, NAME TEXT NOT NULL);
+            CREATE TABLE transfers (
+                ID SERIAL PRIMARY KEY,
+                SOURCE VARCHAR(10) NOT NULL REFERENCES users (USERNAME),
+                TARGET VARCHAR(10) REFERENCES users (USERNAME),
+                AMOUNT DECIMAL(8, 2)
+            );
+            INSERT INTO users VALUES ('ann', 'Ann Lee'), ('bob', 'Bob Ng'), ('cy', 'Cy Ott');
+            INSERT INTO transfers (SOURCE, TARGET, AMOUNT) VALUES ('ann', 'bob', 10), ('bob', 'cy', 5.5), ('cy', NULL, 1)
+        ").unwrap();
+        let history = "
+            SELECT t.ID, s.NAME, d.NAME, AMOUNT FROM transfers t
+                JOIN users s ON s.USERNAME = t.SOURCE
+                LEFT JOIN users AS d ON t.TARGET = d.USERNAME
+            ORDER BY t.ID";
+        let outputs = database.execute(history).unwrap();
+        assert_eq!(outputs[0].to_string(), "\
+t.ID\ts.NAME\td.NAME\tt.AMOUNT
+1\t'Ann Lee'\t'Bob Ng'\t10.00
+2\t'Bob Ng'\t'Cy Ott'\t5.50
+3\t'Cy Ott'\tNULL\t1.00");
+        // Reading the transfers in order of their keys leaves nothing to sort.
+        assert_eq!(explanation(&mut database, &format!("EXPLAIN {}", history)).to_string(), "\
+Project (t.ID, s.NAME, d.NAME, t.AMOUNT)
+  Index Nested Loop Left Join on users as d (t.TARGET = d.USERNAME)
+    Index Nested Loop Join on users as s (s.USERNAME = t.SOURCE)
+      Full Scan on transfers as t");
+        let analyzed = explanation(&mut database, &format!("EXPLAIN ANALYZE {}", history));
+        let rows: Vec<u64> = analyzed.operators().iter().map(|operator| operator.stats.unwrap().rows).collect();
+        assert_eq!(rows, vec!(3, 3, 3, 3));
+
+        let rows = |database: &Database, sql: &str| database.query(sql).unwrap().collect::<DbResult<Vec<Tuple>>>().unwrap();
+        let text = |s: &str| Value::String(s.into());
+        // An inner join drops the transfer that has no target.
+        assert_eq!(rows(&database, "SELECT d.NAME FROM transfers JOIN users d ON TARGET = d.USERNAME"), vec!(
+            vec!(text("Bob Ng")),
+            vec!(text("Cy Ott"))
+        ));
+        // The transfers' targets aren't indexed, so the transfers are hashed on them.
+        let sql = "SELECT u.USERNAME, s.ID FROM users u JOIN transfers s ON s.TARGET = u.USERNAME ORDER BY s.ID DESC";
+        assert_eq!(rows(&database, sql), vec!(vec!(text("cy"), Value::Integer(2)), vec!(text("bob"), Value::Integer(1))));
+        assert!(explanation(&mut database, &format!("EXPLAIN {}", sql)).to_string().contains("Hash Join (s.TARGET = u.USERNAME)"));
+        assert_eq!(rows(&database, "SELECT * FROM users a JOIN users b ON a.USERNAME < b.USERNAME").len(), 3);
+        assert_eq!(rows(&database, "SELECT * FROM users CROSS JOIN transfers").len(), 9);
+    }
+}
@@ -0,0 +1,190 @@
+//! Descriptions of plans, for finding out how a statement is carried out.
+//!
+//! Each operator is written on a line of its own, indented beneath the operator that takes its rows:
+//!
+//! ```text
+//! Project (NAME)
+//!   Filter (AGE <= 30)
+//!     Index Range Scan on people using AGE (AGE <= 30)  (rows: 3, time: 0.012 ms)
+//! ```
+//!
+//! When the plan has been run, each line ends with the number of rows the operator produced
+//! and the time spent in it, which includes the time spent in the operators beneath it.
+//! For an update or a deletion, the rows are those it changed.
+
+use sql::ast::JoinKind;
+use sql::planner::Plan;
+use database::Database;
+use schema::{Schema, Name};
+use table::Value;
+use error::DbResult;
+
+use std::fmt;
+use std::ops::Bound;
+use std::time::Duration;
+
+/// What was measured while an operator ran.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OperatorStats {
+    pub rows: u64,
+    pub time: Duration
+}
+
+/// A description of every operator in a plan, starting at the root and working down, each operator before its inputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    operators: Vec<ExplainedOperator>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExplainedOperator {
+    /// How far the operator is below the root of the plan.
+    pub depth: usize,
+    pub description: String,
+    /// What was measured, if the plan was run.
+    pub stats: Option<OperatorStats>
+}
+
+impl Explanation {
+    pub fn operators(&self) -> &[ExplainedOperator] {
+        &self.operators
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, operator) in self.operators.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:indent$}{}", "", operator.description, indent = operator.depth * 2)?;
+            if let Some(stats) = operator.stats {
+                let millis = stats.time.as_secs() as f64 * 1000.0 + f64::from(stats.time.subsec_nanos()) / 1_000_000.0;
+                write!(f, "  (rows: {}, time: {:.3} ms)", stats.rows, millis)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describes the plan. If it has been run, `stats` holds what was measured for each operator,
+/// in the order the operators are described in.
+pub fn explain(database: &Database, plan: &Plan, stats: Option<&[OperatorStats]>) -> DbResult<Explanation> {
+    let mut operators = Vec::new();
+    describe(database, plan, 0, &mut operators)?;
+    if let Some(stats) = stats {
+        for (operator, stats) in operators.iter_mut().zip(stats) {
+            operator.stats = Some(*stats);
+        }
+    }
+    Ok(Explanation { operators })
+}
+
+fn describe(database: &Database, plan: &Plan, depth: usize, operators: &mut Vec<ExplainedOperator>) -> DbResult<()> {
+    let input_schema = || -> DbResult<Schema> {
+        match plan.inputs().first() {
+            Some(input) => input.schema(database),
+            None => plan.schema(database)
+        }
+    };
+    let column_list = |columns: Vec<String>| columns.join(", ");
+    let description = match *plan {
+        Plan::KeyLookup { ref table, ref alias, ref key } => {
+            let schema = plan.schema(database)?;
+            let conditions = schema.key_columns()
+                .into_iter()
+                .zip(&key.0)
+                .map(|(column, value)| format!("{} = {}", schema.columns()[column].name(), value))
+                .collect::<Vec<String>>();
+            format!("Key Lookup on {} ({})", table_name(table, alias.as_ref()), conditions.join(" AND "))
+        }
+        Plan::KeyRangeScan { ref table, ref alias, ref start, ref end, reverse } => {
+            let schema = plan.schema(database)?;
+            let leading_column = schema.columns()[schema.key_columns()[0]].name();
+            format!("Key Range Scan{} on {} ({})", backward(reverse), table_name(table, alias.as_ref()), range(leading_column, start, end))
+        }
+        Plan::IndexRangeScan { ref table, ref alias, ref column, ref start, ref end, reverse } => {
+            let qualified = match *alias {
+                Some(ref alias) => format!("{}.{}", alias, column),
+                None => column.clone()
+            };
+            format!("Index Range Scan{} on {} using {} ({})", backward(reverse), table_name(table, alias.as_ref()), column, range(&qualified, start, end))
+        }
+        Plan::FullScan { ref table, ref alias, reverse } => format!("Full Scan{} on {}", backward(reverse), table_name(table, alias.as_ref())),
+        Plan::Filter { ref condition, .. } => format!("Filter ({})", condition),
+        Plan::Sort { ref order, .. } => {
+            let schema = input_schema()?;
+            let keys = order.iter()
+                .map(|key| format!("{}{}", schema.columns()[key.column].name(), if key.descending { " DESC" } else { "" }))
+                .collect();
+            format!("Sort ({})", column_list(keys))
+        }
+        Plan::Limit { limit, .. } => format!("Limit {}", limit),
+        Plan::Project { ref columns, .. } => {
+            let schema = input_schema()?;
+            format!("Project ({})", column_list(columns.iter().map(|&column| schema.columns()[column].name().to_string()).collect()))
+        }
+        Plan::Update { ref table, ref assignments, .. } => {
+            let schema = input_schema()?;
+            let assignments = assignments.iter()
+                .map(|&(column, ref value)| format!("{} = {}", schema.columns()[column].name(), value))
+                .collect();
+            format!("Update {} ({})", table, column_list(assignments))
+        }
+        Plan::Delete { ref table, .. } => format!("Delete from {}", table),
+        Plan::NestedLoopJoin { kind, ref condition, .. } => match *condition {
+            Some(ref condition) => format!("Nested Loop {} ({})", join_name(kind), condition),
+            None => format!("Nested Loop {}", join_name(kind))
+        },
+        Plan::IndexNestedLoopJoin { ref table, ref alias, kind, ref condition, .. } => {
+            format!("Index Nested Loop {} on {} ({})", join_name(kind), table_name(table, Some(alias)), condition)
+        }
+        Plan::HashJoin { kind, ref condition, .. } => format!("Hash {} ({})", join_name(kind), condition),
+    };
+    operators.push(ExplainedOperator { depth, description, stats: None });
+    for input in plan.inputs() {
+        describe(database, input, depth + 1, operators)?;
+    }
+    Ok(())
+}
+
+/// The table's name, followed by the name the query refers to it by if that is different.
+fn table_name(table: &str, alias: Option<&Name>) -> String {
+    match alias {
+        Some(alias) if alias != table => format!("{} as {}", table, alias),
+        _ => table.to_string()
+    }
+}
+
+fn join_name(kind: JoinKind) -> &'static str {
+    match kind {
+        JoinKind::Inner => "Join",
+        JoinKind::LeftOuter => "Left Join",
+        JoinKind::Cross => "Cross Join",
+    }
+}
+
+fn backward(reverse: bool) -> &'static str {
+    if reverse { " Backward" } else { "" }
+}
+
+/// Writes the range as comparisons of the column.
+fn range(column: &str, start: &Bound<Value>, end: &Bound<Value>) -> String {
+    if let (Bound::Included(start), Bound::Included(end)) = (start, end) {
+        if start == end {
+            return format!("{} = {}", column, start)
+        }
+    }
+    let mut comparisons = Vec::new();
+    match *start {
+        Bound::Included(ref value) => comparisons.push(format!("{} >= {}", column, value)),
+        Bound::Excluded(ref value) => comparisons.push(format!("{} > {}", column, value)),
+        Bound::Unbounded => {}
+    }
+    match *end {
+        Bound::Included(ref value) => comparisons.push(format!("{} <= {}", column, value)),
+        Bound::Excluded(ref value) => comparisons.push(format!("{} < {}", column, value)),
+        Bound::Unbounded => {}
+    }
+    comparisons.join(" AND ")
+}
@@ -0,0 +1,213 @@
+//! Splits SQL text into tokens, remembering where each one starts.
+
+use error::{DbError, DbResult};
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A line and column in the SQL text, both counted from 1.
+/// Columns count characters rather than bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize
+}
+
+impl Position {
+    /// An error at this position.
+    pub fn error<S: Into<String>>(&self, message: S) -> DbError {
+        DbError::SyntaxError { line: self.line, column: self.column, message: message.into() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    /// A keyword or an unquoted identifier. Which one it is depends on where it appears.
+    Word(String),
+    /// An identifier in double quotes, which is never taken to be a keyword.
+    QuotedIdentifier(String),
+    String(String),
+    /// A hexadecimal byte string, like `X'00ff'`.
+    Bytes(Vec<u8>),
+    /// The digits of a number, with any decimal point and exponent.
+    Number(String),
+    LeftParen,
+    RightParen,
+    Comma,
+    /// Separates a table's name from a column's, as in `users.name`.
+    Dot,
+    Semicolon,
+    Star,
+    Minus,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Word(ref word) => write!(f, "{}", word),
+            Token::QuotedIdentifier(ref identifier) => write!(f, "\"{}\"", identifier),
+            Token::String(ref s) => write!(f, "'{}'", s),
+            Token::Bytes(_) => write!(f, "a byte string"),
+            Token::Number(ref number) => write!(f, "{}", number),
+            Token::LeftParen => write!(f, "'('"),
+            Token::RightParen => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+            Token::Dot => write!(f, "'.'"),
+            Token::Semicolon => write!(f, "';'"),
+            Token::Star => write!(f, "'*'"),
+            Token::Minus => write!(f, "'-'"),
+            Token::Equal => write!(f, "'='"),
+            Token::NotEqual => write!(f, "'<>'"),
+            Token::Less => write!(f, "'<'"),
+            Token::LessOrEqual => write!(f, "'<='"),
+            Token::Greater => write!(f, "'>'"),
+            Token::GreaterOrEqual => write!(f, "'>='"),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    position: Position
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Takes characters for as long as they satisfy the condition.
+    fn take_while<F: Fn(char) -> bool>(&mut self, condition: F) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.peek() {
+            if !condition(c) {
+                break
+            }
+            taken.push(c);
+            self.next();
+        }
+        taken
+    }
+
+    /// Reads up to the closing quote, treating a doubled quote as a quote within the text.
+    fn quoted(&mut self, start: Position, quote: char) -> DbResult<String> {
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => {
+                    if self.peek() == Some(quote) {
+                        self.next();
+                        text.push(quote);
+                    } else {
+                        return Ok(text)
+                    }
+                }
+                Some(c) => text.push(c),
+                None => return Err(start.error(format!("unterminated {}", if quote == '\'' { "string" } else { "identifier" })))
+            }
+        }
+    }
+
+    fn number(&mut self, first: char) -> String {
+        let mut number = first.to_string();
+        number.push_str(&self.take_while(|c| c.is_ascii_digit() || c == '.'));
+        if let Some(e) = self.peek().filter(|c| *c == 'e' || *c == 'E') {
+            self.next();
+            number.push(e);
+            if let Some(sign) = self.peek().filter(|c| *c == '+' || *c == '-') {
+                self.next();
+                number.push(sign);
+            }
+            number.push_str(&self.take_while(|c| c.is_ascii_digit()));
+        }
+        number
+    }
+
+    fn bytes(&mut self, start: Position) -> DbResult<Vec<u8>> {
+        let digits = self.quoted(start, '\'')?;
+        if digits.len() % 2 != 0 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(start.error("a byte string must have an even number of hexadecimal digits"))
+        }
+        Ok((0..digits.len() / 2)
+            .map(|index| u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).expect("The digits were checked"))
+            .collect())
+    }
+
+    fn token(&mut self) -> DbResult<Option<(Token, Position)>> {
+        loop {
+            self.take_while(char::is_whitespace);
+            let start = self.position;
+            let c = match self.next() {
+                Some(c) => c,
+                None => return Ok(None)
+            };
+            let token = match c {
+                '-' if self.peek() == Some('-') => {
+                    // A comment runs to the end of the line.
+                    self.take_while(|c| c != '\n');
+                    continue
+                }
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                ',' => Token::Comma,
+                ';' => Token::Semicolon,
+                '*' => Token::Star,
+                '-' => Token::Minus,
+                '=' => Token::Equal,
+                '<' if self.peek() == Some('=') => { self.next(); Token::LessOrEqual }
+                '<' if self.peek() == Some('>') => { self.next(); Token::NotEqual }
+                '<' => Token::Less,
+                '>' if self.peek() == Some('=') => { self.next(); Token::GreaterOrEqual }
+                '>' => Token::Greater,
+                '!' if self.peek() == Some('=') => { self.next(); Token::NotEqual }
+                '\'' => Token::String(self.quoted(start, '\'')?),
+                '"' => Token::QuotedIdentifier(self.quoted(start, '"')?),
+                'x' | 'X' if self.peek() == Some('\'') => {
+                    self.next();
+                    Token::Bytes(self.bytes(start)?)
+                }
+                c if c.is_ascii_digit() || (c == '.' && self.peek().is_some_and(|c| c.is_ascii_digit())) => Token::Number(self.number(c)),
+                '.' => Token::Dot,
+                c if c.is_alphabetic() || c == '_' => {
+                    let mut word = c.to_string();
+                    word.push_str(&self.take_while(|c| c.is_alphanumeric() || c == '_'));
+                    Token::Word(word)
+                }
+                c => return Err(start.error(format!("unexpected character {:?}", c)))
+            };
+            return Ok(Some((token, start)))
+        }
+    }
+}
+
+/// Splits the text into tokens, each with the position it starts at.
+/// Also returns the position just past the end of the text.
+pub fn tokenize(sql: &str) -> DbResult<(Vec<(Token, Position)>, Position)> {
+    let mut lexer = Lexer {
+        chars: sql.chars().peekable(),
+        position: Position { line: 1, column: 1 }
+    };
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.token()? {
+        tokens.push(token);
+    }
+    Ok((tokens, lexer.position))
+}
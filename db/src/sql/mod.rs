@@ -0,0 +1,24 @@
+//! SQL text, parsed into statements that the database can carry out.
+//!
+//! The supported statements are `CREATE TABLE`, `DROP TABLE`, `INSERT`, `SELECT`, `UPDATE` and `DELETE`.
+//! Conditions in `WHERE` clauses are parsed into `Expression`s, so they are checked against a table's schema
+//! in the same way as conditions built in code.
+//!
+//! A `SELECT` can join tables with `JOIN`, `LEFT JOIN` and `CROSS JOIN`, naming their columns as `table.column`.
+//!
+//! A `SELECT`, `UPDATE` or `DELETE` is carried out by planning how to read the rows it needs, and then executing the plan.
+//! A query's rows are produced lazily. `EXPLAIN` describes the plan instead of running it,
+//! and `EXPLAIN ANALYZE` runs it while measuring each step.
+
+pub mod ast;
+mod lexer;
+mod parser;
+mod planner;
+pub(crate) mod executor;
+mod explain;
+
+pub use self::ast::{Statement, Select, SelectColumns, OrderBy, TableReference, Join, JoinKind};
+pub use self::parser::parse;
+pub use self::planner::{Plan, SortKey, plan, plan_select, plan_update, plan_delete};
+pub use self::executor::{Rows, Output, execute};
+pub use self::explain::{Explanation, ExplainedOperator, OperatorStats, explain};
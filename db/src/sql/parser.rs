@@ -0,0 +1,732 @@
+//! A recursive descent parser from tokens to statements.
+//!
+//! Keywords are matched without regard to case, while identifiers keep the case they were written in.
+//! An identifier in double quotes may be any name at all, including a keyword.
+
+use sql::ast::{Statement, Select, SelectColumns, OrderBy, TableReference, Join, JoinKind};
+use sql::lexer::{tokenize, Token, Position};
+use schema::{Schema, ColumnMetadata, DbType, Constraint, ForeignKey, ReferentialAction, Name};
+use table::Value;
+use expression::{Expression, Comparison};
+use numeric::{Double, Decimal};
+use error::DbResult;
+
+/// Words that can't be used as identifiers unless they are quoted, as they would make statements ambiguous.
+const RESERVED_WORDS: &[&str] = &[
+    "AND", "AS", "BETWEEN", "BY", "CREATE", "CROSS", "DEFAULT", "DELETE", "DROP", "FALSE", "FROM", "IN", "INNER",
+    "INSERT", "INTO", "IS", "JOIN", "LEFT", "LIKE", "LIMIT", "NOT", "NULL", "ON", "OR", "ORDER", "OUTER", "PRIMARY",
+    "REFERENCES", "SELECT", "SET", "TABLE", "TRUE", "UNIQUE", "UPDATE", "VALUES", "WHERE",
+];
+
+/// How deeply `NOT`s and parentheses may be nested in an expression, so that parsing one can't exhaust the stack.
+const MAX_NESTING: usize = 128;
+
+/// Parses any number of statements, separated by semicolons.
+///
+/// A syntax error is reported at the line and column of the token where the parser gave up,
+/// saying what it expected to find there.
+pub fn parse(sql: &str) -> DbResult<Vec<Statement>> {
+    let (tokens, end) = tokenize(sql)?;
+    let mut parser = Parser { tokens, index: 0, end, depth: 0 };
+    let mut statements = Vec::new();
+    loop {
+        while parser.eat(&Token::Semicolon) {}
+        if parser.peek().is_none() {
+            return Ok(statements)
+        }
+        statements.push(parser.statement()?);
+        if parser.peek().is_some() && !parser.eat(&Token::Semicolon) {
+            return Err(parser.unexpected("';'"))
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    index: usize,
+    /// The position just past the last token, where running out of tokens is reported.
+    end: Position,
+    /// How many `NOT`s and parentheses enclose the expression being parsed.
+    depth: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> Position {
+        self.tokens.get(self.index).map_or(self.end, |&(_, position)| position)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        if token.is_some() {
+            self.index += 1;
+        }
+        token
+    }
+
+    /// An error saying what was expected at the current token.
+    fn unexpected(&self, expected: &str) -> ::error::DbError {
+        let found = match self.peek() {
+            Some(token) => token.to_string(),
+            None => "end of input".into()
+        };
+        self.position().error(format!("expected {}, found {}", expected, found))
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> DbResult<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&token.to_string()))
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) => word.eq_ignore_ascii_case(keyword),
+            _ => false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> DbResult<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    /// The name of a table or column. `what` describes it for the error if there is no name.
+    fn identifier(&mut self, what: &str) -> DbResult<Name> {
+        let name = match self.peek() {
+            Some(Token::Word(word)) if !RESERVED_WORDS.iter().any(|reserved| word.eq_ignore_ascii_case(reserved)) => word.clone(),
+            Some(Token::QuotedIdentifier(identifier)) => identifier.clone(),
+            _ => return Err(self.unexpected(what))
+        };
+        self.index += 1;
+        Ok(name)
+    }
+
+    /// A column's name, which may be qualified by the name of its table, as in `users.NAME`.
+    fn column_reference(&mut self, what: &str) -> DbResult<Name> {
+        let name = self.identifier(what)?;
+        if self.eat(&Token::Dot) {
+            Ok(format!("{}.{}", name, self.identifier("a column name")?))
+        } else {
+            Ok(name)
+        }
+    }
+
+    /// A table's name, followed by an optional alias.
+    fn table_reference(&mut self) -> DbResult<TableReference> {
+        let table = self.identifier("a table name")?;
+        let alias = if self.eat_keyword("AS") {
+            Some(self.identifier("an alias")?)
+        } else {
+            // A word that can't be an identifier, like WHERE, is left for whatever follows.
+            self.identifier("an alias").ok()
+        };
+        Ok(TableReference { table, alias })
+    }
+
+    /// The kind of join that starts at the current token, if one does.
+    fn join_kind(&mut self) -> DbResult<Option<JoinKind>> {
+        let kind = if self.eat_keyword("JOIN") {
+            return Ok(Some(JoinKind::Inner))
+        } else if self.eat_keyword("INNER") {
+            JoinKind::Inner
+        } else if self.eat_keyword("LEFT") {
+            self.eat_keyword("OUTER");
+            JoinKind::LeftOuter
+        } else if self.eat_keyword("CROSS") {
+            JoinKind::Cross
+        } else {
+            return Ok(None)
+        };
+        self.expect_keyword("JOIN")?;
+        Ok(Some(kind))
+    }
+
+    /// One or more items separated by commas, inside parentheses.
+    fn parenthesized<T, F: FnMut(&mut Parser) -> DbResult<T>>(&mut self, mut item: F) -> DbResult<Vec<T>> {
+        self.expect(&Token::LeftParen)?;
+        let mut items = vec!(item(self)?);
+        while self.eat(&Token::Comma) {
+            items.push(item(self)?);
+        }
+        self.expect(&Token::RightParen)?;
+        Ok(items)
+    }
+
+    fn statement(&mut self) -> DbResult<Statement> {
+        if self.eat_keyword("EXPLAIN") {
+            let analyze = self.eat_keyword("ANALYZE");
+            if !["SELECT", "UPDATE", "DELETE"].iter().any(|keyword| self.is_keyword(keyword)) {
+                return Err(self.unexpected("SELECT, UPDATE or DELETE"))
+            }
+            let statement = Box::new(self.statement()?);
+            Ok(Statement::Explain { analyze, statement })
+        } else if self.eat_keyword("CREATE") {
+            self.expect_keyword("TABLE")?;
+            self.create_table()
+        } else if self.eat_keyword("DROP") {
+            self.expect_keyword("TABLE")?;
+            Ok(Statement::DropTable { name: self.identifier("a table name")? })
+        } else if self.eat_keyword("INSERT") {
+            self.expect_keyword("INTO")?;
+            self.insert()
+        } else if self.eat_keyword("SELECT") {
+            self.select().map(Statement::Select)
+        } else if self.eat_keyword("UPDATE") {
+            self.update()
+        } else if self.eat_keyword("DELETE") {
+            self.expect_keyword("FROM")?;
+            let table = self.identifier("a table name")?;
+            let condition = self.condition()?;
+            Ok(Statement::Delete { table, condition })
+        } else {
+            Err(self.unexpected("a statement"))
+        }
+    }
+
+    fn create_table(&mut self) -> DbResult<Statement> {
+        let name = self.identifier("a table name")?;
+        let mut columns: Vec<ColumnMetadata> = Vec::new();
+        self.expect(&Token::LeftParen)?;
+        loop {
+            if self.eat_keyword("PRIMARY") {
+                self.expect_keyword("KEY")?;
+                let key_start = self.index;
+                let key = self.parenthesized(|parser| parser.identifier("a column name"))?;
+                // A key's columns are compared in the order they are defined, so the key has to list them in that order.
+                let mut previous = None;
+                for (offset, key_column) in key.iter().enumerate() {
+                    // Point at the name itself, which follows the parenthesis and any earlier names and commas.
+                    let (_, position) = self.tokens[key_start + 1 + offset * 2];
+                    let index = match columns.iter().position(|column| column.name == *key_column) {
+                        Some(index) => index,
+                        None => return Err(position.error(format!("no column named {}", key_column)))
+                    };
+                    if previous.is_some_and(|previous| index <= previous) {
+                        return Err(position.error("a primary key must list its columns in the order they are defined"))
+                    }
+                    columns[index].is_index = true;
+                    previous = Some(index);
+                }
+            } else {
+                let position = self.position();
+                let column = self.column_definition()?;
+                if columns.iter().any(|existing| existing.name == column.name) {
+                    return Err(position.error(format!("column {} is defined more than once", column.name)))
+                }
+                columns.push(column);
+            }
+            if !self.eat(&Token::Comma) {
+                break
+            }
+        }
+        self.expect(&Token::RightParen)?;
+        Ok(Statement::CreateTable { name, schema: Schema::new(columns) })
+    }
+
+    fn column_definition(&mut self) -> DbResult<ColumnMetadata> {
+        let name = self.identifier("a column name")?;
+        let (db_type, serial) = self.column_type()?;
+        let mut column = ColumnMetadata::new(name, db_type);
+        if serial {
+            column = column.with_constraint(Constraint::Serial);
+        }
+        loop {
+            if self.eat_keyword("PRIMARY") {
+                self.expect_keyword("KEY")?;
+                column.is_index = true;
+            } else if self.eat_keyword("NOT") {
+                self.expect_keyword("NULL")?;
+                column = column.with_constraint(Constraint::NotNull);
+            } else if self.eat_keyword("NULL") {
+                // Columns are nullable unless they say otherwise.
+            } else if self.eat_keyword("UNIQUE") {
+                column = column.with_constraint(Constraint::Unique);
+            } else if self.eat_keyword("REFERENCES") {
+                let table = self.identifier("a table name")?;
+                self.expect(&Token::LeftParen)?;
+                let referenced = self.identifier("a column name")?;
+                self.expect(&Token::RightParen)?;
+                let on_delete = if self.eat_keyword("ON") {
+                    self.expect_keyword("DELETE")?;
+                    if self.eat_keyword("CASCADE") {
+                        ReferentialAction::Cascade
+                    } else if self.eat_keyword("RESTRICT") {
+                        ReferentialAction::Restrict
+                    } else {
+                        return Err(self.unexpected("CASCADE or RESTRICT"))
+                    }
+                } else {
+                    ReferentialAction::Restrict
+                };
+                column = column.with_foreign_key(ForeignKey::new(table, referenced, on_delete));
+            } else {
+                return Ok(column)
+            }
+        }
+    }
+
+    /// The column's type, and whether it was declared as a serial.
+    fn column_type(&mut self) -> DbResult<(DbType, bool)> {
+        let word = match self.peek() {
+            Some(Token::Word(word)) => word.to_ascii_uppercase(),
+            _ => return Err(self.unexpected("a column type"))
+        };
+        let db_type = match &word[..] {
+            "INT" | "INTEGER" => DbType::Integer,
+            "BIGINT" => DbType::BigInt,
+            "SERIAL" => DbType::Integer,
+            "BIGSERIAL" => DbType::BigInt,
+            "TEXT" => DbType::Text,
+            "BOOL" | "BOOLEAN" => DbType::Boolean,
+            "DOUBLE" | "FLOAT" | "REAL" => DbType::Double,
+            "DATE" => DbType::Date,
+            "TIME" => DbType::Time,
+            "TIMESTAMP" => DbType::Timestamp,
+            "UUID" => DbType::Uuid,
+            "BYTES" | "BLOB" | "BYTEA" => DbType::Bytes,
+            "VARCHAR" | "CHAR" => {
+                self.advance();
+                let length = self.parenthesized(|parser| parser.small_integer("a length"))?;
+                return match length[..] {
+                    [length] => Ok((DbType::String { length }, false)),
+                    _ => Err(self.tokens[self.index - 1].1.error("a string type takes a single length"))
+                }
+            }
+            "DECIMAL" | "NUMERIC" => {
+                self.advance();
+                let start = self.position();
+                let numbers = self.parenthesized(|parser| parser.small_integer("a precision"))?;
+                let (precision, scale) = match numbers[..] {
+                    [precision] => (precision, 0),
+                    [precision, scale] => (precision, scale),
+                    _ => return Err(start.error("a decimal type takes a precision and an optional scale"))
+                };
+                if precision > u32::from(u8::MAX) {
+                    return Err(start.error(format!("a decimal's precision can't be larger than {}", u8::MAX)))
+                }
+                if scale > precision {
+                    return Err(start.error("a decimal's scale can't be larger than its precision"))
+                }
+                return Ok((DbType::Decimal { precision: precision as u8, scale: scale as u8 }, false))
+            }
+            _ => return Err(self.unexpected("a column type"))
+        };
+        self.advance();
+        if word == "DOUBLE" {
+            self.eat_keyword("PRECISION");
+        }
+        Ok((db_type, word == "SERIAL" || word == "BIGSERIAL"))
+    }
+
+    /// A number without a sign or decimal point, that fits in 32 bits.
+    fn small_integer(&mut self, what: &str) -> DbResult<u32> {
+        let number = match self.peek() {
+            Some(Token::Number(digits)) => digits.parse().ok(),
+            _ => None
+        };
+        match number {
+            Some(number) => {
+                self.index += 1;
+                Ok(number)
+            }
+            None => Err(self.unexpected(what))
+        }
+    }
+
+    fn insert(&mut self) -> DbResult<Statement> {
+        let table = self.identifier("a table name")?;
+        let columns = if self.peek() == Some(&Token::LeftParen) {
+            Some(self.parenthesized(|parser| parser.identifier("a column name"))?)
+        } else {
+            None
+        };
+        self.expect_keyword("VALUES")?;
+        let mut rows = vec!(self.parenthesized(Parser::value_or_default)?);
+        while self.eat(&Token::Comma) {
+            rows.push(self.parenthesized(Parser::value_or_default)?);
+        }
+        Ok(Statement::Insert { table, columns, rows })
+    }
+
+    fn value_or_default(&mut self) -> DbResult<Option<Value>> {
+        if self.eat_keyword("DEFAULT") {
+            Ok(None)
+        } else {
+            self.literal()?.map(Some).ok_or_else(|| self.unexpected("a value"))
+        }
+    }
+
+    fn select(&mut self) -> DbResult<Select> {
+        let columns = if self.eat(&Token::Star) {
+            SelectColumns::All
+        } else {
+            let mut columns = vec!(self.column_reference("a column name or '*'")?);
+            while self.eat(&Token::Comma) {
+                columns.push(self.column_reference("a column name")?);
+            }
+            SelectColumns::Named(columns)
+        };
+        self.expect_keyword("FROM")?;
+        let from = self.table_reference()?;
+        let mut joins = Vec::new();
+        while let Some(kind) = self.join_kind()? {
+            let table = self.table_reference()?;
+            let condition = if kind == JoinKind::Cross {
+                None
+            } else {
+                self.expect_keyword("ON")?;
+                Some(self.or()?)
+            };
+            joins.push(Join { kind, table, condition });
+        }
+        let condition = self.condition()?;
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let column = self.column_reference("a column name")?;
+                let descending = if self.eat_keyword("DESC") {
+                    true
+                } else {
+                    self.eat_keyword("ASC");
+                    false
+                };
+                order_by.push(OrderBy { column, descending });
+                if !self.eat(&Token::Comma) {
+                    break
+                }
+            }
+        }
+        let limit = if self.eat_keyword("LIMIT") {
+            let limit = match self.peek() {
+                Some(Token::Number(digits)) => digits.parse().ok(),
+                _ => None
+            };
+            match limit {
+                Some(limit) => {
+                    self.index += 1;
+                    Some(limit)
+                }
+                None => return Err(self.unexpected("a number of rows"))
+            }
+        } else {
+            None
+        };
+        Ok(Select { columns, from, joins, condition, order_by, limit })
+    }
+
+    fn update(&mut self) -> DbResult<Statement> {
+        let table = self.identifier("a table name")?;
+        self.expect_keyword("SET")?;
+        let mut assignments = Vec::new();
+        loop {
+            let column = self.identifier("a column name")?;
+            self.expect(&Token::Equal)?;
+            let value = self.literal()?.ok_or_else(|| self.unexpected("a value"))?;
+            assignments.push((column, value));
+            if !self.eat(&Token::Comma) {
+                break
+            }
+        }
+        let condition = self.condition()?;
+        Ok(Statement::Update { table, assignments, condition })
+    }
+
+    /// An optional `WHERE` clause.
+    fn condition(&mut self) -> DbResult<Option<Expression>> {
+        if self.eat_keyword("WHERE") {
+            self.or().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn or(&mut self) -> DbResult<Expression> {
+        let mut expression = self.and()?;
+        while self.eat_keyword("OR") {
+            expression = expression.or(self.and()?);
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> DbResult<Expression> {
+        let mut expression = self.not()?;
+        while self.eat_keyword("AND") {
+            expression = expression.and(self.not()?);
+        }
+        Ok(expression)
+    }
+
+    /// Every `NOT` and every parenthesized expression passes through here, so this is where nesting is counted.
+    fn not(&mut self) -> DbResult<Expression> {
+        if self.depth == MAX_NESTING {
+            return Err(self.position().error("expression nested too deeply"))
+        }
+        self.depth += 1;
+        let expression = if self.eat_keyword("NOT") {
+            self.not().map(Expression::negate)
+        } else {
+            self.predicate()
+        };
+        self.depth -= 1;
+        expression
+    }
+
+    /// An operand, followed by anything that tests it.
+    fn predicate(&mut self) -> DbResult<Expression> {
+        let operand = self.operand()?;
+        let comparison = match self.peek() {
+            Some(&Token::Equal) => Some(Comparison::Equal),
+            Some(&Token::NotEqual) => Some(Comparison::NotEqual),
+            Some(&Token::Less) => Some(Comparison::Less),
+            Some(&Token::LessOrEqual) => Some(Comparison::LessOrEqual),
+            Some(&Token::Greater) => Some(Comparison::Greater),
+            Some(&Token::GreaterOrEqual) => Some(Comparison::GreaterOrEqual),
+            _ => None
+        };
+        if let Some(comparison) = comparison {
+            self.index += 1;
+            let other = self.operand()?;
+            return Ok(Expression::Compare(Box::new(operand), comparison, Box::new(other)))
+        }
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            let is_null = operand.is_null();
+            return Ok(if negated { is_null.negate() } else { is_null })
+        }
+        let negated = self.eat_keyword("NOT");
+        let expression = if self.eat_keyword("LIKE") {
+            let pattern = match self.peek() {
+                Some(Token::String(pattern)) => pattern.clone(),
+                _ => return Err(self.unexpected("a pattern"))
+            };
+            self.index += 1;
+            operand.like(&pattern)
+        } else if self.eat_keyword("IN") {
+            let list = self.parenthesized(Parser::operand)?;
+            operand.is_in(list)
+        } else if self.eat_keyword("BETWEEN") {
+            let low = self.operand()?;
+            self.expect_keyword("AND")?;
+            let high = self.operand()?;
+            operand.clone().greater_or_equal(low).and(operand.less_or_equal(high))
+        } else if negated {
+            return Err(self.unexpected("LIKE, IN or BETWEEN"))
+        } else {
+            return Ok(operand)
+        };
+        Ok(if negated { expression.negate() } else { expression })
+    }
+
+    fn operand(&mut self) -> DbResult<Expression> {
+        if self.eat(&Token::LeftParen) {
+            let expression = self.or()?;
+            self.expect(&Token::RightParen)?;
+            return Ok(expression)
+        }
+        if let Some(value) = self.literal()? {
+            return Ok(Expression::Literal(value))
+        }
+        self.column_reference("a column name or a value").map(Expression::Column)
+    }
+
+    /// A constant value, if there is one at the current token.
+    ///
+    /// Whole numbers are integers if they fit in 32 bits, and big integers if they fit in 64.
+    /// Numbers with a decimal point are decimals, and numbers with an exponent are doubles.
+    fn literal(&mut self) -> DbResult<Option<Value>> {
+        let start = self.position();
+        let negative = self.eat(&Token::Minus);
+        let value = match self.peek().cloned() {
+            Some(Token::Number(digits)) => {
+                let number = format!("{}{}{}", if negative { "-" } else { "" }, if digits.starts_with('.') { "0" } else { "" }, digits);
+                let value = if digits.contains(['e', 'E']) {
+                    number.parse().ok().map(|double| Value::Double(Double(double)))
+                } else if digits.contains('.') {
+                    number.parse::<Decimal>().ok().map(Value::Decimal)
+                } else if let Ok(integer) = number.parse() {
+                    Some(Value::Integer(integer))
+                } else if let Ok(integer) = number.parse() {
+                    Some(Value::BigInt(integer))
+                } else {
+                    number.parse::<Decimal>().ok().map(Value::Decimal)
+                };
+                match value {
+                    Some(value) => value,
+                    None => return Err(start.error(format!("{} is not a number that can be represented", number)))
+                }
+            }
+            _ if negative => return Err(self.unexpected("a number")),
+            Some(Token::String(s)) => Value::String(s),
+            Some(Token::Bytes(bytes)) => Value::Bytes(bytes),
+            Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("TRUE") => Value::Boolean(true),
+            Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("FALSE") => Value::Boolean(false),
+            Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("NULL") => Value::Null,
+            _ => return Ok(None)
+        };
+        self.index += 1;
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::DbError;
+
+    fn syntax_error(sql: &str) -> (usize, usize, String) {
+        match parse(sql) {
+            Err(DbError::SyntaxError { line, column, message }) => (line, column, message),
+            other => panic!("expected a syntax error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn statements_are_parsed() {
+        let statements = parse("
+            CREATE TABLE orders (
+                id SERIAL PRIMARY KEY,
+                customer VARCHAR(20) NOT NULL REFERENCES customers (name) ON DELETE CASCADE,
+                total DECIMAL(10, 2),
+                placed timestamp
+            );
+            insert into orders (customer, total) values ('O''Brien', 12.50), ('Ng', DEFAULT);
+            DROP TABLE \"Select\"
+        ").unwrap();
+        let id = ColumnMetadata::new_index("id".into(), DbType::Integer).with_constraint(Constraint::Serial);
+        let customer = ColumnMetadata::new("customer".into(), DbType::String { length: 20 })
+            .with_constraint(Constraint::NotNull)
+            .with_foreign_key(ForeignKey::new("customers".into(), "name".into(), ReferentialAction::Cascade));
+        let total = ColumnMetadata::new("total".into(), DbType::Decimal { precision: 10, scale: 2 });
+        let placed = ColumnMetadata::new("placed".into(), DbType::Timestamp);
+        assert_eq!(statements, vec!(
+            Statement::CreateTable { name: "orders".into(), schema: Schema::new(vec!(id, customer, total, placed)) },
+            Statement::Insert {
+                table: "orders".into(),
+                columns: Some(vec!("customer".into(), "total".into())),
+                rows: vec!(
+                    vec!(Some(Value::String("O'Brien".into())), Some(Value::Decimal("12.50".parse().unwrap()))),
+                    vec!(Some(Value::String("Ng".into())), None)
+                )
+            },
+            Statement::DropTable { name: "Select".into() }
+        ));
+
+        let statements = parse("
+            SELECT name, age FROM people WHERE age BETWEEN 18 AND 65 AND NOT name LIKE 'A%' OR id IN (1, -2)
+                ORDER BY age DESC, name LIMIT 10;
+            UPDATE people SET age = 3000000000, photo = X'00ff' WHERE id = 1;
+            DELETE FROM people -- everyone
+        ").unwrap();
+        let age = || Expression::column("age");
+        let condition = age().greater_or_equal(Value::Integer(18)).and(age().less_or_equal(Value::Integer(65)))
+            .and(Expression::column("name").like("A%").negate())
+            .or(Expression::column("id").is_in(vec!(Value::Integer(1).into(), Value::Integer(-2).into())));
+        assert_eq!(statements, vec!(
+            Statement::Select(Select {
+                columns: SelectColumns::Named(vec!("name".into(), "age".into())),
+                from: TableReference::new("people"),
+                joins: Vec::new(),
+                condition: Some(condition),
+                order_by: vec!(
+                    OrderBy { column: "age".into(), descending: true },
+                    OrderBy { column: "name".into(), descending: false }
+                ),
+                limit: Some(10)
+            }),
+            Statement::Update {
+                table: "people".into(),
+                assignments: vec!(("age".into(), Value::BigInt(3_000_000_000)), ("photo".into(), Value::Bytes(vec!(0, 255)))),
+                condition: Some(Expression::column("id").equal(Value::Integer(1)))
+            },
+            Statement::Delete { table: "people".into(), condition: None }
+        ));
+
+        let joined = parse("SELECT t.id, s.name FROM transfers t JOIN users AS s ON s.id = t.source LEFT OUTER JOIN users d ON d.id = t.target CROSS JOIN days")
+            .unwrap();
+        let column = |table: &str, name: &str| Expression::column(&format!("{}.{}", table, name));
+        let users = |alias: &str| TableReference { table: "users".into(), alias: Some(alias.into()) };
+        assert_eq!(joined, vec!(Statement::Select(Select {
+            columns: SelectColumns::Named(vec!("t.id".into(), "s.name".into())),
+            from: TableReference { table: "transfers".into(), alias: Some("t".into()) },
+            joins: vec!(
+                Join { kind: JoinKind::Inner, table: users("s"), condition: Some(column("s", "id").equal(column("t", "source"))) },
+                Join { kind: JoinKind::LeftOuter, table: users("d"), condition: Some(column("d", "id").equal(column("t", "target"))) },
+                Join { kind: JoinKind::Cross, table: TableReference::new("days"), condition: None }
+            ),
+            condition: None,
+            order_by: Vec::new(),
+            limit: None
+        })));
+
+        assert_eq!(parse("explain analyze delete from people").unwrap(), vec!(Statement::Explain {
+            analyze: true,
+            statement: Box::new(Statement::Delete { table: "people".into(), condition: None })
+        }));
+
+        let composite = parse("create table t (a int, b text, c double precision null, primary key (a, c))").unwrap();
+        match composite[..] {
+            [Statement::CreateTable { ref schema, .. }] => assert_eq!(schema.key_columns(), vec!(0, 2)),
+            _ => panic!("expected a single CREATE TABLE, got {:?}", composite)
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(syntax_error("SELECT * FORM t"), (1, 10, "expected FROM, found FORM".into()));
+        assert_eq!(syntax_error("SELECT *\nFROM t\nWHERE a ="), (3, 10, "expected a column name or a value, found end of input".into()));
+        assert_eq!(syntax_error("SELECT * FROM t WHERE a IS 1"), (1, 28, "expected NULL, found 1".into()));
+        assert_eq!(syntax_error("SELECT from FROM t"), (1, 8, "expected a column name or '*', found from".into()));
+        assert_eq!(syntax_error("DELETE FROM t\n  WHERE name = 'open"), (2, 16, "unterminated string".into()));
+        assert_eq!(syntax_error("CREATE TABLE t (a INT, b MONEY)"), (1, 26, "expected a column type, found MONEY".into()));
+        assert_eq!(syntax_error("CREATE TABLE t (a INT, PRIMARY KEY (a, b))"), (1, 40, "no column named b".into()));
+        assert_eq!(syntax_error("CREATE TABLE t (a INT, b INT, PRIMARY KEY (b, a))"), (1, 47, "a primary key must list its columns in the order they are defined".into()));
+        assert_eq!(syntax_error("CREATE TABLE t (a INT, a TEXT)"), (1, 24, "column a is defined more than once".into()));
+        assert_eq!(syntax_error("DROP TABLE t DROP TABLE u"), (1, 14, "expected ';', found DROP".into()));
+        assert_eq!(syntax_error("INSERT INTO t VALUES (1, @)"), (1, 26, "unexpected character '@'".into()));
+        assert_eq!(syntax_error("EXPLAIN DROP TABLE t"), (1, 9, "expected SELECT, UPDATE or DELETE, found DROP".into()));
+        assert_eq!(syntax_error("SELECT * FROM t JOIN u WHERE a = 1"), (1, 24, "expected ON, found WHERE".into()));
+        assert_eq!(syntax_error("SELECT t. FROM t"), (1, 11, "expected a column name, found FROM".into()));
+        assert_eq!(syntax_error("SELECT * FROM t WHERE S LIKE"), (1, 29, "expected a pattern, found end of input".into()));
+        assert_eq!(syntax_error("SELECT * FROM t WHERE S LIKE 5"), (1, 30, "expected a pattern, found 5".into()));
+        assert_eq!(syntax_error("CREATE TABLE t (a DECIMAL(300, 2))"), (1, 26, "a decimal's precision can't be larger than 255".into()));
+        assert_eq!(syntax_error("CREATE TABLE t (a DECIMAL(5, 6))"), (1, 26, "a decimal's scale can't be larger than its precision".into()));
+    }
+
+    #[test]
+    fn deeply_nested_expressions_are_errors() {
+        let nested = |depth: usize| format!("SELECT * FROM t WHERE {}a = 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_NESTING - 1)).is_ok());
+        // The error is at the first parenthesis past the limit, rather than a stack overflow.
+        assert_eq!(syntax_error(&nested(200_000)), (1, 23 + MAX_NESTING, "expression nested too deeply".into()));
+        assert_eq!(syntax_error(&format!("SELECT * FROM t WHERE {}a", "NOT ".repeat(100_000))).2, "expression nested too deeply");
+    }
+}
@@ -0,0 +1,688 @@
+//! Turns a parsed query into a tree of operators that says how it will be carried out.
+//!
+//! A plan reads its rows through one access path, the cheapest one the `WHERE` clause allows:
+//!
+//! 1. A lookup of a single key, when the condition pins every key column to a value.
+//! 2. A range scan over the primary index, when the condition narrows down the first key column.
+//! 3. A range scan over a secondary index, when the condition narrows down an indexed column.
+//! 4. A scan of the whole table.
+//!
+//! The rows that are read are then filtered by the full condition, sorted, limited and projected, in that order.
+//! Sorting is skipped when the access path already produces rows in the requested order.
+//!
+//! A query that joins tables reads the first table through its access path, and joins the others to it one at a time.
+//! Each join uses the primary index of the table it joins when its condition equates the whole key with the rows joined so far,
+//! a hash table when its condition equates other columns, and otherwise tries every pair of rows.
+//!
+//! `UPDATE` and `DELETE` statements read the rows they change in the same way, through an access path and a filter.
+
+use sql::ast::{Statement, Select, SelectColumns, Join, JoinKind};
+use database::Database;
+use expression::{Expression, Comparison};
+use schema::{Schema, ColumnMetadata, DbType, Name};
+use table::{Key, Value};
+use error::{DbError, DbResult};
+
+use std::iter;
+use std::mem::discriminant;
+use std::ops::Bound;
+
+/// An operator, along with the operators that produce its input.
+///
+/// A scan's alias is set when its table is one of several that a query joins. Its columns are then named `alias.column`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Plan {
+    /// Finds the single row with the key in the primary index.
+    KeyLookup { table: Name, alias: Option<Name>, key: Key },
+    /// Reads the rows whose first key column falls within the range, in order of their keys.
+    KeyRangeScan { table: Name, alias: Option<Name>, start: Bound<Value>, end: Bound<Value>, reverse: bool },
+    /// Reads the rows whose value in the column falls within the range, in order of that value.
+    IndexRangeScan { table: Name, alias: Option<Name>, column: Name, start: Bound<Value>, end: Bound<Value>, reverse: bool },
+    /// Reads every row of the table, in order of their keys.
+    FullScan { table: Name, alias: Option<Name>, reverse: bool },
+    /// Passes on only the rows for which the condition is true.
+    Filter { input: Box<Plan>, condition: Expression },
+    /// Orders the rows by the values in the columns at these positions, the first differing column deciding.
+    Sort { input: Box<Plan>, order: Vec<SortKey> },
+    /// Passes on no more than this many rows.
+    Limit { input: Box<Plan>, limit: u64 },
+    /// Keeps only the columns at these positions, in this order.
+    Project { input: Box<Plan>, columns: Vec<usize> },
+    /// Sets the columns at these positions to the values, in every row the input produces.
+    Update { input: Box<Plan>, table: Name, assignments: Vec<(usize, Value)> },
+    /// Deletes every row the input produces, along with any rows that cascade from them.
+    Delete { input: Box<Plan>, table: Name },
+    /// Pairs each left row with every right row, keeping the pairs for which the condition holds.
+    /// The right input is read once, and kept in memory.
+    NestedLoopJoin { left: Box<Plan>, right: Box<Plan>, kind: JoinKind, condition: Option<Expression> },
+    /// Pairs each left row with the row of the table whose key is the left row's values in the columns at these positions,
+    /// found in the table's primary index.
+    IndexNestedLoopJoin { left: Box<Plan>, table: Name, alias: Name, key: Vec<usize>, kind: JoinKind, condition: Expression },
+    /// Reads the right input into a hash table, keyed by the rows' values in the right columns,
+    /// and pairs each left row with the right rows that have its values in the left columns.
+    HashJoin { left: Box<Plan>, right: Box<Plan>, left_columns: Vec<usize>, right_columns: Vec<usize>, kind: JoinKind, condition: Expression },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortKey {
+    pub column: usize,
+    pub descending: bool
+}
+
+impl Plan {
+    /// The plans that produce this plan's input. A join's left input comes before its right.
+    pub fn inputs(&self) -> Vec<&Plan> {
+        match *self {
+            Plan::KeyLookup { .. } | Plan::KeyRangeScan { .. } | Plan::IndexRangeScan { .. } | Plan::FullScan { .. } => Vec::new(),
+            Plan::Filter { ref input, .. }
+            | Plan::Sort { ref input, .. }
+            | Plan::Limit { ref input, .. }
+            | Plan::Project { ref input, .. }
+            | Plan::Update { ref input, .. }
+            | Plan::Delete { ref input, .. }
+            | Plan::IndexNestedLoopJoin { left: ref input, .. } => vec!(input),
+            Plan::NestedLoopJoin { ref left, ref right, .. } | Plan::HashJoin { ref left, ref right, .. } => vec!(left, right),
+        }
+    }
+
+    /// Describes the rows this plan produces.
+    /// A projection keeps the metadata of the columns it picks out, and updates and deletions produce no rows.
+    /// A join produces the left row's columns followed by the right row's.
+    pub fn schema(&self, database: &Database) -> DbResult<Schema> {
+        match *self {
+            Plan::KeyLookup { ref table, ref alias, .. }
+            | Plan::KeyRangeScan { ref table, ref alias, .. }
+            | Plan::IndexRangeScan { ref table, ref alias, .. }
+            | Plan::FullScan { ref table, ref alias, .. } => {
+                let schema = table_schema(database, table)?;
+                Ok(match *alias {
+                    Some(ref alias) => qualified_schema(schema, alias),
+                    None => schema.clone()
+                })
+            }
+            Plan::Filter { ref input, .. } | Plan::Sort { ref input, .. } | Plan::Limit { ref input, .. } => input.schema(database),
+            Plan::Project { ref input, ref columns } => {
+                let schema = input.schema(database)?;
+                Ok(Schema::new(columns.iter().map(|&column| schema.columns()[column].clone()).collect()))
+            }
+            Plan::Update { .. } | Plan::Delete { .. } => Ok(Schema::new(Vec::new())),
+            Plan::NestedLoopJoin { ref left, ref right, .. } | Plan::HashJoin { ref left, ref right, .. } => {
+                Ok(joined_schema(&left.schema(database)?, &right.schema(database)?))
+            }
+            Plan::IndexNestedLoopJoin { ref left, ref table, ref alias, .. } => {
+                Ok(joined_schema(&left.schema(database)?, &qualified_schema(table_schema(database, table)?, alias)))
+            }
+        }
+    }
+}
+
+/// Works out how to carry out a `SELECT`, `UPDATE` or `DELETE` statement.
+/// Other statements don't read rows, and so have nothing to plan.
+pub fn plan(database: &Database, statement: &Statement) -> DbResult<Plan> {
+    match *statement {
+        Statement::Select(ref select) => plan_select(database, select),
+        Statement::Update { ref table, ref assignments, ref condition } => plan_update(database, table, assignments, condition.as_ref()),
+        Statement::Delete { ref table, ref condition } => plan_delete(database, table, condition.as_ref()),
+        _ => Err(DbError::NotPlannable)
+    }
+}
+
+/// Works out how to carry out a query against the tables of the database.
+///
+/// The tables and columns it names must exist, and its condition must make sense for the table's schema.
+/// A column may be named on its own as long as only one of the query's tables has a column by that name.
+pub fn plan_select(database: &Database, select: &Select) -> DbResult<Plan> {
+    let scope = Scope::new(database, select)?;
+    let order = select.order_by
+        .iter()
+        .map(|order_by| Ok(SortKey { column: scope.resolve(&order_by.column)?, descending: order_by.descending }))
+        .collect::<DbResult<Vec<SortKey>>>()?;
+    let condition = match select.condition {
+        Some(ref condition) => Some(scope.rename(condition)?),
+        None => None
+    };
+
+    let (mut plan, sorted) = if select.joins.is_empty() {
+        let (mut plan, provided_order) = access_path(database, &select.from.table, condition.as_ref())?;
+        let sorted = match satisfied_order(&order, &provided_order) {
+            Some(reverse) => {
+                set_reverse(&mut plan, reverse);
+                true
+            }
+            // A single row is in every order.
+            None => matches!(plan, Plan::KeyLookup { .. })
+        };
+        if let Some(condition) = condition {
+            plan = Plan::Filter { input: Box::new(plan), condition };
+        }
+        (plan, sorted)
+    } else {
+        plan_joins(database, &scope, select, condition, &order)?
+    };
+    if !sorted {
+        plan = Plan::Sort { input: Box::new(plan), order };
+    }
+    if let Some(limit) = select.limit {
+        plan = Plan::Limit { input: Box::new(plan), limit };
+    }
+    if let SelectColumns::Named(ref names) = select.columns {
+        let columns = names.iter().map(|name| scope.resolve(name)).collect::<DbResult<Vec<usize>>>()?;
+        plan = Plan::Project { input: Box::new(plan), columns };
+    }
+    Ok(plan)
+}
+
+/// Joins the tables in the order the query names them, each join taking the rows joined so far as its left input.
+///
+/// The parts of the condition that only concern the first table choose its access path, and filter its rows before they are joined.
+/// The rest of the condition filters the joined rows.
+/// Returns the plan, and whether its rows come out in the requested order, which they do if the first table's rows do.
+fn plan_joins(database: &Database, scope: &Scope, select: &Select, condition: Option<Expression>, order: &[SortKey]) -> DbResult<(Plan, bool)> {
+    let first_width = scope.tables[0].1.columns().len();
+    let mut first_conditions = Vec::new();
+    let mut other_conditions = Vec::new();
+    if let Some(ref condition) = condition {
+        for conjunct in condition.conjuncts() {
+            if scope.columns_of(conjunct)?.iter().all(|&column| column < first_width) {
+                first_conditions.push(conjunct.clone());
+            } else {
+                other_conditions.push(conjunct.clone());
+            }
+        }
+    }
+
+    let first_condition = conjunction(first_conditions);
+    let unqualified = match first_condition {
+        Some(ref condition) => Some(scope.unqualify(condition)?),
+        None => None
+    };
+    let (mut plan, provided_order) = access_path(database, &select.from.table, unqualified.as_ref())?;
+    set_alias(&mut plan, select.from.name());
+    let sorted = match satisfied_order(order, &provided_order) {
+        Some(reverse) => {
+            set_reverse(&mut plan, reverse);
+            true
+        }
+        None => false
+    };
+    if let Some(condition) = first_condition {
+        plan = Plan::Filter { input: Box::new(plan), condition };
+    }
+
+    for (index, join) in select.joins.iter().enumerate() {
+        plan = plan_join(database, &scope.first(index + 2), plan, join)?;
+    }
+    if let Some(condition) = conjunction(other_conditions) {
+        condition.compile(&plan.schema(database)?)?;
+        plan = Plan::Filter { input: Box::new(plan), condition };
+    }
+    Ok((plan, sorted))
+}
+
+/// Picks how to join the last table in the scope to the rows of the tables before it.
+///
+/// When the `ON` condition equates every key column of the table with a column of the left rows, each left row looks its match up
+/// in the primary index. Failing that, if it equates any of the table's columns with the left rows' columns, the table is hashed on them.
+/// Otherwise every pair of rows is tried.
+fn plan_join(database: &Database, scope: &Scope, left: Plan, join: &Join) -> DbResult<Plan> {
+    let table_index = scope.tables.len() - 1;
+    let schema = scope.tables[table_index].1;
+    let alias = join.table.name().to_string();
+    let left_width = scope.offset(table_index);
+    let right = Box::new(Plan::FullScan { table: join.table.table.clone(), alias: Some(alias.clone()), reverse: false });
+    let left = Box::new(left);
+    let kind = join.kind;
+    let condition = match join.condition {
+        Some(ref condition) => scope.rename(condition)?,
+        None => return Ok(Plan::NestedLoopJoin { left, right, kind, condition: None })
+    };
+
+    // The pairs of a left row's column and a right row's column that the condition says must be equal.
+    let mut equal_columns = Vec::new();
+    for conjunct in condition.conjuncts() {
+        let (first, second) = match *conjunct {
+            Expression::Compare(ref first, Comparison::Equal, ref second) => match (&**first, &**second) {
+                (Expression::Column(first), Expression::Column(second)) => (scope.resolve(first)?, scope.resolve(second)?),
+                _ => continue
+            },
+            _ => continue
+        };
+        let (left_column, right_column) = match (first < left_width, second < left_width) {
+            (true, false) => (first, second),
+            (false, true) => (second, first),
+            _ => continue
+        };
+        if hashed_alike(&scope.column(left_column).db_type, &scope.column(right_column).db_type) {
+            equal_columns.push((left_column, right_column - left_width));
+        }
+    }
+
+    let key: Option<Vec<usize>> = schema.key_columns()
+        .into_iter()
+        .map(|key_column| {
+            let key_type = &schema.columns()[key_column].db_type;
+            equal_columns.iter()
+                .find(|&&(left_column, right_column)| right_column == key_column && convertible(&scope.column(left_column).db_type, key_type))
+                .map(|&(left_column, _)| left_column)
+        })
+        .collect();
+    let plan = if let Some(key) = key {
+        Plan::IndexNestedLoopJoin { left, table: join.table.table.clone(), alias, key, kind, condition }
+    } else if !equal_columns.is_empty() {
+        let (left_columns, right_columns) = equal_columns.into_iter().unzip();
+        Plan::HashJoin { left, right, left_columns, right_columns, kind, condition }
+    } else {
+        Plan::NestedLoopJoin { left, right, kind, condition: Some(condition) }
+    };
+    // Any problem with the condition is reported now, rather than once rows are being read.
+    if let Plan::IndexNestedLoopJoin { ref condition, .. } | Plan::HashJoin { ref condition, .. } | Plan::NestedLoopJoin { condition: Some(ref condition), .. } = plan {
+        condition.compile(&plan.schema(database)?)?;
+    }
+    Ok(plan)
+}
+
+/// Works out how to find the rows to update.
+/// The values are converted to their columns' types. Like `Table::update_where`, key columns can't be changed.
+pub fn plan_update(database: &Database, table: &str, assignments: &[(Name, Value)], condition: Option<&Expression>) -> DbResult<Plan> {
+    let schema = table_schema(database, table)?;
+    let assignments = assignments
+        .iter()
+        .map(|(name, value)| {
+            let index = schema.column_index(name).ok_or_else(|| DbError::UnknownColumn(name.clone()))?;
+            let column = &schema.columns()[index];
+            if column.is_index {
+                return Err(DbError::InvalidValue { column: name.clone(), reason: "key columns can't be updated".into() })
+            }
+            Ok((index, column.coerce(value.clone())?))
+        })
+        .collect::<DbResult<Vec<(usize, Value)>>>()?;
+    Ok(Plan::Update { input: Box::new(filtered_access_path(database, table, condition)?), table: table.into(), assignments })
+}
+
+/// Works out how to find the rows to delete.
+pub fn plan_delete(database: &Database, table: &str, condition: Option<&Expression>) -> DbResult<Plan> {
+    Ok(Plan::Delete { input: Box::new(filtered_access_path(database, table, condition)?), table: table.into() })
+}
+
+fn filtered_access_path(database: &Database, table: &str, condition: Option<&Expression>) -> DbResult<Plan> {
+    let (plan, _) = access_path(database, table, condition)?;
+    Ok(match condition {
+        Some(condition) => Plan::Filter { input: Box::new(plan), condition: condition.clone() },
+        None => plan
+    })
+}
+
+pub(crate) fn table_schema<'a>(database: &'a Database, table: &str) -> DbResult<&'a Schema> {
+    database.table(table)
+        .map(|table| table.schema())
+        .ok_or_else(|| DbError::UnknownTable(table.into()))
+}
+
+/// Picks the way the table's rows will be read, given the condition they have to satisfy.
+/// Also returns the positions of the columns that the rows will come out ordered by, most significant first.
+pub(crate) fn access_path(database: &Database, table_name: &str, condition: Option<&Expression>) -> DbResult<(Plan, Vec<usize>)> {
+    let table = database.table(table_name).ok_or_else(|| DbError::UnknownTable(table_name.into()))?;
+    let schema = table.schema();
+    let key_columns = schema.key_columns();
+    let table_name = table_name.to_string();
+    let condition = match condition {
+        Some(condition) => {
+            // Any problem with the condition is reported now, rather than once rows are being read.
+            condition.compile(schema)?;
+            condition
+        }
+        None => return Ok((Plan::FullScan { table: table_name, alias: None, reverse: false }, key_columns))
+    };
+
+    let pinned: Option<Vec<Value>> = key_columns
+        .iter()
+        .map(|&column| match condition.column_range(schema, column) {
+            Some((Bound::Included(start), Bound::Included(end))) if start == end => Some(start),
+            _ => None
+        })
+        .collect();
+    if let Some(values) = pinned {
+        return Ok((Plan::KeyLookup { table: table_name, alias: None, key: Key(values) }, Vec::new()))
+    }
+    if let Some((start, end)) = condition.column_range(schema, key_columns[0]) {
+        return Ok((Plan::KeyRangeScan { table: table_name, alias: None, start, end, reverse: false }, key_columns))
+    }
+    for column in 0..schema.columns().len() {
+        if !table.has_secondary_index(column) {
+            continue
+        }
+        if let Some((start, end)) = condition.column_range(schema, column) {
+            let plan = Plan::IndexRangeScan {
+                table: table_name,
+                alias: None,
+                column: schema.columns()[column].name().to_string(),
+                start,
+                end,
+                reverse: false
+            };
+            return Ok((plan, vec!(column)))
+        }
+    }
+    Ok((Plan::FullScan { table: table_name, alias: None, reverse: false }, key_columns))
+}
+
+/// Whether rows that come out ordered by the provided columns are already in the requested order,
+/// either as they are or read backwards. If so, returns whether they need to be read backwards.
+fn satisfied_order(order: &[SortKey], provided: &[usize]) -> Option<bool> {
+    let descending = order.first().is_some_and(|key| key.descending);
+    let satisfied = order.len() <= provided.len()
+        && order.iter().zip(provided).all(|(key, &column)| key.column == column && key.descending == descending);
+    if satisfied { Some(descending) } else { None }
+}
+
+fn set_reverse(plan: &mut Plan, reversed: bool) {
+    match *plan {
+        Plan::KeyRangeScan { ref mut reverse, .. }
+        | Plan::IndexRangeScan { ref mut reverse, .. }
+        | Plan::FullScan { ref mut reverse, .. } => *reverse = reversed,
+        _ => {}
+    }
+}
+
+fn set_alias(plan: &mut Plan, name: &str) {
+    match *plan {
+        Plan::KeyLookup { ref mut alias, .. }
+        | Plan::KeyRangeScan { ref mut alias, .. }
+        | Plan::IndexRangeScan { ref mut alias, .. }
+        | Plan::FullScan { ref mut alias, .. } => *alias = Some(name.into()),
+        _ => {}
+    }
+}
+
+/// The conditions joined by `AND`, if there are any.
+fn conjunction(conditions: Vec<Expression>) -> Option<Expression> {
+    conditions.into_iter().fold(None, |joined, condition| match joined {
+        Some(joined) => Some(Expression::and(joined, condition)),
+        None => Some(condition)
+    })
+}
+
+/// The schema with its columns named after the alias, as `alias.column`.
+fn qualified_schema(schema: &Schema, alias: &str) -> Schema {
+    Schema::new(schema.columns()
+        .iter()
+        .map(|column| {
+            let mut column = column.clone();
+            column.name = format!("{}.{}", alias, column.name);
+            column
+        })
+        .collect())
+}
+
+fn joined_schema(left: &Schema, right: &Schema) -> Schema {
+    Schema::new(left.columns().iter().chain(right.columns()).cloned().collect())
+}
+
+/// Whether values of the two types that are equal can be brought to the same form, to be hashed.
+/// The executor brings integers and decimals to decimals.
+fn hashed_alike(first: &DbType, second: &DbType) -> bool {
+    let exact_number = |db_type: &DbType| matches!(*db_type, DbType::Integer | DbType::BigInt | DbType::Decimal { .. });
+    let string = |db_type: &DbType| matches!(*db_type, DbType::String { .. } | DbType::Text);
+    (exact_number(first) && exact_number(second)) || (string(first) && string(second)) || discriminant(first) == discriminant(second)
+}
+
+/// Whether every value of the first type that could equal a value of the second type is coerced to it by `ColumnMetadata::coerce`.
+fn convertible(from: &DbType, to: &DbType) -> bool {
+    match (from, to) {
+        (&DbType::Integer, &DbType::BigInt)
+        | (&DbType::Integer, &DbType::Decimal { .. })
+        | (&DbType::BigInt, &DbType::Decimal { .. })
+        | (&DbType::String { .. }, &DbType::Text)
+        | (&DbType::Text, &DbType::String { .. }) => true,
+        _ => discriminant(from) == discriminant(to)
+    }
+}
+
+/// The tables a query reads from, each under the name the query refers to it by.
+/// Their columns are numbered one table after another, the way they are laid out in joined rows.
+struct Scope<'a> {
+    tables: Vec<(&'a str, &'a Schema)>,
+    /// Whether the rows name their columns after their tables, which they do when a query has more than one table.
+    qualified: bool
+}
+
+impl<'a> Scope<'a> {
+    fn new(database: &'a Database, select: &'a Select) -> DbResult<Scope<'a>> {
+        let mut tables: Vec<(&str, &Schema)> = Vec::new();
+        for table in iter::once(&select.from).chain(select.joins.iter().map(|join| &join.table)) {
+            if tables.iter().any(|&(name, _)| name == table.name()) {
+                return Err(DbError::AmbiguousName(table.name().into()))
+            }
+            tables.push((table.name(), table_schema(database, &table.table)?));
+        }
+        Ok(Scope { qualified: tables.len() > 1, tables })
+    }
+
+    /// The scope of a join's condition, which can only see the tables up to and including the one being joined.
+    fn first(&self, count: usize) -> Scope<'a> {
+        Scope { tables: self.tables[..count].to_vec(), qualified: self.qualified }
+    }
+
+    fn columns(&self) -> Vec<(&'a str, &'a ColumnMetadata)> {
+        self.tables.iter().flat_map(|&(name, schema)| schema.columns().iter().map(move |column| (name, column))).collect()
+    }
+
+    fn column(&self, position: usize) -> &'a ColumnMetadata {
+        self.columns()[position].1
+    }
+
+    /// The position of the table's first column.
+    fn offset(&self, table: usize) -> usize {
+        self.tables[..table].iter().map(|&(_, schema)| schema.columns().len()).sum()
+    }
+
+    /// The position of the column with the name, which may be qualified by its table's name.
+    fn resolve(&self, reference: &str) -> DbResult<usize> {
+        let found: Vec<usize> = self.columns()
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, (table, column))| column.name == reference || format!("{}.{}", table, column.name) == reference)
+            .map(|(position, _)| position)
+            .collect();
+        match found[..] {
+            [position] => Ok(position),
+            [] => Err(DbError::UnknownColumn(reference.into())),
+            _ => Err(DbError::AmbiguousName(reference.into()))
+        }
+    }
+
+    /// The name of the column at the position in the rows.
+    fn name(&self, position: usize) -> Name {
+        let (table, column) = self.columns()[position];
+        if self.qualified { format!("{}.{}", table, column.name) } else { column.name.clone() }
+    }
+
+    /// The expression with its columns named the way the rows name them.
+    fn rename(&self, expression: &Expression) -> DbResult<Expression> {
+        expression.rename_columns(&mut |reference| Ok(self.name(self.resolve(reference)?)))
+    }
+
+    /// The expression with its columns named the way their own table names them.
+    fn unqualify(&self, expression: &Expression) -> DbResult<Expression> {
+        expression.rename_columns(&mut |reference| Ok(self.column(self.resolve(reference)?).name.clone()))
+    }
+
+    /// The positions of the columns the expression refers to.
+    fn columns_of(&self, expression: &Expression) -> DbResult<Vec<usize>> {
+        let mut positions = Vec::new();
+        expression.rename_columns(&mut |reference| {
+            positions.push(self.resolve(reference)?);
+            Ok(reference.into())
+        })?;
+        Ok(positions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sql::parse;
+
+    fn accounts() -> Database {
+        let mut database = Database::new();
+        database.create_table("accounts", Schema::new(vec!(
+            ColumnMetadata::new_index("ID".into(), DbType::Integer),
+            ColumnMetadata::new("OWNER".into(), DbType::Text),
+            ColumnMetadata::new("BALANCE".into(), DbType::BigInt)
+        ))).unwrap();
+        database.table_mut("accounts").unwrap().create_index("OWNER").unwrap();
+        database
+    }
+
+    fn plan(database: &Database, sql: &str) -> DbResult<Plan> {
+        super::plan(database, &parse(sql)?[0])
+    }
+
+    fn scan_of(plan: Plan) -> Plan {
+        match plan {
+            Plan::Filter { input, .. } | Plan::Sort { input, .. } | Plan::Limit { input, .. } | Plan::Project { input, .. } => scan_of(*input),
+            scan => scan
+        }
+    }
+
+    #[test]
+    fn access_paths_are_chosen_from_the_condition() {
+        let database = accounts();
+        let access = |sql: &str| scan_of(plan(&database, sql).unwrap());
+        assert_eq!(access("SELECT * FROM accounts WHERE ID = 3 AND BALANCE > 0"), Plan::KeyLookup { table: "accounts".into(), alias: None, key: Key(vec!(Value::Integer(3))) });
+        assert_eq!(access("SELECT * FROM accounts WHERE 3 < ID"), Plan::KeyRangeScan {
+            table: "accounts".into(),
+            alias: None,
+            start: Bound::Excluded(Value::Integer(3)),
+            end: Bound::Unbounded,
+            reverse: false
+        });
+        assert_eq!(access("SELECT * FROM accounts WHERE OWNER = 'ann' AND BALANCE > 0"), Plan::IndexRangeScan {
+            table: "accounts".into(),
+            alias: None,
+            column: "OWNER".into(),
+            start: Bound::Included(Value::String("ann".into())),
+            end: Bound::Included(Value::String("ann".into())),
+            reverse: false
+        });
+        assert_eq!(access("SELECT * FROM accounts WHERE ID = 3 OR BALANCE > 0"), Plan::FullScan { table: "accounts".into(), alias: None, reverse: false });
+    }
+
+    #[test]
+    fn operators_are_stacked_on_the_access_path() {
+        let database = accounts();
+        let full_scan = |reverse| Box::new(Plan::FullScan { table: "accounts".into(), alias: None, reverse });
+        assert_eq!(plan(&database, "SELECT OWNER, ID FROM accounts ORDER BY BALANCE DESC LIMIT 2").unwrap(), Plan::Project {
+            input: Box::new(Plan::Limit {
+                input: Box::new(Plan::Sort { input: full_scan(false), order: vec!(SortKey { column: 2, descending: true }) }),
+                limit: 2
+            }),
+            columns: vec!(1, 0)
+        });
+        // Ordering by the key is done by reading the primary index backwards rather than by sorting.
+        assert_eq!(plan(&database, "SELECT * FROM accounts WHERE BALANCE > 0 ORDER BY ID DESC").unwrap(), Plan::Filter {
+            input: full_scan(true),
+            condition: Expression::column("BALANCE").greater(Value::Integer(0))
+        });
+
+        assert_eq!(plan(&database, "UPDATE accounts SET BALANCE = 5 WHERE ID = 2").unwrap(), Plan::Update {
+            input: Box::new(Plan::Filter {
+                input: Box::new(Plan::KeyLookup { table: "accounts".into(), alias: None, key: Key(vec!(Value::Integer(2))) }),
+                condition: Expression::column("ID").equal(Value::Integer(2))
+            }),
+            table: "accounts".into(),
+            assignments: vec!((2, Value::BigInt(5)))
+        });
+        assert_eq!(plan(&database, "DELETE FROM accounts").unwrap(), Plan::Delete { input: full_scan(false), table: "accounts".into() });
+        match plan(&database, "UPDATE accounts SET ID = 5") {
+            Err(DbError::InvalidValue { ref column, .. }) if column == "ID" => {}
+            other => panic!("expected the key column to be rejected, got {:?}", other)
+        }
+
+        match plan(&database, "SELECT * FROM accounts WHERE OWNER > 1") {
+            Err(DbError::InvalidExpression(_)) => {}
+            other => panic!("expected an invalid expression, got {:?}", other)
+        }
+        match plan(&database, "SELECT * FROM accounts ORDER BY MISSING") {
+            Err(DbError::UnknownColumn(ref column)) if column == "MISSING" => {}
+            other => panic!("expected an unknown column, got {:?}", other)
+        }
+        match plan(&database, "SELECT * FROM missing") {
+            Err(DbError::UnknownTable(ref table)) if table == "missing" => {}
+            other => panic!("expected an unknown table, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn join_strategies_follow_the_indexes() {
+        let mut database = accounts();
+        database.create_table("transfers", Schema::new(vec!(
+            ColumnMetadata::new_index("ID".into(), DbType::Integer),
+            ColumnMetadata::new("SOURCE".into(), DbType::BigInt),
+            ColumnMetadata::new("TARGET".into(), DbType::Integer)
+        ))).unwrap();
+        let join = |on: &str| scan_of(plan(&database, &format!("SELECT * FROM transfers t JOIN accounts a ON {}", on)).unwrap());
+        let transfers = Box::new(Plan::FullScan { table: "transfers".into(), alias: Some("t".into()), reverse: false });
+        let accounts = Box::new(Plan::FullScan { table: "accounts".into(), alias: Some("a".into()), reverse: false });
+        let column = |name: &str| Expression::column(name);
+
+        // The key of accounts is found in the primary index.
+        assert_eq!(join("a.ID = TARGET"), Plan::IndexNestedLoopJoin {
+            left: transfers.clone(),
+            table: "accounts".into(),
+            alias: "a".into(),
+            key: vec!(2),
+            kind: JoinKind::Inner,
+            condition: column("a.ID").equal(column("t.TARGET"))
+        });
+        // A big integer might not fit in the integer key, so the accounts are hashed on it instead.
+        assert_eq!(join("t.SOURCE = a.ID AND BALANCE > 0"), Plan::HashJoin {
+            left: transfers.clone(),
+            right: accounts.clone(),
+            left_columns: vec!(1),
+            right_columns: vec!(0),
+            kind: JoinKind::Inner,
+            condition: column("t.SOURCE").equal(column("a.ID")).and(column("a.BALANCE").greater(Value::Integer(0)))
+        });
+        assert_eq!(join("a.ID < t.TARGET"), Plan::NestedLoopJoin {
+            left: transfers.clone(),
+            right: accounts.clone(),
+            kind: JoinKind::Inner,
+            condition: Some(column("a.ID").less(column("t.TARGET")))
+        });
+
+        // The parts of the condition that only concern the first table narrow down its scan.
+        match plan(&database, "SELECT a.OWNER FROM transfers t LEFT JOIN accounts a ON a.ID = t.TARGET WHERE t.ID = 7 AND a.OWNER IS NULL").unwrap() {
+            Plan::Project { input, columns } => {
+                assert_eq!(columns, vec!(4));
+                match *input {
+                    Plan::Filter { input, condition } => {
+                        assert_eq!(condition, column("a.OWNER").is_null());
+                        match *input {
+                            Plan::IndexNestedLoopJoin { left, kind: JoinKind::LeftOuter, .. } => assert_eq!(*left, Plan::Filter {
+                                input: Box::new(Plan::KeyLookup { table: "transfers".into(), alias: Some("t".into()), key: Key(vec!(Value::Integer(7))) }),
+                                condition: column("t.ID").equal(Value::Integer(7))
+                            }),
+                            other => panic!("expected a left join, got {:?}", other)
+                        }
+                    }
+                    other => panic!("expected a filter, got {:?}", other)
+                }
+            }
+            other => panic!("expected a projection, got {:?}", other)
+        }
+
+        match plan(&database, "SELECT ID FROM transfers t JOIN accounts a ON a.ID = t.TARGET") {
+            Err(DbError::AmbiguousName(ref name)) if name == "ID" => {}
+            other => panic!("expected an ambiguous column, got {:?}", other)
+        }
+        match plan(&database, "SELECT * FROM accounts CROSS JOIN accounts") {
+            Err(DbError::AmbiguousName(ref name)) if name == "accounts" => {}
+            other => panic!("expected an ambiguous table, got {:?}", other)
+        }
+        match plan(&database, "SELECT * FROM transfers t JOIN accounts a ON a.OWNER = t.TARGET") {
+            Err(DbError::InvalidExpression(_)) => {}
+            other => panic!("expected an invalid expression, got {:?}", other)
+        }
+    }
+}
@@ -1,168 +1,1141 @@
-use schema::Schema;
+use schema::{Schema, Name, Constraint};
 
-//use btree::BTree;
-use std::collections::BTreeMap;
+use btree::{self, BTree};
 
 use schema::DbType;
+use numeric::{Double, Decimal};
+use temporal::{Date, Time, Timestamp};
+use uuid::Uuid;
 
-use std::mem::transmute;
 
-const PAGE_SIZE:usize = 8000;
 pub type Tuple = Vec<Value>;
+/// A tuple in which values may be left out, to be filled in by the table.
+pub type PartialTuple = Vec<Option<Value>>;
+/// Decodes the values of a tuple out of an encoded row.
+pub type RowDecoder<'a> = Box<dyn Fn(&[u8]) -> DbResult<Tuple> + 'a>;
 
-use row::BoxedRow;
+use row::{BoxedRow, row_to_record, record_to_row, add_null_bitmap, take_bytes, null_bitmap_size, set_null};
+use page::{Page, PageType, RowLocation, MAX_RECORD_SIZE, NULL_BITMAP_VERSION};
+use pager::Pager;
+use overflow::{self, OverflowPointer};
+use error::{DbError, DbResult};
+use wal::{Wal, LogRecord};
+use index::{SecondaryIndex, IndexRange, leading_value_range};
+use expression::Expression;
 
 use std::slice::Iter;
+use std::borrow::Cow;
 use std::cmp::Ord;
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeBounds;
+use std::fmt;
 
+use serde_json;
 
-const INTEGER_SIZE: usize = 4;
-const BIG_INT_SIZE: usize = 8;
 
+/// Once the write-ahead log grows past this many bytes, the table is checkpointed.
+const CHECKPOINT_THRESHOLD: u64 = 1 << 20;
 
-#[derive(Serialize, Deserialize)]
+/// The largest record that can be stored, as the log and overflow pointers record lengths in 32 bits.
+const MAX_ROW_SIZE: usize = u32::max_value() as usize;
+
+
+/// A table whose rows are packed into pages.
+///
+/// The first page of the table holds its schema, and every page after that holds rows.
+/// The primary index, a B+tree from each row's key to its location in the pages,
+/// is kept in memory and is rebuilt from the pages when the table is opened.
+///
+/// Secondary indexes over other columns are kept in memory in the same way,
+/// with only the list of indexed columns being stored in the header page.
+///
+/// Rows that are too large for a page are spilled into a chain of overflow pages,
+/// which is freed again when the row is deleted.
+///
+/// Columns with a `Unique` constraint are always given a secondary index, which is used to find duplicates.
+///
+/// Every mutation is recorded in the table's write-ahead log before it is applied,
+/// and the pages are only written back to the file when the table is checkpointed.
 pub struct Table {
     schema: Schema,
-    rows: BTreeMap<Value, BoxedRow>
+    primary_index: BTree<Key>,
+    secondary_indexes: Vec<SecondaryIndex>,
+    /// The next value to be assigned to each `Serial` column, by the column's position.
+    serial_counters: BTreeMap<usize, i64>,
+    pager: Pager,
+    wal: Wal
+}
+
+/// What is stored in the header page of a table's file.
+#[derive(Serialize, Deserialize)]
+struct TableMetadata {
+    schema: Schema,
+    /// The names of the columns that have secondary indexes,
+    /// not including those that are created to enforce a `Unique` constraint.
+    secondary_indexes: Vec<Name>,
+    /// The next value to be assigned to each `Serial` column.
+    /// This keeps values from being reused after the rows holding the largest values are deleted.
+    #[serde(default)]
+    serial_counters: BTreeMap<Name, i64>
 }
 
 
 impl Table
 {
 
-    fn new(schema: Schema) -> Table {
-        Table {
+    /// Creates a table that only lives in memory.
+    pub(crate) fn new(schema: Schema) -> DbResult<Table> {
+        schema.check()?;
+        let mut table = Table {
             schema,
-            rows: BTreeMap::new()
+            primary_index: BTree::new(),
+            secondary_indexes: Vec::new(),
+            serial_counters: BTreeMap::new(),
+            pager: Pager::in_memory(),
+            wal: Wal::in_memory()
+        };
+        table.init_constraints(BTreeMap::new())?;
+        let header = table.header_page()?;
+        table.pager.allocate(header);
+        Ok(table)
+    }
+
+    /// Creates a new table backed by a file at the given path.
+    /// This will fail if the file already exists.
+    ///
+    /// The table's write-ahead log is kept next to it, at the same path with `.wal` appended.
+    pub fn create<P: AsRef<Path>>(path: P, schema: Schema) -> DbResult<Table> {
+        let path = path.as_ref();
+        schema.check()?;
+        let pager = Pager::create(path)?;
+        let (mut wal, _) = Wal::open(log_path(path))?;
+        wal.clear()?;
+        let mut table = Table {
+            schema,
+            primary_index: BTree::new(),
+            secondary_indexes: Vec::new(),
+            serial_counters: BTreeMap::new(),
+            pager,
+            wal
+        };
+        table.init_constraints(BTreeMap::new())?;
+        let header = table.header_page()?;
+        table.pager.allocate(header);
+        table.pager.flush()?;
+        Ok(table)
+    }
+
+    /// Opens a table from a file previously created with `create`,
+    /// reading its schema from the header page and indexing every stored row.
+    ///
+    /// Any mutations left in the write-ahead log by a crash are replayed,
+    /// and the recovered table is checkpointed before it is returned.
+    pub fn open<P: AsRef<Path>>(path: P) -> DbResult<Table> {
+        let path = path.as_ref();
+        let mut pager = Pager::open(path)?;
+        let (version, metadata): (u16, TableMetadata) = {
+            let header = pager.page(0)
+                .ok_or_else(|| DbError::CorruptFile("file contains no pages".into()))?;
+            let metadata = serde_json::from_slice(header.header_metadata()?)
+                .map_err(|e| DbError::CorruptFile(format!("unreadable schema: {}", e)))?;
+            (header.header_version()?, metadata)
+        };
+        let schema = metadata.schema;
+
+        let mut primary_index = BTree::new();
+        let mut stale_locations = Vec::new();
+        for page_number in 1..pager.num_pages() {
+            let page = pager.page(page_number)
+                .ok_or_else(|| DbError::CorruptFile(format!("page {} is missing", page_number)))?;
+            if page.page_type() != Some(PageType::Data) {
+                continue
+            }
+            for (slot, record) in page.records() {
+                let key = schema.extract_key_from_row(&record_to_row(&overflow::resolve(&pager, record)?, schema.columns.len())?)?;
+                let location = RowLocation { page: page_number, slot };
+                // A crash part way through a checkpoint can leave a moved row in both its old and new page.
+                // Only one copy is kept; replaying the log will bring it up to date.
+                if let Some(stale) = primary_index.insert(key, location.into()) {
+                    stale_locations.push(RowLocation::from(stale));
+                }
+            }
+        }
+        for stale in stale_locations {
+            if let Some(page) = pager.page_mut(stale.page) {
+                page.delete(stale.slot);
+            }
+        }
+        free_unreachable_overflow_pages(&mut pager, &primary_index)?;
+
+        let (wal, mut records) = Wal::open(log_path(path))?;
+        if version < NULL_BITMAP_VERSION {
+            // The log holds rows without a format version, written in the format of the file.
+            let num_columns = schema.columns.len();
+            records = records.into_iter().map(|record| record.map_row(|row| add_null_bitmap(&row, num_columns).into_boxed_slice())).collect();
+        }
+        let mut table = Table {
+            schema,
+            primary_index,
+            secondary_indexes: Vec::new(),
+            serial_counters: BTreeMap::new(),
+            pager,
+            wal
+        };
+        table.init_constraints(metadata.serial_counters)?;
+        for record in records {
+            table.apply(record)?;
+        }
+        for column in metadata.secondary_indexes {
+            table.build_secondary_index(&column)?;
+        }
+        table.checkpoint()?;
+        Ok(table)
+    }
+
+    /// Creates the header page that records the table's schema and indexes.
+    fn header_page(&self) -> DbResult<Page> {
+        let metadata = TableMetadata {
+            schema: self.schema.clone(),
+            secondary_indexes: self.secondary_indexes
+                .iter()
+                .map(|index| &self.schema.columns[index.column])
+                .filter(|column| !column.has_constraint(&Constraint::Unique))
+                .map(|column| column.name.clone())
+                .collect(),
+            serial_counters: self.serial_counters
+                .iter()
+                .map(|(column, next)| (self.schema.columns[*column].name.clone(), *next))
+                .collect()
+        };
+        let bytes = serde_json::to_vec(&metadata).expect("Table metadata should always be serializable");
+        Page::new_header(&bytes)
+    }
+
+    /// Creates a secondary index over the column, so that rows can be found by their value in that column.
+    /// The index is maintained on every insert, update and delete from then on.
+    pub fn create_index(&mut self, column: &str) -> DbResult<()> {
+        self.build_secondary_index(column)?;
+        self.write_header()
+    }
+
+    /// Removes the secondary index over the column.
+    pub fn drop_index(&mut self, column: &str) -> DbResult<()> {
+        let column_index = self.schema.column_index(column)
+            .ok_or_else(|| DbError::UnknownColumn(column.into()))?;
+        let position = self.secondary_indexes
+            .iter()
+            .position(|index| index.column == column_index)
+            .ok_or_else(|| DbError::NoSuchIndex(column.into()))?;
+        if self.schema.columns[column_index].has_constraint(&Constraint::Unique) {
+            return Err(DbError::IndexRequiredByConstraint(column.into()))
+        }
+        self.secondary_indexes.remove(position);
+        self.write_header()
+    }
+
+    /// Rewrites the header page, and checkpoints so that it reaches the disk right away.
+    fn write_header(&mut self) -> DbResult<()> {
+        self.checkpoint()
+    }
+
+    /// Builds the indexes used to enforce `Unique` constraints, and works out where each `Serial` column's sequence is up to.
+    /// The counters recorded in the header are used if they are ahead of the values found in the rows.
+    fn init_constraints(&mut self, recorded_counters: BTreeMap<Name, i64>) -> DbResult<()> {
+        let leading_key_column = self.schema.key_columns().first().cloned();
+        let unique_columns: Vec<Name> = self.schema.columns
+            .iter()
+            .enumerate()
+            .filter(|&(index, column)| column.has_constraint(&Constraint::Unique) && Some(index) != leading_key_column)
+            .map(|(_, column)| column.name.clone())
+            .collect();
+        for column in unique_columns {
+            self.build_secondary_index(&column)?;
         }
+
+        let row_to_tuple = self.schema.generate_general_row_to_tuple_fn();
+        for (index, column) in self.schema.columns.iter().enumerate() {
+            let is_number = column.db_type == DbType::Integer || column.db_type == DbType::BigInt;
+            if !column.has_constraint(&Constraint::Serial) || !is_number {
+                continue
+            }
+            let mut next_in_rows = 1;
+            for (_, location) in self.primary_index.iter() {
+                let tuple = row_to_tuple(&self.read_row(location.into())?)?;
+                if let Some(number) = tuple.get(index).and_then(serial_number) {
+                    next_in_rows = ::std::cmp::max(next_in_rows, number.saturating_add(1));
+                }
+            }
+            let recorded = recorded_counters.get(&column.name).cloned().unwrap_or(1);
+            self.serial_counters.insert(index, ::std::cmp::max(next_in_rows, recorded));
+        }
+        Ok(())
     }
 
-    pub fn find_tuple(&self, index: &Value) -> Option<Tuple> {
+    /// Builds an index over the column from the rows currently in the table.
+    fn build_secondary_index(&mut self, column: &str) -> DbResult<()> {
+        let column_index = self.schema.column_index(column)
+            .ok_or_else(|| DbError::UnknownColumn(column.into()))?;
+        if self.schema.key_columns().first() == Some(&column_index)
+            || self.secondary_indexes.iter().any(|index| index.column == column_index) {
+            return Err(DbError::IndexAlreadyExists(column.into()))
+        }
+
+        let mut index = SecondaryIndex::new(column_index);
+        let extract_value = self.schema.generate_extract_column_value_fn(column_index);
+        for (key, location) in self.primary_index.iter() {
+            let value = extract_value(&self.read_row(location.into())?)?;
+            index.insert(value, key.clone(), location);
+        }
+        self.secondary_indexes.push(index);
+        Ok(())
+    }
+
+    /// Whether the column at this position in the schema has a secondary index.
+    pub(crate) fn has_secondary_index(&self, column: usize) -> bool {
+        self.secondary_indexes.iter().any(|index| index.column == column)
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Writes every modified page to the table's file, after which the write-ahead log can be emptied.
+    /// The header page is refreshed first, so that the `Serial` counters are recorded.
+    pub fn checkpoint(&mut self) -> DbResult<()> {
+        let header = self.header_page()?;
+        *self.pager.page_mut(0).expect("Every table has a header page") = header;
+        self.pager.flush()?;
+        self.wal.clear()
+    }
+
+    /// Keeps the write-ahead log from growing without bound.
+    fn checkpoint_if_needed(&mut self) -> DbResult<()> {
+        if self.wal.len() > CHECKPOINT_THRESHOLD {
+            self.checkpoint()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Records the mutation in the write-ahead log, and then applies it.
+    fn log_and_apply(&mut self, record: LogRecord) -> DbResult<()> {
+        self.wal.append(&record)?;
+        self.apply(record)?;
+        self.checkpoint_if_needed()
+    }
+
+    /// Applies a mutation to the pages and the index, without logging it.
+    fn apply(&mut self, record: LogRecord) -> DbResult<()> {
+        match record {
+            LogRecord::Insert(row) => self.insert_row(row),
+            LogRecord::Update(row) => self.update_row(row),
+            LogRecord::Delete(row) => {
+                let key: Key = self.schema.extract_key_from_row(&row)?;
+                self.delete_row(&key)
+            }
+        }
+    }
+
+    /// Finds the tuple with the given key.
+    /// For a table with a single key column, the key can be given as a `Value`.
+    pub fn find_tuple<K: Into<Key>>(&self, key: K) -> DbResult<Option<Tuple>> {
         let conversion_fn = self.schema.generate_general_row_to_tuple_fn();
 
-        if let Some(row)  = self.rows.get(index) {
-            (conversion_fn)(row)
+        if let Some(location) = self.primary_index.find(&key.into()) {
+            let row = self.read_row(location.into())?;
+            (conversion_fn)(&row).map(Some)
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub fn insert_tuple(&mut self, tuple: Tuple) {
-        let row = tuple_to_row(tuple, &self.schema);
-        self.insert_row(row.into_boxed_slice());
+    /// Finds the tuple with the given key, reading only the named columns.
+    /// The values come back in the order the columns are named in.
+    pub fn find_tuple_columns<K: Into<Key>>(&self, key: K, columns: &[&str]) -> DbResult<Option<Tuple>> {
+        let conversion_fn = self.schema.generate_extract_columns_fn(&self.schema.column_indexes(columns)?);
+
+        if let Some(location) = self.primary_index.find(&key.into()) {
+            let row = self.read_row(location.into())?;
+            (conversion_fn)(&row).map(Some)
+        } else {
+            Ok(None)
+        }
     }
 
-    fn insert_row(&mut self, row: BoxedRow) {
-        let key: Value = self.schema.extract_index_value_from_row(row.clone());
-        self.rows.insert(key,row);
+    /// Lazily reads every tuple whose value in the first key column falls within the range, in ascending order of keys.
+    /// For a table with a composite key, this is a scan over a prefix of the key,
+    /// and every key column after the first is unconstrained.
+    /// The returned cursor can be reversed to read the tuples in descending order instead.
+    pub fn scan<R: RangeBounds<Value>>(&self, range: R) -> Scan {
+        let conversion_fn = self.schema.generate_general_row_to_tuple_fn();
+        Scan {
+            table: self,
+            locations: leading_value_range(&self.primary_index, range.start_bound(), range.end_bound()),
+            conversion_fn: Box::new(conversion_fn)
+        }
     }
 
-    pub fn delete_tuple(&mut self, index: &Value) {
-        self.rows.remove(index);
+    /// Like `scan`, but reads only the named columns of each tuple, in the order they are named in.
+    /// The bytes of every other column are skipped over without being decoded.
+    pub fn scan_columns<R: RangeBounds<Value>>(&self, range: R, columns: &[&str]) -> DbResult<Scan> {
+        let conversion_fn = self.schema.generate_extract_columns_fn(&self.schema.column_indexes(columns)?);
+        Ok(Scan {
+            table: self,
+            locations: leading_value_range(&self.primary_index, range.start_bound(), range.end_bound()),
+            conversion_fn: Box::new(conversion_fn)
+        })
     }
 
-    pub fn update_tuple(&mut self, tuple: Tuple) {
-        let row = tuple_to_row(tuple, &self.schema);
-        self.update_row(row.into_boxed_slice());
+    /// Lazily reads every tuple whose entire key falls within the range, in ascending order of keys.
+    /// Keys are compared column by column, so a bound made of fewer values than the key
+    /// sorts before every key that starts with those values.
+    pub fn scan_keys<R: RangeBounds<Key>>(&self, range: R) -> Scan {
+        let conversion_fn = self.schema.generate_general_row_to_tuple_fn();
+        Scan {
+            table: self,
+            locations: self.primary_index.range((range.start_bound(), range.end_bound())),
+            conversion_fn: Box::new(conversion_fn)
+        }
     }
 
-    fn update_row(&mut self, row: BoxedRow) {
-        let key: Value = self.schema.extract_index_value_from_row(row.clone());
-        self.delete_tuple(&key);
+    /// Lazily reads every tuple whose value in the column falls within the range,
+    /// in ascending order of that value, using the column's secondary index.
+    pub fn index_scan<R: RangeBounds<Value>>(&self, column: &str, range: R) -> DbResult<IndexScan> {
+        let column_index = self.schema.column_index(column)
+            .ok_or_else(|| DbError::UnknownColumn(column.into()))?;
+        let index = self.secondary_indexes
+            .iter()
+            .find(|index| index.column == column_index)
+            .ok_or_else(|| DbError::NoSuchIndex(column.into()))?;
+        let conversion_fn = self.schema.generate_general_row_to_tuple_fn();
+        Ok(IndexScan {
+            table: self,
+            locations: index.range(range.start_bound(), range.end_bound()),
+            conversion_fn: Box::new(conversion_fn)
+        })
+    }
+
+    /// Finds every tuple whose value in the column is equal to the provided value.
+    ///
+    /// The first key column is looked up in the primary index, and a column with a secondary index is looked up in that.
+    /// Any other column requires every row in the table to be checked.
+    pub fn find_tuples_by(&self, column: &str, value: &Value) -> DbResult<Vec<Tuple>> {
+        let column_index = self.schema.column_index(column)
+            .ok_or_else(|| DbError::UnknownColumn(column.into()))?;
+        if self.schema.key_columns().first() == Some(&column_index) {
+            return self.scan(value.clone()..=value.clone()).collect()
+        }
+        if self.secondary_indexes.iter().any(|index| index.column == column_index) {
+            return self.index_scan(column, value.clone()..=value.clone())?.collect()
+        }
+        self.scan(..)
+            .filter(|tuple| tuple.as_ref().map_or(true, |tuple| tuple.get(column_index) == Some(value)))
+            .collect()
+    }
+
+    /// Finds every tuple for which the condition is true.
+    /// The condition is checked against each row before it is decoded, reading only the columns the condition refers to.
+    pub fn find_tuples_where(&self, condition: &Expression) -> DbResult<Vec<Tuple>> {
+        let row_to_tuple = self.schema.generate_general_row_to_tuple_fn();
+        self.rows_where(condition)?
+            .iter()
+            .map(|row| row_to_tuple(row))
+            .collect()
+    }
+
+    /// Gets every row for which the condition is true.
+    fn rows_where(&self, condition: &Expression) -> DbResult<Vec<BoxedRow>> {
+        let predicate = condition.compile(&self.schema)?;
+        let mut rows = Vec::new();
+        for location in self.candidate_locations(condition) {
+            let row = self.read_row(location.into())?;
+            if predicate.matches_row(&row)? {
+                rows.push(row.into_owned().into_boxed_slice());
+            }
+        }
+        Ok(rows)
+    }
+
+    /// The locations of the rows that the condition might be true for.
+    ///
+    /// If the condition narrows down the first key column, only that range of the primary index is read,
+    /// and otherwise a secondary index over a column that the condition narrows down is used.
+    /// If neither is possible, every row is a candidate.
+    fn candidate_locations(&self, condition: &Expression) -> Vec<usize> {
+        if let Some(&leading_key_column) = self.schema.key_columns().first() {
+            if let Some((start, end)) = condition.column_range(&self.schema, leading_key_column) {
+                return leading_value_range(&self.primary_index, start.as_ref(), end.as_ref())
+                    .map(|(_, location)| location)
+                    .collect()
+            }
+        }
+        for index in &self.secondary_indexes {
+            if let Some((start, end)) = condition.column_range(&self.schema, index.column) {
+                return index.range(start.as_ref(), end.as_ref()).collect()
+            }
+        }
+        self.primary_index.iter().map(|(_, location)| location).collect()
+    }
+
+    /// Sets the columns to the given values in every tuple for which the condition is true,
+    /// returning the number of tuples that were updated.
+    ///
+    /// Every updated tuple is checked before any of them are written, so either all of them are updated or none are.
+    /// Key columns can't be changed this way.
+    pub fn update_where(&mut self, condition: &Expression, changes: &[(&str, Value)]) -> DbResult<usize> {
+        let mut changed_columns = Vec::with_capacity(changes.len());
+        for &(name, ref value) in changes {
+            let index = self.schema.column_index(name)
+                .ok_or_else(|| DbError::UnknownColumn(name.into()))?;
+            let column = &self.schema.columns[index];
+            if column.is_index {
+                return Err(DbError::InvalidValue { column: column.name.clone(), reason: "key columns can't be changed by update_where".into() })
+            }
+            changed_columns.push((index, column.coerce(value.clone())?));
+        }
+
+        let row_to_tuple = self.schema.generate_general_row_to_tuple_fn();
+        let mut rows = Vec::new();
+        for row in self.rows_where(condition)? {
+            let mut tuple = row_to_tuple(&row)?;
+            for &(index, ref value) in &changed_columns {
+                tuple[index] = value.clone();
+            }
+            self.check_unique(&tuple)?;
+            let row = tuple_to_row(tuple, &self.schema)?;
+            check_record_size(&row)?;
+            rows.push(row.into_boxed_slice());
+        }
+        // Each updated tuple was only checked against the rows already in the table,
+        // but every one of them gets the same value, so they would clash with one another.
+        if rows.len() > 1 {
+            for &(index, ref value) in &changed_columns {
+                let column = &self.schema.columns[index];
+                if column.has_constraint(&Constraint::Unique) && *value != Value::Null {
+                    return Err(DbError::UniqueViolation { column: column.name.clone(), value: value.clone() })
+                }
+            }
+        }
+
+        let count = rows.len();
+        for row in rows {
+            self.log_and_apply(LogRecord::Update(row))?;
+        }
+        Ok(count)
+    }
+
+    /// Deletes every tuple for which the condition is true, returning the number of tuples that were deleted.
+    pub fn delete_where(&mut self, condition: &Expression) -> DbResult<usize> {
+        let rows = self.rows_where(condition)?;
+        let count = rows.len();
+        for row in rows {
+            self.log_and_apply(LogRecord::Delete(row))?;
+        }
+        Ok(count)
+    }
+
+    /// Gets the record in the slot at the location, which may be a pointer to overflow pages.
+    fn slot_record(&self, location: RowLocation) -> DbResult<&[u8]> {
+        self.pager
+            .page(location.page)
+            .and_then(|page| page.get(location.slot))
+            .ok_or_else(|| DbError::CorruptFile(format!("no row in slot {} of page {}", location.slot, location.page)))
+    }
+
+    /// Gets the bytes of the row stored at the location, without its format version.
+    /// A row held in overflow pages is put back together, and so has to be copied.
+    fn read_row(&self, location: RowLocation) -> DbResult<Cow<[u8]>> {
+        let num_columns = self.schema.columns.len();
+        match overflow::resolve(&self.pager, self.slot_record(location)?)? {
+            Cow::Borrowed(record) => record_to_row(record, num_columns),
+            Cow::Owned(record) => record_to_row(&record, num_columns).map(|row| Cow::Owned(row.into_owned()))
+        }
+    }
+
+    /// Inserts the tuple, replacing any existing tuple with the same key.
+    pub fn insert_tuple(&mut self, tuple: Tuple) -> DbResult<()> {
+        self.insert_partial_tuple(tuple.into_iter().map(Some).collect())
+            .map(|_| ())
+    }
+
+    /// Inserts a tuple in which some values may be left out.
+    /// The tuple is checked against the schema before anything is written.
+    /// Left out values in `Serial` columns are filled in with the next number in the column's sequence,
+    /// and left out values in other columns are null.
+    ///
+    /// The completed tuple is returned, so that the caller can find out which serial numbers were assigned.
+    pub fn insert_partial_tuple(&mut self, tuple: PartialTuple) -> DbResult<Tuple> {
+        let tuple = self.complete_tuple(tuple)?;
+        let tuple = self.schema.coerce_tuple(tuple)?;
+        self.check_unique(&tuple)?;
+        let row = tuple_to_row(tuple.clone(), &self.schema)?.into_boxed_slice();
+        check_record_size(&row)?;
+        self.log_and_apply(LogRecord::Insert(row))?;
+        Ok(tuple)
+    }
+
+    /// Fills in the values that were left out of the tuple, or reports the column that can't be left out.
+    pub(crate) fn complete_tuple(&self, tuple: PartialTuple) -> DbResult<Tuple> {
+        self.complete_tuples(vec!(tuple)).map(|mut tuples| tuples.remove(0))
+    }
+
+    /// Fills in the values that were left out of tuples that are to be inserted one after another.
+    /// Each `Serial` column's sequence is moved along past the numbers in the tuples before.
+    pub(crate) fn complete_tuples(&self, tuples: Vec<PartialTuple>) -> DbResult<Vec<Tuple>> {
+        let mut counters = self.serial_counters.clone();
+        let mut completed_tuples = Vec::with_capacity(tuples.len());
+        for tuple in tuples {
+            let mut completed = Vec::with_capacity(tuple.len());
+            for (index, value) in tuple.into_iter().enumerate() {
+                let value = match value {
+                    Some(value) => value,
+                    None => self.default_value(index, &counters)?
+                };
+                completed.push(value);
+            }
+            for (&column, next) in counters.iter_mut() {
+                if let Some(number) = completed.get(column).and_then(serial_number) {
+                    if number >= *next {
+                        *next = number.saturating_add(1);
+                    }
+                }
+            }
+            completed_tuples.push(completed);
+        }
+        Ok(completed_tuples)
+    }
+
+    /// The value used for a column that was left out of a tuple:
+    /// the next number in a `Serial` column's sequence, or else null if the column allows it.
+    fn default_value(&self, index: usize, serial_counters: &BTreeMap<usize, i64>) -> DbResult<Value> {
+        let column = match self.schema.columns.get(index) {
+            Some(column) => column,
+            None => return Err(DbError::MissingValue { column: format!("#{}", index) })
+        };
+        let next = match serial_counters.get(&index) {
+            Some(next) => *next,
+            None if column.is_nullable() => return Ok(Value::Null),
+            None if column.has_constraint(&Constraint::NotNull) => return Err(DbError::NotNullViolation { column: column.name.clone() }),
+            None => return Err(DbError::MissingValue { column: column.name.clone() })
+        };
+        let value = match column.db_type {
+            DbType::Integer if next <= i64::from(i32::max_value()) => Value::Integer(next as i32),
+            DbType::BigInt if next < i64::max_value() => Value::BigInt(next),
+            _ => return Err(DbError::SerialOutOfRange { column: column.name.clone() })
+        };
+        // The counter is only moved along once the row has been stored,
+        // so that a rejected tuple doesn't use up a number.
+        Ok(value)
+    }
+
+    /// Moves the sequences of any `Serial` columns past the values in the row.
+    fn advance_serial_counters(&mut self, row: &[u8]) -> DbResult<()> {
+        let columns: Vec<usize> = self.serial_counters.keys().cloned().collect();
+        for column in columns {
+            let value = (self.schema.generate_extract_column_value_fn(column))(row)?;
+            if let Some(number) = serial_number(&value) {
+                let next = self.serial_counters.entry(column).or_insert(1);
+                if number >= *next {
+                    *next = number.saturating_add(1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure that no other row shares a value with the tuple in any column with a `Unique` constraint.
+    /// The row with the same key as the tuple doesn't count, as the tuple will replace it.
+    pub(crate) fn check_unique(&self, tuple: &Tuple) -> DbResult<()> {
+        let key = self.schema.key_from_tuple(tuple);
+        for (index, column) in self.schema.columns.iter().enumerate() {
+            if !column.has_constraint(&Constraint::Unique) {
+                continue
+            }
+            // Nulls are never equal to one another, so any number of rows may have them.
+            let value = match tuple.get(index) {
+                Some(&Value::Null) | None => continue,
+                Some(value) => value
+            };
+            let is_duplicate = self.find_tuples_by(&column.name, value)?
+                .iter()
+                .any(|other| self.schema.key_from_tuple(other) != key);
+            if is_duplicate {
+                return Err(DbError::UniqueViolation { column: column.name.clone(), value: value.clone() })
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure that the tuple, which must already be coerced to the schema, could be written over the row with the same key,
+    /// without writing anything.
+    pub(crate) fn check_tuple(&self, tuple: &Tuple) -> DbResult<()> {
+        self.check_unique(tuple)?;
+        check_record_size(&tuple_to_row(tuple.clone(), &self.schema)?)
+    }
+
+    fn insert_row(&mut self, row: BoxedRow) -> DbResult<()> {
+        let key: Key = self.schema.extract_key_from_row(&row)?;
+        if let Some(location) = self.primary_index.find(&key) {
+            // Inserting over an existing key replaces that row.
+            self.remove_row(&key, location.into())?;
+        }
+        let record = row_to_record(&row);
+        let location = self.store_record(&record)?;
+        self.primary_index.insert(key.clone(), location.into());
+        self.index_row(&row, &key, location)?;
+        self.advance_serial_counters(&row)
+    }
+
+    /// The value of each secondary indexed column in the row.
+    fn secondary_values(&self, row: &[u8]) -> DbResult<Vec<Value>> {
+        self.secondary_indexes
+            .iter()
+            .map(|index| (self.schema.generate_extract_column_value_fn(index.column))(row))
+            .collect()
+    }
+
+    /// Adds the row to every secondary index.
+    fn index_row(&mut self, row: &[u8], key: &Key, location: RowLocation) -> DbResult<()> {
+        let values = self.secondary_values(row)?;
+        for (index, value) in self.secondary_indexes.iter_mut().zip(values) {
+            index.insert(value, key.clone(), location.into());
+        }
+        Ok(())
+    }
+
+    /// Removes the row from every secondary index.
+    fn unindex_row(&mut self, row: &[u8], key: &Key) -> DbResult<()> {
+        let values = self.secondary_values(row)?;
+        for (index, value) in self.secondary_indexes.iter_mut().zip(values) {
+            index.remove(value, key.clone());
+        }
+        Ok(())
+    }
+
+    /// Places the record in the first page with enough room for it, allocating a new page if none has room.
+    /// A record that is too large for any page is written to overflow pages, and only a pointer to them is placed.
+    fn store_record(&mut self, record: &[u8]) -> DbResult<RowLocation> {
+        if record.len() > MAX_RECORD_SIZE {
+            let pointer = overflow::write_chain(&mut self.pager, record);
+            return self.store_record(&pointer.to_record())
+        }
+        let page_number = match self.pager.find_page_with_space(record.len()) {
+            Some(page_number) => page_number,
+            None => self.pager.allocate(Page::new_data())
+        };
+        let slot = self.pager
+            .page_mut(page_number)
+            .and_then(|page| page.insert(record))
+            .expect("The page was checked to have enough space");
+        Ok(RowLocation { page: page_number, slot })
+    }
+
+    /// Frees the row's slot along with any overflow pages, and removes it from the secondary indexes.
+    /// The primary index is left for the caller to update.
+    fn remove_row(&mut self, key: &Key, location: RowLocation) -> DbResult<()> {
+        let old_row = self.read_row(location)?.into_owned();
+        self.unindex_row(&old_row, key)?;
+        if let Some(pointer) = OverflowPointer::from_record(self.slot_record(location)?)? {
+            overflow::free_chain(&mut self.pager, pointer)?;
+        }
+        if let Some(page) = self.pager.page_mut(location.page) {
+            page.delete(location.slot);
+        }
+        Ok(())
+    }
+
+    /// Deletes the tuple with the given key, if there is one.
+    pub fn delete_tuple<K: Into<Key>>(&mut self, key: K) -> DbResult<()> {
+        let row: BoxedRow = match self.primary_index.find(&key.into()) {
+            Some(location) => self.read_row(location.into())?.into_owned().into_boxed_slice(),
+            None => return Ok(())
+        };
+        self.log_and_apply(LogRecord::Delete(row))
+    }
+
+    fn delete_row(&mut self, key: &Key) -> DbResult<()> {
+        match self.primary_index.remove(key) {
+            Some(location) => self.remove_row(key, location.into()),
+            None => Ok(())
+        }
+    }
+
+    /// Replaces the tuple that has the same key as the provided one.
+    pub fn update_tuple(&mut self, tuple: Tuple) -> DbResult<()> {
+        let tuple = self.schema.coerce_tuple(tuple)?;
+        self.check_unique(&tuple)?;
+        let row = tuple_to_row(tuple, &self.schema)?.into_boxed_slice();
+        check_record_size(&row)?;
+        self.log_and_apply(LogRecord::Update(row))
+    }
+
+    fn update_row(&mut self, row: BoxedRow) -> DbResult<()> {
+        let key: Key = self.schema.extract_key_from_row(&row)?;
+        if let Some(location) = self.primary_index.find(&key).map(RowLocation::from) {
+            let old_row = self.read_row(location)?.into_owned();
+            let record = row_to_record(&row);
+            // A row in overflow pages is moved instead, so that its chain is freed.
+            let in_place = record.len() <= MAX_RECORD_SIZE
+                && OverflowPointer::from_record(self.slot_record(location)?)?.is_none();
+            let replaced = in_place && self.pager
+                .page_mut(location.page)
+                .map(|page| page.replace(location.slot, &record))
+                .unwrap_or(false);
+            if replaced {
+                self.unindex_row(&old_row, &key)?;
+                self.index_row(&row, &key, location)?;
+                return self.advance_serial_counters(&row)
+            }
+        }
         self.insert_row(row)
     }
 }
 
+/// A cursor over a range of a table's primary index, producing the tuples found along the way.
+pub struct Scan<'a> {
+    table: &'a Table,
+    locations: btree::Range<'a, Key>,
+    conversion_fn: RowDecoder<'a>
+}
+
+impl<'a> Scan<'a> {
+    fn tuple_at(&self, location: usize) -> DbResult<Tuple> {
+        let row = self.table.read_row(location.into())?;
+        (self.conversion_fn)(&row)
+    }
+}
 
+impl<'a> Iterator for Scan<'a> {
+    type Item = DbResult<Tuple>;
 
+    fn next(&mut self) -> Option<DbResult<Tuple>> {
+        let (_, location) = self.locations.next()?;
+        Some(self.tuple_at(location))
+    }
+}
 
+impl<'a> DoubleEndedIterator for Scan<'a> {
+    fn next_back(&mut self) -> Option<DbResult<Tuple>> {
+        let (_, location) = self.locations.next_back()?;
+        Some(self.tuple_at(location))
+    }
+}
 
+/// A cursor over a range of one of a table's secondary indexes, producing the tuples found along the way.
+pub struct IndexScan<'a> {
+    table: &'a Table,
+    locations: IndexRange<'a>,
+    conversion_fn: RowDecoder<'a>
+}
 
-#[derive(Clone, Debug, Ord, Eq, PartialEq, PartialOrd, Serialize, Deserialize)]
+impl<'a> IndexScan<'a> {
+    fn tuple_at(&self, location: usize) -> DbResult<Tuple> {
+        let row = self.table.read_row(location.into())?;
+        (self.conversion_fn)(&row)
+    }
+}
+
+impl<'a> Iterator for IndexScan<'a> {
+    type Item = DbResult<Tuple>;
+
+    fn next(&mut self) -> Option<DbResult<Tuple>> {
+        let location = self.locations.next()?;
+        Some(self.tuple_at(location))
+    }
+}
+
+impl<'a> DoubleEndedIterator for IndexScan<'a> {
+    fn next_back(&mut self) -> Option<DbResult<Tuple>> {
+        let location = self.locations.next_back()?;
+        Some(self.tuple_at(location))
+    }
+}
+
+/// The number held by a value in a `Serial` column.
+fn serial_number(value: &Value) -> Option<i64> {
+    match *value {
+        Value::Integer(number) => Some(i64::from(number)),
+        Value::BigInt(number) => Some(number),
+        _ => None
+    }
+}
+
+/// The path of the write-ahead log belonging to the table at the given path.
+pub(crate) fn log_path(table_path: &Path) -> PathBuf {
+    let mut path = table_path.as_os_str().to_owned();
+    path.push(".wal");
+    PathBuf::from(path)
+}
+
+/// Makes sure that the row, once prefixed with its metadata byte, isn't too large to be stored.
+fn check_record_size(row: &[u8]) -> DbResult<()> {
+    let size = row.len() + 1;
+    if size > MAX_ROW_SIZE {
+        Err(DbError::RowTooLarge { size, max: MAX_ROW_SIZE })
+    } else {
+        Ok(())
+    }
+}
+
+/// Frees any overflow pages that no row leads to,
+/// which a crash part way through a checkpoint can leave behind.
+fn free_unreachable_overflow_pages(pager: &mut Pager, primary_index: &BTree<Key>) -> DbResult<()> {
+    let mut reachable = BTreeSet::new();
+    for (_, location) in primary_index.iter() {
+        let location = RowLocation::from(location);
+        let record = pager.page(location.page)
+            .and_then(|page| page.get(location.slot))
+            .expect("Every indexed location was read from the pages");
+        if let Some(pointer) = OverflowPointer::from_record(record)? {
+            reachable.extend(overflow::chain_pages(pager, pointer)?);
+        }
+    }
+    for page_number in 1..pager.num_pages() {
+        let is_overflow = pager.page(page_number).and_then(|page| page.page_type()) == Some(PageType::Overflow);
+        if is_overflow && !reachable.contains(&page_number) {
+            pager.free(page_number);
+        }
+    }
+    Ok(())
+}
+
+
+
+
+
+
+
+
+#[derive(Clone, Debug, Ord, Eq, PartialEq, PartialOrd, Hash, Serialize, Deserialize)]
 pub enum Value {
+    /// The absence of a value. Nulls sort before every other value.
+    Null,
     Integer(i32),
     BigInt(i64),
-    String(String)
+    String(String),
+    Boolean(bool),
+    Double(Double),
+    Decimal(Decimal),
+    Date(Date),
+    Time(Time),
+    Timestamp(Timestamp),
+    Uuid(Uuid),
+    Bytes(Vec<u8>)
 }
 
 
+/// The key that a row is stored under: the values of its key columns, in the order they appear in the schema.
+/// Keys are ordered lexicographically, column by column.
+#[derive(Clone, Debug, Ord, Eq, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Key(pub Vec<Value>);
+
+impl From<Value> for Key {
+    fn from(value: Value) -> Key {
+        Key(vec!(value))
+    }
+}
+
+impl<'a> From<&'a Value> for Key {
+    fn from(value: &'a Value) -> Key {
+        Key(vec!(value.clone()))
+    }
+}
+
+impl From<Vec<Value>> for Key {
+    fn from(values: Vec<Value>) -> Key {
+        Key(values)
+    }
+}
+
+impl AsRef<[Value]> for Key {
+    fn as_ref(&self) -> &[Value] {
+        &self.0
+    }
+}
+
+/// Writes the value the way it would be written in SQL.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => write!(f, "NULL"),
+            Value::Integer(integer) => write!(f, "{}", integer),
+            Value::BigInt(integer) => write!(f, "{}", integer),
+            Value::String(ref s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Value::Boolean(boolean) => write!(f, "{}", if boolean { "TRUE" } else { "FALSE" }),
+            Value::Double(double) => write!(f, "{:?}", double.0),
+            Value::Decimal(ref decimal) => write!(f, "{}", decimal),
+            Value::Date(date) => write!(f, "'{}'", date),
+            Value::Time(time) => write!(f, "'{}'", time),
+            Value::Timestamp(timestamp) => write!(f, "'{}'", timestamp),
+            Value::Uuid(uuid) => write!(f, "'{}'", uuid),
+            Value::Bytes(ref bytes) => {
+                write!(f, "X'")?;
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "'")
+            }
+        }
+    }
+}
+
 use schema::ColumnMetadata;
 impl Value {
-    pub fn into_bytes(self, metadata: &ColumnMetadata) -> Vec<u8> {
-        match self {
-            Value::Integer(value) => {
-                let bytes: [u8; INTEGER_SIZE] = unsafe { transmute(value) };
-                println!("inserting: {:?}", bytes);
-                bytes.to_vec()
-            }
-            Value::BigInt(value) => {
-                let bytes: [u8; BIG_INT_SIZE] = unsafe { transmute(value) };
-                bytes.to_vec()
-            }
-            Value::String(value) => {
-
-                if let DbType::String {length} = metadata.db_type {
-                    let mut bytes: Vec<u8> = unsafe {transmute(value)};
-                    let byte_length = bytes.len();
-                    let required_padding_size: usize = (length as usize) - byte_length; // this could panic
-                    let mut pad = vec![0u8; required_padding_size];
-                    bytes.append(&mut pad);
-                    bytes
-                } else {
-                    panic!("Wrong metadata for into_bytes")
-                }
+    /// Encodes the value for storage in a column described by the metadata,
+    /// in the layout described in the `row` module.
+    pub fn into_bytes(self, metadata: &ColumnMetadata) -> DbResult<Vec<u8>> {
+        metadata.check_value(&self)?;
+        match (self, &metadata.db_type) {
+            // Nulls take up no bytes, they are only marked in the row's null bitmap.
+            (Value::Null, _) => Ok(Vec::new()),
+            (Value::Integer(value), &DbType::Integer) => Ok(value.to_le_bytes().to_vec()),
+            (Value::BigInt(value), &DbType::BigInt) => Ok(value.to_le_bytes().to_vec()),
+            (Value::String(value), &DbType::String { length }) => {
+                let mut bytes: Vec<u8> = value.into_bytes();
+                let byte_length = bytes.len();
+                let required_padding_size: usize = (length as usize) - byte_length;
+                let mut pad = vec![0u8; required_padding_size];
+                bytes.append(&mut pad);
+                Ok(bytes)
+            }
+            (Value::String(value), &DbType::Text) => {
+                let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+                bytes.extend_from_slice(value.as_bytes());
+                Ok(bytes)
+            }
+            (Value::Boolean(value), &DbType::Boolean) => Ok(vec!(value as u8)),
+            (Value::Double(value), &DbType::Double) => Ok(value.0.to_bits().to_le_bytes().to_vec()),
+            (Value::Decimal(value), &DbType::Decimal { scale, .. }) => {
+                let rescaled = value.rescale(scale)
+                    .ok_or_else(|| DbError::NumericOverflow { column: metadata.name.clone(), value: Value::Decimal(value) })?;
+                Ok(rescaled.units().to_le_bytes().to_vec())
             }
-            _ => unimplemented!()
+            (Value::Date(value), &DbType::Date) => Ok(value.0.to_le_bytes().to_vec()),
+            (Value::Time(value), &DbType::Time) => Ok(value.0.to_le_bytes().to_vec()),
+            (Value::Timestamp(value), &DbType::Timestamp) => Ok(value.0.to_le_bytes().to_vec()),
+            (Value::Uuid(value), &DbType::Uuid) => Ok(value.0.to_vec()),
+            (Value::Bytes(value), &DbType::Bytes) => {
+                let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+                bytes.extend_from_slice(&value);
+                Ok(bytes)
+            }
+            (value, db_type) => Err(DbError::TypeMismatch {
+                column: metadata.name.clone(),
+                expected: db_type.clone(),
+                found: value
+            })
         }
     }
 
-    pub fn from_bytestream(db_type: &DbType, iter_bytes: &mut Iter<u8>) -> Self {
-        let bytes_to_take = db_type.size_bytes();
-        let bytes: Vec<u8> = iter_bytes.take(bytes_to_take).cloned().collect();
-        match *db_type {
+    /// Decodes a value of the given type from the front of the bytes.
+    pub fn from_bytestream(db_type: &DbType, iter_bytes: &mut Iter<u8>) -> DbResult<Self> {
+        let value = match *db_type {
             DbType::Integer => {
                 let mut byte_array = [0u8; 4];
-                byte_array.clone_from_slice(&bytes[0..bytes_to_take]);
-
-                let int: i32 = unsafe {
-                     transmute::<[u8; 4], i32>(byte_array)
-                };
-                Value::Integer(int)
+                byte_array.copy_from_slice(take_bytes(iter_bytes, 4)?);
+                Value::Integer(i32::from_le_bytes(byte_array))
             }
             DbType::BigInt => {
                 let mut byte_array = [0u8; 8];
-                byte_array.clone_from_slice(&bytes[0..bytes_to_take]);
-
-                let int: i64 = unsafe {
-                     transmute::<[u8; 8], i64>(byte_array)
-                };
-                Value::BigInt(int)
+                byte_array.copy_from_slice(take_bytes(iter_bytes, 8)?);
+                Value::BigInt(i64::from_le_bytes(byte_array))
             }
-            DbType::String{ length } => {
-                let s: String = unsafe {
-                     transmute(bytes)
-                };
-                Value::String(s)
+            DbType::String { length } => {
+                let bytes = take_bytes(iter_bytes, length as usize)?;
+                let end = bytes.iter().rposition(|byte| *byte != 0).map_or(0, |last| last + 1);
+                Value::String(decode_utf8(&bytes[..end])?)
             }
+            DbType::Text => {
+                let length = length_prefix(iter_bytes)?;
+                Value::String(decode_utf8(take_bytes(iter_bytes, length)?)?)
+            }
+            DbType::Boolean => {
+                match take_bytes(iter_bytes, 1)?[0] {
+                    0 => Value::Boolean(false),
+                    1 => Value::Boolean(true),
+                    _ => return Err(DbError::CorruptFile("boolean is neither 0 nor 1".into()))
+                }
+            }
+            DbType::Double => {
+                let mut byte_array = [0u8; 8];
+                byte_array.copy_from_slice(take_bytes(iter_bytes, 8)?);
+                Value::Double(Double(f64::from_bits(u64::from_le_bytes(byte_array))))
+            }
+            DbType::Decimal { scale, .. } => {
+                let mut byte_array = [0u8; 16];
+                byte_array.copy_from_slice(take_bytes(iter_bytes, 16)?);
+                let decimal = Decimal::new(i128::from_le_bytes(byte_array), scale)
+                    .ok_or_else(|| DbError::CorruptFile("decimal scale is too large".into()))?;
+                Value::Decimal(decimal)
+            }
+            DbType::Date => {
+                let mut byte_array = [0u8; 4];
+                byte_array.copy_from_slice(take_bytes(iter_bytes, 4)?);
+                Value::Date(Date(i32::from_le_bytes(byte_array)))
+            }
+            DbType::Time => {
+                let mut byte_array = [0u8; 8];
+                byte_array.copy_from_slice(take_bytes(iter_bytes, 8)?);
+                Value::Time(Time(i64::from_le_bytes(byte_array)))
+            }
+            DbType::Timestamp => {
+                let mut byte_array = [0u8; 8];
+                byte_array.copy_from_slice(take_bytes(iter_bytes, 8)?);
+                Value::Timestamp(Timestamp(i64::from_le_bytes(byte_array)))
+            }
+            DbType::Uuid => {
+                let mut byte_array = [0u8; 16];
+                byte_array.copy_from_slice(take_bytes(iter_bytes, 16)?);
+                Value::Uuid(Uuid(byte_array))
+            }
+            DbType::Bytes => {
+                let length = length_prefix(iter_bytes)?;
+                Value::Bytes(take_bytes(iter_bytes, length)?.to_vec())
+            }
+        };
+        Ok(value)
+    }
 
-            _ => unimplemented!()
-        }
-
+    /// Moves past a value of the given type at the front of the bytes, without decoding it.
+    pub fn skip_bytestream(db_type: &DbType, iter_bytes: &mut Iter<u8>) -> DbResult<()> {
+        let length = match *db_type {
+            DbType::Text | DbType::Bytes => length_prefix(iter_bytes)?,
+            ref fixed_size => fixed_size.size_bytes()
+        };
+        take_bytes(iter_bytes, length).map(|_| ())
     }
 }
 
+/// Reads the length prefix of a `Text` or `Bytes` value.
+fn length_prefix(iter_bytes: &mut Iter<u8>) -> DbResult<usize> {
+    let mut byte_array = [0u8; 4];
+    byte_array.copy_from_slice(take_bytes(iter_bytes, 4)?);
+    Ok(u32::from_le_bytes(byte_array) as usize)
+}
+
+fn decode_utf8(bytes: &[u8]) -> DbResult<String> {
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| DbError::CorruptFile("string is not valid UTF-8".into()))
+}
+
 /// Converts a Tuple to a Vec<u8>
-/// Assumes that the tuple is the same length and orientation as the schema.
+/// The tuple must have a value for every column in the schema, in the same order.
+/// The row starts with a bitmap marking which of the values are null.
 #[inline(always)]
-fn tuple_to_row(tuple: Tuple, schema: &Schema) -> Vec<u8> {
-    tuple.into_iter()
-        .zip(schema.columns.iter())
-        .map(|x| x.0.into_bytes(x.1))
-        .fold(Vec::new(), |acc: Vec<u8>, each: Vec<u8>| {
-            let mut acc = acc;
-            acc.extend_from_slice(&each);
-            acc
-        })
+pub(crate) fn tuple_to_row(tuple: Tuple, schema: &Schema) -> DbResult<Vec<u8>> {
+    if tuple.len() != schema.columns.len() {
+        return Err(DbError::WrongNumberOfValues { expected: schema.columns.len(), found: tuple.len() })
+    }
+    let mut row = vec![0u8; null_bitmap_size(schema.columns.len())];
+    for (index, (value, column)) in tuple.into_iter().zip(schema.columns.iter()).enumerate() {
+        if value == Value::Null {
+            set_null(&mut row, index);
+        }
+        let bytes = value.into_bytes(column)?;
+        row.extend_from_slice(&bytes);
+    }
+    Ok(row)
 }
 
 #[cfg(test)]
@@ -170,23 +1143,30 @@ mod tests {
     use super::*;
     use schema::ColumnMetadata;
     use schema::Schema;
+    use page::PAGE_SIZE;
+    use test_utils::temp_dir;
+    use std::fs;
+    use std::collections::BTreeMap;
+    use schema::Constraint;
+    use std::ops::Bound;
+
     #[test]
     fn insert_and_retrieve() {
         let col0 = ColumnMetadata::new_index("ID".into(), DbType::Integer);
         let col1 = ColumnMetadata::new("AGE".into(), DbType::Integer);
         let mut table: Table = Table::new(Schema {
             columns: Box::new([col0, col1])
-        });
+        }).unwrap();
 
         let tuple: Tuple = vec!(Value::Integer(1), Value::Integer(33));
-        let row = tuple_to_row(tuple).into_boxed_slice();
-        table.insert_row(row);
+        let row = tuple_to_row(tuple, &table.schema).unwrap().into_boxed_slice();
+        table.insert_row(row).unwrap();
 
 
 //        let age_of_id_1 = table.get_without_condition(&vec!("AGE".into()));
-        let age_of_id_1 = table.find_row(&Value::Integer(1)).unwrap();
-        assert_eq!(age_of_id_1, []);
-        println!("{:?}", age_of_id_1);
+        let age_of_id_1 = table.find_tuple(&Value::Integer(1)).unwrap().unwrap();
+        assert_eq!(age_of_id_1, vec!(Value::Integer(1), Value::Integer(33)));
+        assert_eq!(table.find_tuple(&Value::Integer(2)).unwrap(), None);
 
 //        let tuple: &Tuple = age_of_id_1.get(0).unwrap();
 //        let age: &Value = tuple.get(0).unwrap();
@@ -198,6 +1178,789 @@ mod tests {
 //        }
     }
 
+    fn id_age_schema() -> Schema {
+        let col0 = ColumnMetadata::new_index("ID".into(), DbType::Integer);
+        let col1 = ColumnMetadata::new("AGE".into(), DbType::Integer);
+        Schema {
+            columns: Box::new([col0, col1])
+        }
+    }
+
+    #[test]
+    fn rows_survive_reopening() {
+        let path = temp_dir("reopen").join("table");
+        {
+            let mut table = Table::create(&path, id_age_schema()).unwrap();
+            for id in 0..3000 {
+                table.insert_tuple(vec!(Value::Integer(id), Value::Integer(id % 90))).unwrap();
+            }
+            table.update_tuple(vec!(Value::Integer(7), Value::Integer(100))).unwrap();
+            table.delete_tuple(&Value::Integer(8)).unwrap();
+        }
+
+        let table = Table::open(&path).unwrap();
+        assert!(table.pager.num_pages() > 2, "The rows should span multiple pages");
+        assert_eq!(table.find_tuple(&Value::Integer(2999)).unwrap(), Some(vec!(Value::Integer(2999), Value::Integer(29))));
+        assert_eq!(table.find_tuple(&Value::Integer(7)).unwrap(), Some(vec!(Value::Integer(7), Value::Integer(100))));
+        assert_eq!(table.find_tuple(&Value::Integer(8)).unwrap(), None);
+        assert_eq!(table.primary_index.len(), 2999);
+    }
+
+    #[test]
+    fn deleted_space_is_reused() {
+        let mut table = Table::new(id_age_schema()).unwrap();
+        for id in 0..1000 {
+            table.insert_tuple(vec!(Value::Integer(id), Value::Integer(1))).unwrap();
+        }
+        let pages = table.pager.num_pages();
+        for id in 0..1000 {
+            table.delete_tuple(&Value::Integer(id)).unwrap();
+        }
+        for id in 1000..2000 {
+            table.insert_tuple(vec!(Value::Integer(id), Value::Integer(1))).unwrap();
+        }
+        assert_eq!(pages, table.pager.num_pages());
+    }
+
+    #[test]
+    fn opening_garbage_fails() {
+        let path = temp_dir("garbage").join("table");
+        ::std::fs::write(&path, vec!(1u8; PAGE_SIZE)).unwrap();
+        assert!(Table::open(&path).is_err());
+    }
+
+    #[test]
+    fn recovers_from_log_truncated_anywhere() {
+        let path = temp_dir("crash").join("table");
+        let mut state: BTreeMap<i32, Tuple> = BTreeMap::new();
+        // The state of the table after each mutation, along with the length of the log at that point.
+        let mut states: Vec<(u64, BTreeMap<i32, Tuple>)> = Vec::new();
+        {
+            let mut table = Table::create(&path, id_age_schema()).unwrap();
+            for id in 0..20 {
+                let tuple = vec!(Value::Integer(id), Value::Integer(id * 2));
+                table.insert_tuple(tuple.clone()).unwrap();
+                state.insert(id, tuple);
+            }
+            table.checkpoint().unwrap();
+            states.push((0, state.clone()));
+
+            for step in 0..30 {
+                let id = step % 25;
+                match step % 3 {
+                    0 => {
+                        let tuple = vec!(Value::Integer(id + 100), Value::Integer(step));
+                        table.insert_tuple(tuple.clone()).unwrap();
+                        state.insert(id + 100, tuple);
+                    }
+                    1 => {
+                        let tuple = vec!(Value::Integer(id), Value::Integer(-step));
+                        table.update_tuple(tuple.clone()).unwrap();
+                        state.insert(id, tuple);
+                    }
+                    _ => {
+                        table.delete_tuple(&Value::Integer(id)).unwrap();
+                        state.remove(&id);
+                    }
+                }
+                states.push((table.wal.len(), state.clone()));
+            }
+            // The table is dropped without a checkpoint, as if the process had died.
+        }
+
+        let table_bytes = fs::read(&path).unwrap();
+        let log_bytes = fs::read(log_path(&path)).unwrap();
+        assert_eq!(log_bytes.len() as u64, states.last().unwrap().0);
+
+        let crash_path = temp_dir("crash_cut").join("table");
+        for cut in 0..log_bytes.len() + 1 {
+            fs::write(&crash_path, &table_bytes).unwrap();
+            fs::write(log_path(&crash_path), &log_bytes[..cut]).unwrap();
+
+            let table = Table::open(&crash_path).unwrap();
+            let expected = &states.iter()
+                .rev()
+                .find(|&&(length, _)| length <= cut as u64)
+                .unwrap()
+                .1;
+            assert_eq!(table.primary_index.len(), expected.len(), "wrong number of rows with the log cut at {}", cut);
+            for (id, tuple) in expected {
+                assert_eq!(table.find_tuple(&Value::Integer(*id)).unwrap().as_ref(), Some(tuple), "wrong row with the log cut at {}", cut);
+            }
+            assert_eq!(table.wal.len(), 0, "recovery should checkpoint the table");
+        }
+    }
+
+    #[test]
+    fn bad_tuples_are_errors() {
+        let name = ColumnMetadata::new("NAME".into(), DbType::String { length: 4 });
+        let mut table = Table::new(Schema { columns: Box::new([ColumnMetadata::new_index("ID".into(), DbType::Integer), name]) }).unwrap();
+        match table.insert_tuple(vec!(Value::BigInt(1), Value::String("abc".into()))) {
+            Err(DbError::TypeMismatch { ref column, expected: DbType::Integer, .. }) if column == "ID" => {}
+            other => panic!("Expected a type mismatch, got {:?}", other)
+        }
+        match table.insert_tuple(vec!(Value::Integer(1), Value::Integer(2))) {
+            Err(DbError::TypeMismatch { ref column, .. }) if column == "NAME" => {}
+            other => panic!("Expected a type mismatch, got {:?}", other)
+        }
+        match table.insert_tuple(vec!(Value::Integer(1), Value::String("abcde".into()))) {
+            Err(DbError::StringTooLong { ref column, length: 5, max: 4 }) if column == "NAME" => {}
+            other => panic!("Expected the string to be too long, got {:?}", other)
+        }
+        match table.insert_tuple(vec!(Value::Integer(1), Value::String("ab\0".into()))) {
+            Err(DbError::InvalidValue { ref column, .. }) if column == "NAME" => {}
+            other => panic!("Expected a trailing null to be invalid, got {:?}", other)
+        }
+        match table.insert_tuple(vec!(Value::Integer(1), Value::String("abc".into()), Value::Integer(3))) {
+            Err(DbError::WrongNumberOfValues { expected: 2, found: 3 }) => {}
+            other => panic!("Expected the tuple to be too long, got {:?}", other)
+        }
+        match table.update_tuple(vec!(Value::Integer(1))) {
+            Err(DbError::WrongNumberOfValues { expected: 2, found: 1 }) => {}
+            other => panic!("Expected the tuple to be too short, got {:?}", other)
+        }
+        assert_eq!(table.scan(..).count(), 0);
+
+        let keyless = Schema { columns: Box::new([ColumnMetadata::new("AGE".into(), DbType::Integer)]) };
+        assert!(Table::new(keyless).is_err());
+    }
+
+    /// A bad tuple is turned away before anything is written, to the pages or to the log.
+    #[test]
+    fn bad_tuples_change_nothing() {
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("NAME".into(), DbType::String { length: 4 })
+            ])
+        };
+        let path = temp_dir("bad_tuples").join("table");
+        let mut table = Table::create(&path, schema).unwrap();
+        table.insert_tuple(vec!(Value::Integer(1), Value::String("ann".into()))).unwrap();
+        let rows: Vec<Tuple> = table.scan(..).collect::<DbResult<_>>().unwrap();
+        let log_length = table.wal.len();
+        let log = fs::read(log_path(&path)).unwrap();
+        let file = fs::read(&path).unwrap();
+
+        let bad_tuples = vec!(
+            vec!(Value::Integer(1)),
+            vec!(Value::Integer(1), Value::String("bob".into()), Value::Integer(3)),
+            vec!(Value::Integer(1), Value::Integer(2)),
+            vec!(Value::Integer(1), Value::String("annie".into()))
+        );
+        for tuple in bad_tuples {
+            assert!(table.insert_tuple(tuple.clone()).is_err(), "inserted {:?}", tuple);
+            assert!(table.update_tuple(tuple.clone()).is_err(), "updated to {:?}", tuple);
+            assert_eq!(table.scan(..).collect::<DbResult<Vec<Tuple>>>().unwrap(), rows);
+            assert_eq!(table.wal.len(), log_length);
+            assert_eq!(fs::read(log_path(&path)).unwrap(), log);
+            assert_eq!(fs::read(&path).unwrap(), file);
+        }
+    }
+
+    /// Rows written by this version of the format must stay readable, so the layout is pinned to a file.
+    #[test]
+    fn row_format_matches_golden_file() {
+        let golden: &[u8] = include_bytes!("../testdata/row_v2.bin");
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("BALANCE".into(), DbType::BigInt),
+                ColumnMetadata::new("NAME".into(), DbType::String { length: 8 })
+            ])
+        };
+        let tuple = vec!(Value::Integer(-2), Value::BigInt(0x0102_0304_0506_0708), Value::String("zé".into()));
+        let record = row_to_record(&tuple_to_row(tuple, &schema).unwrap());
+        assert_eq!(record, golden);
+
+        let decoded = schema.generate_general_row_to_tuple_fn()(&record_to_row(golden, 3).unwrap()).unwrap();
+        assert_eq!(&decoded[..2], &[Value::Integer(-2), Value::BigInt(0x0102_0304_0506_0708)]);
+        assert_eq!(decoded[2], Value::String("zé".into()));
+
+        // Rows written before there was a null bitmap are read as rows in which nothing is null.
+        let version_1: &[u8] = include_bytes!("../testdata/row_v1.bin");
+        assert_eq!(schema.generate_general_row_to_tuple_fn()(&record_to_row(version_1, 3).unwrap()).unwrap(), decoded);
+
+        let mut unknown_version = golden.to_vec();
+        unknown_version[0] = 99;
+        assert!(record_to_row(&unknown_version, 3).is_err());
+        let mut invalid_utf8 = golden.to_vec();
+        invalid_utf8[14] = 0xff;
+        assert!(schema.generate_general_row_to_tuple_fn()(&record_to_row(&invalid_utf8, 3).unwrap()).is_err());
+    }
+
+    /// A file from before rows had a null bitmap is still read, log and all, and is brought up to date when it is opened.
+    #[test]
+    fn files_from_before_null_bitmaps_are_read() {
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("NAME".into(), DbType::String { length: 8 })
+            ])
+        };
+        let path = temp_dir("version_2").join("table");
+        let person = |id: i32, name: &str| vec!(Value::Integer(id), Value::String(name.into()));
+        {
+            let mut table = Table::create(&path, schema.clone()).unwrap();
+            table.insert_tuple(person(1, "ann")).unwrap();
+            table.insert_tuple(person(2, "bob")).unwrap();
+            table.checkpoint().unwrap();
+        }
+        {
+            // Rewrite the file the way version 2 wrote it, with version 1 rows that have no null bitmap.
+            let mut pager = Pager::open(&path).unwrap();
+            let header = pager.page(0).unwrap().with_header_version(2);
+            *pager.page_mut(0).unwrap() = header;
+            for page_number in 1..pager.num_pages() {
+                let page = pager.page_mut(page_number).unwrap();
+                if page.page_type() != Some(PageType::Data) {
+                    continue
+                }
+                let records: Vec<(u16, Vec<u8>)> = page.records().map(|(slot, record)| (slot, record.to_vec())).collect();
+                for (slot, record) in records {
+                    let mut version_1 = vec!(1u8);
+                    version_1.extend_from_slice(&record[2..]);
+                    assert!(page.replace(slot, &version_1));
+                }
+            }
+            pager.flush().unwrap();
+            let (mut wal, _) = Wal::open(log_path(&path)).unwrap();
+            let row = tuple_to_row(person(3, "cy"), &schema).unwrap();
+            wal.append(&LogRecord::Insert(row[1..].to_vec().into_boxed_slice())).unwrap();
+        }
+
+        let expected = vec!(person(1, "ann"), person(2, "bob"), person(3, "cy"));
+        let table = Table::open(&path).unwrap();
+        assert_eq!(table.scan(..).collect::<DbResult<Vec<Tuple>>>().unwrap(), expected);
+        drop(table);
+        assert!(Pager::open(&path).unwrap().page(0).unwrap().header_version().unwrap() > 2);
+        let table = Table::open(&path).unwrap();
+        assert_eq!(table.scan(..).collect::<DbResult<Vec<Tuple>>>().unwrap(), expected);
+    }
+
+    /// However many of a checkpoint's writes reach the disk before a crash,
+    /// the table is put back together from the journal and the log when it is next opened.
+    #[test]
+    fn checkpoints_survive_crashes() {
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("BIO".into(), DbType::Text)
+            ])
+        };
+        let bio = |id: i32, long: bool| {
+            let text = format!("bio of {} ", id);
+            Value::String(if long { text.repeat(PAGE_SIZE / 3) } else { text })
+        };
+        let mut writes = 0;
+        loop {
+            let path = temp_dir("checkpoint_crash").join("table");
+            let mut table = Table::create(&path, schema.clone()).unwrap();
+            for id in 0..12 {
+                table.insert_tuple(vec!(Value::Integer(id), bio(id, id % 2 == 0))).unwrap();
+            }
+            table.checkpoint().unwrap();
+            // Free overflow pages and reuse them for other rows, all since the last checkpoint.
+            table.delete_tuple(&Value::Integer(0)).unwrap();
+            table.delete_tuple(&Value::Integer(2)).unwrap();
+            table.insert_tuple(vec!(Value::Integer(20), bio(20, true))).unwrap();
+            table.update_tuple(vec!(Value::Integer(1), bio(1, true))).unwrap();
+            table.update_tuple(vec!(Value::Integer(4), bio(4, false))).unwrap();
+            for id in 21..40 {
+                table.insert_tuple(vec!(Value::Integer(id), bio(id, false))).unwrap();
+            }
+            let expected: Vec<Tuple> = table.scan(..).collect::<DbResult<_>>().unwrap();
+
+            table.pager.crash_after_writes = Some(writes);
+            let finished = table.checkpoint().is_ok();
+            drop(table);
+            let table = Table::open(&path).unwrap_or_else(|e| panic!("could not open after {} writes: {}", writes, e));
+            assert_eq!(table.scan(..).collect::<DbResult<Vec<Tuple>>>().unwrap(), expected, "wrong rows after {} writes", writes);
+            if finished {
+                break
+            }
+            writes += 1;
+        }
+        assert!(writes > 5);
+    }
+
+    #[test]
+    fn text_takes_only_the_space_it_needs() {
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("BIO".into(), DbType::Text)
+            ])
+        };
+        let golden: &[u8] = include_bytes!("../testdata/row_v2_text.bin");
+        let row = tuple_to_row(vec!(Value::Integer(7), Value::String("hello".into())), &schema).unwrap();
+        assert_eq!(row_to_record(&row), golden);
+        let version_1: &[u8] = include_bytes!("../testdata/row_v1_text.bin");
+        assert_eq!(
+            schema.generate_general_row_to_tuple_fn()(&record_to_row(version_1, 2).unwrap()).unwrap(),
+            vec!(Value::Integer(7), Value::String("hello".into()))
+        );
+
+        let path = temp_dir("text").join("table");
+        let long = "long ".repeat(1000);
+        {
+            let mut table = Table::create(&path, schema).unwrap();
+            table.insert_tuple(vec!(Value::Integer(1), Value::String("".into()))).unwrap();
+            table.insert_tuple(vec!(Value::Integer(2), Value::String("ends with a nul\0".into()))).unwrap();
+            table.insert_tuple(vec!(Value::Integer(3), Value::String(long.clone()))).unwrap();
+            table.create_index("BIO").unwrap();
+        }
+        let table = Table::open(&path).unwrap();
+        assert_eq!(table.find_tuple(&Value::Integer(1)).unwrap().unwrap()[1], Value::String("".into()));
+        assert_eq!(table.find_tuple(&Value::Integer(2)).unwrap().unwrap()[1], Value::String("ends with a nul\0".into()));
+        assert_eq!(table.find_tuple(&Value::Integer(3)).unwrap().unwrap()[1], Value::String(long.clone()));
+        assert_eq!(table.find_tuples_by("BIO", &Value::String(long)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn nulls_are_stored_in_the_bitmap() {
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("NICKNAME".into(), DbType::Text).with_constraint(Constraint::Unique)
+            ])
+        };
+        let golden: &[u8] = include_bytes!("../testdata/row_v2_null.bin");
+        let row = tuple_to_row(vec!(Value::Integer(7), Value::Null), &schema).unwrap();
+        assert_eq!(row_to_record(&row), golden);
+
+        let mut table = Table::new(schema).unwrap();
+        // Left out values are null, and nulls don't clash with one another in a unique column.
+        let tuple = table.insert_partial_tuple(vec!(Some(Value::Integer(1)), None)).unwrap();
+        assert_eq!(tuple, vec!(Value::Integer(1), Value::Null));
+        table.insert_tuple(vec!(Value::Integer(2), Value::Null)).unwrap();
+        table.insert_tuple(vec!(Value::Integer(3), Value::String("trey".into()))).unwrap();
+        assert_eq!(table.find_tuple(&Value::Integer(2)).unwrap().unwrap(), vec!(Value::Integer(2), Value::Null));
+        assert_eq!(table.find_tuples_by("NICKNAME", &Value::Null).unwrap().len(), 2);
+
+        match table.insert_tuple(vec!(Value::Null, Value::Null)) {
+            Err(DbError::NotNullViolation { ref column }) if column == "ID" => {}
+            other => panic!("Expected a not null violation, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn numeric_and_boolean_columns() {
+        let decimal = |s: &str| Value::Decimal(s.parse().unwrap());
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("AMOUNT".into(), DbType::Decimal { precision: 6, scale: 2 }),
+                ColumnMetadata::new("IS_ACTIVE".into(), DbType::Boolean),
+                ColumnMetadata::new("RATE".into(), DbType::Double)
+            ])
+        };
+        let golden: &[u8] = include_bytes!("../testdata/row_v2_numeric.bin");
+        let row = tuple_to_row(vec!(decimal("-12.5"), Value::Boolean(true), Value::Double(Double(0.5))), &schema).unwrap();
+        assert_eq!(row_to_record(&row), golden);
+
+        let path = temp_dir("numeric").join("table");
+        {
+            let mut table = Table::create(&path, schema).unwrap();
+            table.insert_tuple(vec!(decimal("1000.01"), Value::Boolean(true), Value::Double(Double(-0.25)))).unwrap();
+            table.insert_tuple(vec!(Value::Integer(-3), Value::Boolean(false), Value::Double(Double(1e300)))).unwrap();
+            table.insert_tuple(vec!(decimal("0.1"), Value::Boolean(true), Value::Double(Double(::std::f64::NAN)))).unwrap();
+
+            for too_precise in &["0.001", "10000.00", "-10000"] {
+                match table.insert_tuple(vec!(decimal(too_precise), Value::Boolean(true), Value::Double(Double(0.0)))) {
+                    Err(DbError::NumericOverflow { ref column, .. }) if column == "AMOUNT" => {}
+                    other => panic!("Expected {} to overflow, got {:?}", too_precise, other)
+                }
+            }
+        }
+
+        let table = Table::open(&path).unwrap();
+        let amounts: Vec<Value> = table.scan(..).map(|tuple| tuple.unwrap()[0].clone()).collect();
+        assert_eq!(amounts, vec!(decimal("-3"), decimal("0.10"), decimal("1000.01")));
+        assert_eq!(
+            table.find_tuple(&decimal("-3.0")).unwrap().unwrap(),
+            vec!(decimal("-3.00"), Value::Boolean(false), Value::Double(Double(1e300)))
+        );
+        assert_eq!(table.find_tuples_by("IS_ACTIVE", &Value::Boolean(true)).unwrap().len(), 2);
+
+        let bad_decimal = Schema { columns: Box::new([ColumnMetadata::new_index("D".into(), DbType::Decimal { precision: 2, scale: 3 })]) };
+        assert!(Table::new(bad_decimal).is_err());
+    }
+
+    #[test]
+    fn temporal_columns() {
+        let string = |s: &str| Value::String(s.into());
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("AT".into(), DbType::Timestamp),
+                ColumnMetadata::new("ON".into(), DbType::Date),
+                ColumnMetadata::new("OPENS".into(), DbType::Time)
+            ])
+        };
+        let golden: &[u8] = include_bytes!("../testdata/row_v2_temporal.bin");
+        let tuple = schema.coerce_tuple(vec!(string("2024-02-29T12:30:00Z"), string("2024-02-29"), string("12:30:00.5"))).unwrap();
+        assert_eq!(row_to_record(&tuple_to_row(tuple, &schema).unwrap()), golden);
+
+        let mut table = Table::new(schema).unwrap();
+        for hour in 0..24 {
+            let at = format!("2024-01-01T{:02}:00:00+01:00", hour);
+            table.insert_tuple(vec!(string(&at), string("2024-01-01"), string("09:00:00"))).unwrap();
+        }
+        match table.insert_tuple(vec!(string("2024-01-01"), string("2024-01-01"), string("09:00:00"))) {
+            Err(DbError::InvalidValue { ref column, .. }) if column == "AT" => {}
+            other => panic!("Expected the timestamp to be rejected, got {:?}", other)
+        }
+        match table.insert_tuple(vec!(string("2024-01-01T00:00:00Z"), string("2024-01-01"), Value::Time(Time(-1)))) {
+            Err(DbError::InvalidValue { ref column, .. }) if column == "OPENS" => {}
+            other => panic!("Expected the time to be rejected, got {:?}", other)
+        }
+
+        // The offset moves the first hours back into the previous day.
+        let start = Value::Timestamp("2024-01-01T00:00:00Z".parse().unwrap());
+        let on_new_years_day: Vec<String> = table.scan(start..)
+            .map(|tuple| match tuple.unwrap()[0] {
+                Value::Timestamp(at) => at.to_string(),
+                ref other => panic!("Expected a timestamp, got {:?}", other)
+            })
+            .collect();
+        assert_eq!(on_new_years_day.len(), 23);
+        assert_eq!(on_new_years_day[0], "2024-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn uuid_and_bytes_columns() {
+        let uuid = |s: &str| Value::Uuid(s.parse().unwrap());
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Uuid),
+                ColumnMetadata::new("DATA".into(), DbType::Bytes)
+            ])
+        };
+        let golden: &[u8] = include_bytes!("../testdata/row_v2_binary.bin");
+        let tuple = schema.coerce_tuple(vec!(Value::String("123e4567-e89b-12d3-a456-426614174000".into()), Value::Bytes(vec!(0, 1, 0xff)))).unwrap();
+        assert_eq!(row_to_record(&tuple_to_row(tuple, &schema).unwrap()), golden);
+
+        let path = temp_dir("binary").join("table");
+        {
+            let mut table = Table::create(&path, schema).unwrap();
+            table.insert_tuple(vec!(uuid("ffffffff-0000-0000-0000-000000000000"), Value::Bytes(vec!()))).unwrap();
+            table.insert_tuple(vec!(uuid("00000000-0000-0000-0000-000000000001"), Value::Bytes(vec!(0; 4000)))).unwrap();
+            match table.insert_tuple(vec!(Value::String("not a uuid".into()), Value::Null)) {
+                Err(DbError::InvalidValue { ref column, .. }) if column == "ID" => {}
+                other => panic!("Expected the uuid to be rejected, got {:?}", other)
+            }
+        }
+        let table = Table::open(&path).unwrap();
+        let keys: Vec<Value> = table.scan(..).map(|tuple| tuple.unwrap()[0].clone()).collect();
+        assert_eq!(keys, vec!(uuid("00000000-0000-0000-0000-000000000001"), uuid("ffffffff-0000-0000-0000-000000000000")));
+        assert_eq!(table.find_tuple(&uuid("00000000-0000-0000-0000-000000000001")).unwrap().unwrap()[1], Value::Bytes(vec!(0; 4000)));
+        assert_eq!(table.find_tuple(&uuid("ffffffff-0000-0000-0000-000000000000")).unwrap().unwrap()[1], Value::Bytes(vec!()));
+    }
+
+    #[test]
+    fn large_rows_overflow_into_chained_pages() {
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("BODY".into(), DbType::Text)
+            ])
+        };
+        let body = |id: i32, length: usize| -> String {
+            (0..length).map(|i| (b'a' + ((i + id as usize) % 26) as u8) as char).collect()
+        };
+        let path = temp_dir("overflow").join("table");
+        {
+            let mut table = Table::create(&path, schema).unwrap();
+            for id in 0..4 {
+                table.insert_tuple(vec!(Value::Integer(id), Value::String(body(id, PAGE_SIZE * 3)))).unwrap();
+            }
+            table.create_index("BODY").unwrap();
+        }
+        let mut table = Table::open(&path).unwrap();
+        for id in 0..4 {
+            assert_eq!(table.find_tuple(&Value::Integer(id)).unwrap().unwrap()[1], Value::String(body(id, PAGE_SIZE * 3)));
+        }
+        assert_eq!(table.find_tuples_by("BODY", &Value::String(body(2, PAGE_SIZE * 3))).unwrap().len(), 1);
+
+        // Shrinking a row back into its page, and deleting one, frees their chains for new rows to use.
+        let pages = table.pager.num_pages();
+        table.update_tuple(vec!(Value::Integer(0), Value::String("short".into()))).unwrap();
+        table.delete_tuple(&Value::Integer(1)).unwrap();
+        table.insert_tuple(vec!(Value::Integer(4), Value::String(body(4, PAGE_SIZE * 6)))).unwrap();
+        table.update_tuple(vec!(Value::Integer(2), Value::String(body(5, PAGE_SIZE * 2)))).unwrap();
+        assert_eq!(table.pager.num_pages(), pages);
+
+        let bodies: Vec<Value> = table.scan(..).map(|tuple| tuple.unwrap()[1].clone()).collect();
+        assert_eq!(bodies, vec!(
+            Value::String("short".into()),
+            Value::String(body(5, PAGE_SIZE * 2)),
+            Value::String(body(3, PAGE_SIZE * 3)),
+            Value::String(body(4, PAGE_SIZE * 6))
+        ));
+        assert!(table.find_tuples_by("BODY", &Value::String(body(1, PAGE_SIZE * 3))).unwrap().is_empty());
+    }
+
+    #[test]
+    fn projection_reads_only_the_named_columns() {
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("NAME".into(), DbType::Text),
+                ColumnMetadata::new("SCORE".into(), DbType::Double),
+                ColumnMetadata::new("NOTE".into(), DbType::String { length: 8 })
+            ])
+        };
+        let mut table = Table::new(schema).unwrap();
+        for id in 0..5 {
+            table.insert_tuple(vec!(
+                Value::Integer(id),
+                Value::String(format!("player {}", id)),
+                Value::Double(Double(f64::from(id) / 2.0)),
+                if id % 2 == 0 { Value::Null } else { Value::String("odd".into()) }
+            )).unwrap();
+        }
+
+        assert_eq!(
+            table.find_tuple_columns(&Value::Integer(3), &["NOTE", "ID", "NOTE"]).unwrap(),
+            Some(vec!(Value::String("odd".into()), Value::Integer(3), Value::String("odd".into())))
+        );
+        assert_eq!(table.find_tuple_columns(&Value::Integer(9), &["ID"]).unwrap(), None);
+        match table.find_tuple_columns(&Value::Integer(3), &["ID", "RANK"]) {
+            Err(DbError::UnknownColumn(ref column)) if column == "RANK" => {}
+            other => panic!("Expected an unknown column, got {:?}", other)
+        }
+
+        let scores: Vec<Tuple> = table.scan_columns(Value::Integer(2).., &["SCORE", "NAME"])
+            .unwrap()
+            .rev()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(scores, vec!(
+            vec!(Value::Double(Double(2.0)), Value::String("player 4".into())),
+            vec!(Value::Double(Double(1.5)), Value::String("player 3".into())),
+            vec!(Value::Double(Double(1.0)), Value::String("player 2".into()))
+        ));
+        assert_eq!(table.scan_columns(.., &[]).unwrap().map(Result::unwrap).collect::<Vec<Tuple>>(), vec!(Vec::new(); 5));
+    }
+
+    #[test]
+    fn bulk_updates_and_deletes() {
+        let schema = Schema {
+            columns: Box::new([
+                ColumnMetadata::new_index("ID".into(), DbType::Integer),
+                ColumnMetadata::new("BALANCE".into(), DbType::BigInt),
+                ColumnMetadata::new("STATUS".into(), DbType::Text),
+                ColumnMetadata::new("HANDLE".into(), DbType::Text).with_constraint(Constraint::Unique)
+            ])
+        };
+        let path = temp_dir("bulk").join("table");
+        let mut table = Table::create(&path, schema).unwrap();
+        for id in 0..20 {
+            let status = if id % 3 == 0 { "new" } else { "open" };
+            table.insert_tuple(vec!(Value::Integer(id), Value::BigInt(i64::from(id) - 5), Value::String(status.into()), Value::Null)).unwrap();
+        }
+        table.create_index("BALANCE").unwrap();
+        let statuses = |table: &Table, status: &str| table
+            .find_tuples_where(&Expression::column("STATUS").equal(Value::String(status.into())))
+            .unwrap()
+            .len();
+
+        // Through the secondary index, with an integer literal compared with a big integer column.
+        let overdrawn = Expression::column("BALANCE").less(Value::Integer(0));
+        assert_eq!(table.update_where(&overdrawn, &[("STATUS", Value::String("frozen".into()))]).unwrap(), 5);
+        assert_eq!(statuses(&table, "frozen"), 5);
+        // Through the primary index.
+        let middle = Expression::column("ID").greater_or_equal(Value::Integer(5)).and(Expression::column("ID").less(Value::Integer(8)));
+        assert_eq!(table.update_where(&middle, &[("BALANCE", Value::Integer(100))]).unwrap(), 3);
+        assert_eq!(table.find_tuples_by("BALANCE", &Value::BigInt(100)).unwrap().len(), 3);
+        // Through every row.
+        assert_eq!(table.delete_where(&Expression::column("STATUS").equal(Value::String("new".into()))).unwrap(), 5);
+        assert_eq!(table.delete_where(&Expression::column("STATUS").equal(Value::String("new".into()))).unwrap(), 0);
+
+        // A failed update changes nothing.
+        match table.update_where(&Expression::column("ID").greater(Value::Integer(15)), &[("HANDLE", Value::String("taken".into()))]) {
+            Err(DbError::UniqueViolation { ref column, .. }) if column == "HANDLE" => {}
+            other => panic!("Expected a unique violation, got {:?}", other)
+        }
+        assert!(table.update_where(&overdrawn, &[("ID", Value::Integer(0))]).is_err());
+        assert!(table.update_where(&overdrawn, &[("BALANCE", Value::String("lots".into()))]).is_err());
+        assert_eq!(table.update_where(&Expression::column("ID").equal(Value::Integer(19)), &[("HANDLE", Value::String("taken".into()))]).unwrap(), 1);
+        assert_eq!(table.find_tuples_by("HANDLE", &Value::String("taken".into())).unwrap().len(), 1);
+        drop(table);
 
+        let table = Table::open(&path).unwrap();
+        assert_eq!(table.scan(..).count(), 15);
+        assert_eq!(statuses(&table, "frozen"), 5);
+        assert_eq!(statuses(&table, "open"), 10);
+    }
+
+    #[test]
+    fn scan_ranges() {
+        let mut table = Table::new(id_age_schema()).unwrap();
+        // Insert out of order, the scan should still come back sorted.
+        for id in (0..300).rev() {
+            table.insert_tuple(vec!(Value::Integer(id), Value::Integer(id % 50))).unwrap();
+        }
+        let ids = |scan: Scan| -> Vec<i32> {
+            scan.map(|tuple| match tuple.unwrap()[0] {
+                Value::Integer(id) => id,
+                _ => panic!("ID should be an integer")
+            }).collect()
+        };
+
+        assert_eq!(ids(table.scan(Value::Integer(100)..=Value::Integer(200))), (100..201).collect::<Vec<i32>>());
+        assert_eq!(ids(table.scan(Value::Integer(100)..Value::Integer(200))), (100..200).collect::<Vec<i32>>());
+        assert_eq!(ids(table.scan((Bound::Excluded(Value::Integer(289)), Bound::Unbounded))), (290..300).collect::<Vec<i32>>());
+        assert_eq!(ids(table.scan(..Value::Integer(3))), vec!(0, 1, 2));
+        assert_eq!(table.scan(..).count(), 300);
+        assert_eq!(table.scan(Value::Integer(200)..Value::Integer(100)).count(), 0);
+
+        let descending: Vec<Tuple> = table.scan(Value::Integer(10)..Value::Integer(13)).rev().collect::<DbResult<_>>().unwrap();
+        assert_eq!(descending, vec!(
+            vec!(Value::Integer(12), Value::Integer(12)),
+            vec!(Value::Integer(11), Value::Integer(11)),
+            vec!(Value::Integer(10), Value::Integer(10)),
+        ));
+    }
+
+    #[test]
+    fn secondary_index_lookups() {
+        let path = temp_dir("secondary").join("table");
+        let ids = |tuples: Vec<Tuple>| -> Vec<Value> {
+            tuples.into_iter().map(|tuple| tuple[0].clone()).collect()
+        };
+        {
+            let mut table = Table::create(&path, id_age_schema()).unwrap();
+            for id in 0..50 {
+                table.insert_tuple(vec!(Value::Integer(id), Value::Integer(id % 10))).unwrap();
+            }
+            table.create_index("AGE").unwrap();
+            assert!(table.create_index("AGE").is_err());
+            assert!(table.create_index("NOT_A_COLUMN").is_err());
+
+            assert_eq!(
+                ids(table.find_tuples_by("AGE", &Value::Integer(3)).unwrap()),
+                vec!(Value::Integer(3), Value::Integer(13), Value::Integer(23), Value::Integer(33), Value::Integer(43))
+            );
+
+            // The index follows inserts, updates and deletes.
+            table.insert_tuple(vec!(Value::Integer(100), Value::Integer(3))).unwrap();
+            table.update_tuple(vec!(Value::Integer(13), Value::Integer(4))).unwrap();
+            table.delete_tuple(&Value::Integer(23)).unwrap();
+            assert_eq!(
+                ids(table.find_tuples_by("AGE", &Value::Integer(3)).unwrap()),
+                vec!(Value::Integer(3), Value::Integer(33), Value::Integer(43), Value::Integer(100))
+            );
+        }
 
+        // The index is rebuilt when the table is reopened.
+        let table = Table::open(&path).unwrap();
+        assert_eq!(table.find_tuples_by("AGE", &Value::Integer(4)).unwrap().len(), 6);
+        let ages: Vec<Value> = table.index_scan("AGE", Value::Integer(8)..)
+            .unwrap()
+            .map(|tuple| tuple.unwrap()[1].clone())
+            .collect();
+        assert_eq!(ages, vec!(Value::Integer(8); 5).into_iter().chain(vec!(Value::Integer(9); 5)).collect::<Vec<Value>>());
+        let ages_over_8 = table.index_scan("AGE", (Bound::Excluded(Value::Integer(8)), Bound::Unbounded)).unwrap().count();
+        assert_eq!(ages_over_8, 5);
+        assert!(table.index_scan("ID", ..).is_err());
+
+        let young = Expression::column("AGE").equal(Value::Integer(4)).and(Expression::column("ID").less(Value::Integer(20)));
+        assert_eq!(ids(table.find_tuples_where(&young).unwrap()), vec!(Value::Integer(4), Value::Integer(13), Value::Integer(14)));
+        assert!(table.find_tuples_where(&Expression::column("AGE")).is_err());
+    }
+
+    #[test]
+    fn composite_keys() {
+        // Ledger entries, keyed by account and then sequence number.
+        let account = ColumnMetadata::new_index("ACCOUNT".into(), DbType::BigInt);
+        let amount = ColumnMetadata::new("AMOUNT".into(), DbType::Integer);
+        let sequence = ColumnMetadata::new_index("SEQUENCE".into(), DbType::Integer);
+        let path = temp_dir("composite").join("table");
+        {
+            let mut table = Table::create(&path, Schema { columns: Box::new([account, amount, sequence]) }).unwrap();
+            for account in (1..4).rev() {
+                for sequence in 0..100 {
+                    table.insert_tuple(vec!(Value::BigInt(account), Value::Integer(sequence * 10), Value::Integer(sequence))).unwrap();
+                }
+            }
+        }
+        let mut table = Table::open(&path).unwrap();
+        let key = |account: i64, sequence: i32| Key(vec!(Value::BigInt(account), Value::Integer(sequence)));
+
+        assert_eq!(table.find_tuple(key(2, 5)).unwrap(), Some(vec!(Value::BigInt(2), Value::Integer(50), Value::Integer(5))));
+        table.delete_tuple(key(2, 5)).unwrap();
+        assert_eq!(table.find_tuple(key(2, 5)).unwrap(), None);
+        assert_eq!(table.primary_index.len(), 299);
+
+        // Scanning on the leading column gets every entry for the account, in sequence order.
+        let sequences: Vec<Value> = table.scan(Value::BigInt(3)..=Value::BigInt(3)).map(|tuple| tuple.unwrap()[2].clone()).collect();
+        assert_eq!(sequences, (0..100).map(Value::Integer).collect::<Vec<Value>>());
+        assert_eq!(table.scan(Value::BigInt(2)..).count(), 199);
+        assert_eq!(table.scan(..Value::BigInt(2)).rev().next().map(Result::unwrap), Some(vec!(Value::BigInt(1), Value::Integer(990), Value::Integer(99))));
+        assert_eq!(table.find_tuples_by("ACCOUNT", &Value::BigInt(2)).unwrap().len(), 99);
+
+        // Ranges over the whole key are ordered column by column.
+        let first_of_account_2 = Key(vec!(Value::BigInt(2)));
+        assert_eq!(table.scan_keys(first_of_account_2..key(2, 3)).count(), 3);
+        assert_eq!(table.scan_keys(key(1, 98)..=key(2, 1)).count(), 4);
+    }
+
+    #[test]
+    fn constraints_are_enforced() {
+        let id = ColumnMetadata::new_index("ID".into(), DbType::Integer).with_constraint(Constraint::Serial);
+        let email = ColumnMetadata::new("EMAIL".into(), DbType::String { length: 20 })
+            .with_constraint(Constraint::Unique)
+            .with_constraint(Constraint::NotNull);
+        let age = ColumnMetadata::new("AGE".into(), DbType::Integer);
+        let path = temp_dir("constraints").join("table");
+        let email_value = |s: &str| Some(Value::String(s.into()));
+        {
+            let mut table = Table::create(&path, Schema { columns: Box::new([id, email, age]) }).unwrap();
+
+            let first = table.insert_partial_tuple(vec!(None, email_value("a@example.com"), Some(Value::Integer(30)))).unwrap();
+            assert_eq!(first[0], Value::Integer(1));
+            let second = table.insert_partial_tuple(vec!(None, email_value("b@example.com"), Some(Value::Integer(30)))).unwrap();
+            assert_eq!(second[0], Value::Integer(2));
+
+            // Explicit serial values move the sequence along.
+            table.insert_partial_tuple(vec!(Some(Value::Integer(10)), email_value("c@example.com"), Some(Value::Integer(30)))).unwrap();
+            let fourth = table.insert_partial_tuple(vec!(None, email_value("d@example.com"), Some(Value::Integer(30)))).unwrap();
+            assert_eq!(fourth[0], Value::Integer(11));
+
+            match table.insert_partial_tuple(vec!(None, email_value("a@example.com"), Some(Value::Integer(1)))) {
+                Err(DbError::UniqueViolation { ref column, .. }) if column == "EMAIL" => {}
+                other => panic!("Expected a unique violation, got {:?}", other)
+            }
+            match table.insert_partial_tuple(vec!(None, None, Some(Value::Integer(1)))) {
+                Err(DbError::NotNullViolation { ref column }) if column == "EMAIL" => {}
+                other => panic!("Expected a not null violation, got {:?}", other)
+            }
+            match table.insert_partial_tuple(vec!(None, Some(Value::Null), Some(Value::Integer(1)))) {
+                Err(DbError::NotNullViolation { ref column }) if column == "EMAIL" => {}
+                other => panic!("Expected a not null violation, got {:?}", other)
+            }
+            match table.update_tuple(vec!(Value::Integer(2), email_value("d@example.com").unwrap(), Value::Integer(1))) {
+                Err(DbError::UniqueViolation { .. }) => {}
+                other => panic!("Expected a unique violation, got {:?}", other)
+            }
+            // A row may keep its own value.
+            table.update_tuple(vec!(Value::Integer(2), email_value("b@example.com").unwrap(), Value::Integer(31))).unwrap();
+            assert!(table.drop_index("EMAIL").is_err());
+
+            table.delete_tuple(&Value::Integer(11)).unwrap();
+            table.checkpoint().unwrap();
+        }
+
+        // The sequence isn't rewound by deleting the newest row, even across reopening.
+        let mut table = Table::open(&path).unwrap();
+        let next = table.insert_partial_tuple(vec!(None, email_value("f@example.com"), Some(Value::Integer(30)))).unwrap();
+        assert_eq!(next[0], Value::Integer(12));
+        assert!(table.insert_tuple(vec!(Value::Integer(20), email_value("c@example.com").unwrap(), Value::Integer(1))).is_err());
+
+        let serial_text = ColumnMetadata::new("CODE".into(), DbType::Text).with_constraint(Constraint::Serial);
+        match Table::new(Schema { columns: Box::new([ColumnMetadata::new_index("ID".into(), DbType::Integer), serial_text]) }) {
+            Err(DbError::InvalidColumnType { ref column, .. }) if column == "CODE" => {}
+            other => panic!("Expected a serial text column to be rejected, got {:?}", other.map(|_| ()))
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,287 @@
+//! Dates and times, and their ISO-8601 representations.
+//!
+//! Each is a count of some unit from a fixed starting point,
+//! so they order the same way as the moments they represent.
+
+use std::fmt;
+use std::str::FromStr;
+
+const MICROSECONDS_PER_SECOND: i64 = 1_000_000;
+const MICROSECONDS_PER_DAY: i64 = 86_400 * MICROSECONDS_PER_SECOND;
+
+/// A calendar date, counted in days since 1970-01-01.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Date(pub i32);
+
+/// A time of day, counted in microseconds since midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Time(pub i64);
+
+/// A moment in UTC, counted in microseconds since 1970-01-01T00:00:00Z.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Timestamp(pub i64);
+
+/// The string wasn't in the ISO-8601 format expected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseTemporalError;
+
+impl Date {
+    /// Creates a date from its year, month (1 to 12) and day of the month,
+    /// returning `None` if there is no such day.
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Option<Date> {
+        if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+            return None
+        }
+        Some(Date(days_from_civil(i64::from(year), month, day) as i32))
+    }
+
+    /// The year, month and day of the month.
+    pub fn ymd(&self) -> (i32, u32, u32) {
+        civil_from_days(i64::from(self.0))
+    }
+}
+
+impl Time {
+    /// Creates a time of day, returning `None` if any part is out of range.
+    pub fn from_hms_micro(hour: u32, minute: u32, second: u32, microsecond: u32) -> Option<Time> {
+        if hour > 23 || minute > 59 || second > 59 || i64::from(microsecond) >= MICROSECONDS_PER_SECOND {
+            return None
+        }
+        let seconds = i64::from(hour) * 3600 + i64::from(minute) * 60 + i64::from(second);
+        Some(Time(seconds * MICROSECONDS_PER_SECOND + i64::from(microsecond)))
+    }
+
+    /// Whether the time is within a day.
+    pub fn is_valid(&self) -> bool {
+        self.0 >= 0 && self.0 < MICROSECONDS_PER_DAY
+    }
+}
+
+impl Timestamp {
+    pub fn from_date_time(date: Date, time: Time) -> Timestamp {
+        Timestamp(i64::from(date.0) * MICROSECONDS_PER_DAY + time.0)
+    }
+
+    pub fn date(&self) -> Date {
+        Date(self.0.div_euclid(MICROSECONDS_PER_DAY) as i32)
+    }
+
+    pub fn time(&self) -> Time {
+        Time(self.0.rem_euclid(MICROSECONDS_PER_DAY))
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+/// The number of days from 1970-01-01 to the date, in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as i32, month, day)
+}
+
+/// Parses a fixed number of decimal digits.
+fn parse_digits(s: &str) -> Result<u32, ParseTemporalError> {
+    if s.is_empty() || !s.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(ParseTemporalError)
+    }
+    s.parse().map_err(|_| ParseTemporalError)
+}
+
+/// Splits off the first `count` bytes of the string, if they are all there.
+fn split(s: &str, count: usize) -> Result<(&str, &str), ParseTemporalError> {
+    if s.len() < count || !s.is_char_boundary(count) {
+        return Err(ParseTemporalError)
+    }
+    Ok(s.split_at(count))
+}
+
+/// Expects the string to start with the separator, and returns what follows it.
+fn expect(s: &str, separator: char) -> Result<&str, ParseTemporalError> {
+    if s.starts_with(separator) {
+        Ok(&s[separator.len_utf8()..])
+    } else {
+        Err(ParseTemporalError)
+    }
+}
+
+/// Parses `YYYY-MM-DD` from the front of the string, returning the rest.
+fn parse_date(s: &str) -> Result<(Date, &str), ParseTemporalError> {
+    let (year, rest) = split(s, 4)?;
+    let (month, rest) = split(expect(rest, '-')?, 2)?;
+    let (day, rest) = split(expect(rest, '-')?, 2)?;
+    let date = Date::from_ymd(parse_digits(year)? as i32, parse_digits(month)?, parse_digits(day)?)
+        .ok_or(ParseTemporalError)?;
+    Ok((date, rest))
+}
+
+/// Parses `HH:MM:SS` with an optional fraction of up to six digits from the front of the string, returning the rest.
+fn parse_time(s: &str) -> Result<(Time, &str), ParseTemporalError> {
+    let (hour, rest) = split(s, 2)?;
+    let (minute, rest) = split(expect(rest, ':')?, 2)?;
+    let (second, mut rest) = split(expect(rest, ':')?, 2)?;
+    let mut microsecond = 0;
+    if rest.starts_with('.') {
+        let digits = rest[1..].bytes().take_while(|byte| byte.is_ascii_digit()).count();
+        if digits == 0 || digits > 6 {
+            return Err(ParseTemporalError)
+        }
+        let (fraction, remainder) = rest[1..].split_at(digits);
+        microsecond = parse_digits(fraction)? * 10u32.pow(6 - digits as u32);
+        rest = remainder;
+    }
+    let time = Time::from_hms_micro(parse_digits(hour)?, parse_digits(minute)?, parse_digits(second)?, microsecond)
+        .ok_or(ParseTemporalError)?;
+    Ok((time, rest))
+}
+
+impl FromStr for Date {
+    type Err = ParseTemporalError;
+
+    /// Parses `YYYY-MM-DD`.
+    fn from_str(s: &str) -> Result<Date, ParseTemporalError> {
+        match parse_date(s)? {
+            (date, "") => Ok(date),
+            _ => Err(ParseTemporalError)
+        }
+    }
+}
+
+impl FromStr for Time {
+    type Err = ParseTemporalError;
+
+    /// Parses `HH:MM:SS`, optionally followed by a fraction of a second of up to six digits.
+    fn from_str(s: &str) -> Result<Time, ParseTemporalError> {
+        match parse_time(s)? {
+            (time, "") => Ok(time),
+            _ => Err(ParseTemporalError)
+        }
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = ParseTemporalError;
+
+    /// Parses a date and time separated by `T` (or a space), followed by `Z` or an offset from UTC like `+05:30`.
+    /// The timestamp is converted to UTC.
+    fn from_str(s: &str) -> Result<Timestamp, ParseTemporalError> {
+        let (date, rest) = parse_date(s)?;
+        let rest = expect(rest, 'T').or_else(|_| expect(rest, ' '))?;
+        let (time, rest) = parse_time(rest)?;
+        let offset_minutes = match rest {
+            "Z" | "z" => 0,
+            _ => {
+                let (sign, rest) = split(rest, 1)?;
+                let sign = match sign {
+                    "+" => 1,
+                    "-" => -1,
+                    _ => return Err(ParseTemporalError)
+                };
+                let (hours, rest) = split(rest, 2)?;
+                let minutes = expect(rest, ':')?;
+                if minutes.len() != 2 {
+                    return Err(ParseTemporalError)
+                }
+                let (hours, minutes) = (parse_digits(hours)?, parse_digits(minutes)?);
+                if hours > 23 || minutes > 59 {
+                    return Err(ParseTemporalError)
+                }
+                sign * i64::from(hours * 60 + minutes)
+            }
+        };
+        let local = Timestamp::from_date_time(date, time);
+        Ok(Timestamp(local.0 - offset_minutes * 60 * MICROSECONDS_PER_SECOND))
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl fmt::Display for Time {
+    /// Writes `HH:MM:SS`, followed by six digits of fraction if the time isn't a whole second.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.0 / MICROSECONDS_PER_SECOND;
+        let microseconds = self.0 % MICROSECONDS_PER_SECOND;
+        write!(f, "{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)?;
+        if microseconds != 0 {
+            write!(f, ".{:06}", microseconds)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}T{}Z", self.date(), self.time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_match_the_calendar() {
+        assert_eq!("1970-01-01".parse(), Ok(Date(0)));
+        assert_eq!("2000-03-01".parse(), Ok(Date(11_017)));
+        assert_eq!("1969-12-31".parse(), Ok(Date(-1)));
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("2024-13-01".parse::<Date>().is_err());
+        assert!("2024-1-01".parse::<Date>().is_err());
+        // Every day over a few centuries survives the round trip, in order.
+        for days in -80_000..80_000 {
+            let date = Date(days);
+            assert_eq!(date.to_string().parse(), Ok(date));
+        }
+    }
+
+    #[test]
+    fn times_and_timestamps_round_trip() {
+        assert_eq!("00:00:00".parse(), Ok(Time(0)));
+        assert_eq!("23:59:59.5".parse(), Ok(Time(86_399_500_000)));
+        assert!("24:00:00".parse::<Time>().is_err());
+        assert!("12:00:00.1234567".parse::<Time>().is_err());
+        assert_eq!(Time(86_399_500_000).to_string(), "23:59:59.500000");
+
+        let timestamp: Timestamp = "2024-02-29T12:30:00.000001Z".parse().unwrap();
+        assert_eq!(timestamp.to_string(), "2024-02-29T12:30:00.000001Z");
+        assert_eq!("2024-02-29T18:00:00.000001+05:30".parse(), Ok(timestamp));
+        assert_eq!("2024-02-29 07:30:00.000001-05:00".parse(), Ok(timestamp));
+        assert_eq!("1969-12-31T23:59:59Z".parse(), Ok(Timestamp(-1_000_000)));
+        assert_eq!(Timestamp(-1).to_string(), "1969-12-31T23:59:59.999999Z");
+        assert!("2024-02-29T12:30:00".parse::<Timestamp>().is_err());
+    }
+}
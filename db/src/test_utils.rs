@@ -0,0 +1,16 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates a fresh, empty directory for a test to put its files in.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let unique = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let dir = env::temp_dir().join(format!("zeppelin_db_{}_{}_{}", name, process::id(), unique));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("Could not create a temporary directory");
+    dir
+}
@@ -0,0 +1,66 @@
+//! Universally unique identifiers.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A 128 bit identifier, written as 32 hexadecimal digits in groups of 8, 4, 4, 4 and 12,
+/// like `123e4567-e89b-12d3-a456-426614174000`.
+///
+/// UUIDs are ordered by their bytes, which is the same as ordering their written form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Uuid(pub [u8; 16]);
+
+/// The string wasn't a UUID in its hyphenated form.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseUuidError;
+
+impl FromStr for Uuid {
+    type Err = ParseUuidError;
+
+    fn from_str(s: &str) -> Result<Uuid, ParseUuidError> {
+        let groups: Vec<&str> = s.split('-').collect();
+        let group_lengths: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+        if group_lengths != [8, 4, 4, 4, 12] {
+            return Err(ParseUuidError)
+        }
+        let digits: String = groups.concat();
+        if !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(ParseUuidError)
+        }
+        let mut bytes = [0u8; 16];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).map_err(|_| ParseUuidError)?;
+        }
+        Ok(Uuid(bytes))
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if index == 4 || index == 6 || index == 8 || index == 10 {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_strings() {
+        let written = "123e4567-e89b-12d3-a456-426614174000";
+        let uuid: Uuid = written.parse().unwrap();
+        assert_eq!(uuid.0[0], 0x12);
+        assert_eq!(uuid.0[15], 0x00);
+        assert_eq!(uuid.to_string(), written);
+        assert_eq!("123E4567-E89B-12D3-A456-426614174000".parse(), Ok(uuid));
+        assert!("123e4567e89b12d3a456426614174000".parse::<Uuid>().is_err());
+        assert!("123e4567-e89b-12d3-a456-42661417400g".parse::<Uuid>().is_err());
+        assert!("+23e4567-e89b-12d3-a456-426614174000".parse::<Uuid>().is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! The write-ahead log.
+//!
+//! Every mutation of a table is appended to its log, and the log is synced to disk,
+//! before the mutation is applied to the table's pages.
+//! Pages are only written back to the table's file when the table is checkpointed,
+//! after which the log is emptied.
+//! If the process dies between checkpoints, the table's file still holds the state of the last checkpoint,
+//! and replaying the log on top of it restores every mutation that was acknowledged.
+//!
+//! Each entry in the log is framed as:
+//!
+//! ```text
+//! +----------------+------------------+------+---------+
+//! | length (u32)   | checksum (u32)   | kind | row ... |
+//! +----------------+------------------+------+---------+
+//! ```
+//!
+//! `length` counts the kind byte and the row, and `checksum` is the FNV-1a hash of those same bytes.
+//! Both integers are little-endian.
+//! Reading stops at the first entry that is incomplete or whose checksum doesn't match,
+//! because that is where a crash interrupted the log.
+
+use row::BoxedRow;
+use error::DbResult;
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// A mutation that was made to a table.
+///
+/// Replaying a record is idempotent, so a record that had already reached the pages
+/// before a crash can safely be replayed again.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogRecord {
+    /// The row was inserted, replacing any row with the same key.
+    Insert(BoxedRow),
+    /// The row replaced the row with the same key.
+    Update(BoxedRow),
+    /// The row with the same key as this one was removed.
+    Delete(BoxedRow),
+}
+
+impl LogRecord {
+    /// The same mutation, made with the row that `f` gives for this one's row.
+    pub fn map_row<F: FnOnce(BoxedRow) -> BoxedRow>(self, f: F) -> LogRecord {
+        match self {
+            LogRecord::Insert(row) => LogRecord::Insert(f(row)),
+            LogRecord::Update(row) => LogRecord::Update(f(row)),
+            LogRecord::Delete(row) => LogRecord::Delete(f(row)),
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match *self {
+            LogRecord::Insert(_) => 1,
+            LogRecord::Update(_) => 2,
+            LogRecord::Delete(_) => 3,
+        }
+    }
+
+    fn row(&self) -> &[u8] {
+        match *self {
+            LogRecord::Insert(ref row) | LogRecord::Update(ref row) | LogRecord::Delete(ref row) => row
+        }
+    }
+
+    fn from_parts(kind: u8, row: &[u8]) -> Option<LogRecord> {
+        let row: BoxedRow = row.to_vec().into_boxed_slice();
+        match kind {
+            1 => Some(LogRecord::Insert(row)),
+            2 => Some(LogRecord::Update(row)),
+            3 => Some(LogRecord::Delete(row)),
+            _ => None
+        }
+    }
+
+    /// Encodes the record along with its frame.
+    fn to_frame(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(self.row().len() + 1);
+        body.push(self.kind());
+        body.extend_from_slice(self.row());
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + body.len());
+        frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&checksum(&body).to_le_bytes());
+        frame.extend_from_slice(&body);
+        frame
+    }
+}
+
+/// FNV-1a, which is plenty to tell a torn write apart from a complete one.
+pub(crate) fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5u32, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Decodes records from the start of the bytes,
+/// returning them along with the number of bytes that made up complete, valid records.
+fn decode_records(bytes: &[u8]) -> (Vec<LogRecord>, usize) {
+    let mut records = Vec::new();
+    let mut position = 0;
+    while bytes.len() - position >= FRAME_HEADER_SIZE {
+        let mut length_bytes = [0u8; 4];
+        length_bytes.copy_from_slice(&bytes[position..position + 4]);
+        let mut checksum_bytes = [0u8; 4];
+        checksum_bytes.copy_from_slice(&bytes[position + 4..position + 8]);
+        let length = u32::from_le_bytes(length_bytes) as usize;
+
+        let body_start = position + FRAME_HEADER_SIZE;
+        if length == 0 || bytes.len() - body_start < length {
+            break
+        }
+        let body = &bytes[body_start..body_start + length];
+        if checksum(body) != u32::from_le_bytes(checksum_bytes) {
+            break
+        }
+        match LogRecord::from_parts(body[0], &body[1..]) {
+            Some(record) => records.push(record),
+            None => break
+        }
+        position = body_start + length;
+    }
+    (records, position)
+}
+
+/// The log belonging to a single table.
+/// A log that isn't backed by a file records nothing.
+pub struct Wal {
+    file: Option<File>,
+    length: u64
+}
+
+impl Wal {
+    pub fn in_memory() -> Wal {
+        Wal {
+            file: None,
+            length: 0
+        }
+    }
+
+    /// Opens the log at the path, creating it if it doesn't exist.
+    /// Every intact record in the log is returned so that it can be replayed,
+    /// and any partially written record at the end of the log is cut off.
+    pub fn open<P: AsRef<Path>>(path: P) -> DbResult<(Wal, Vec<LogRecord>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let (records, valid_length) = decode_records(&bytes);
+        if valid_length != bytes.len() {
+            file.set_len(valid_length as u64)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(valid_length as u64))?;
+        let wal = Wal {
+            file: Some(file),
+            length: valid_length as u64
+        };
+        Ok((wal, records))
+    }
+
+    /// The number of bytes currently in the log.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Appends the record to the log, and waits for it to reach the disk.
+    pub fn append(&mut self, record: &LogRecord) -> DbResult<()> {
+        if let Some(ref mut file) = self.file {
+            let frame = record.to_frame();
+            file.write_all(&frame)?;
+            file.sync_data()?;
+            self.length += frame.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Empties the log.
+    /// This should only be done once every record in it has been written to the table's file.
+    pub fn clear(&mut self) -> DbResult<()> {
+        if let Some(ref mut file) = self.file {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.sync_all()?;
+        }
+        self.length = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoding_stops_at_torn_record() {
+        let first = LogRecord::Insert(vec!(1, 2, 3).into_boxed_slice());
+        let second = LogRecord::Delete(vec!(4, 5).into_boxed_slice());
+        let mut bytes = first.to_frame();
+        let first_length = bytes.len();
+        bytes.extend(second.to_frame());
+
+        assert_eq!(decode_records(&bytes), (vec!(first.clone(), second), bytes.len()));
+        for cut in first_length..bytes.len() {
+            assert_eq!(decode_records(&bytes[..cut]), (vec!(first.clone()), first_length));
+        }
+
+        // A flipped bit in the row is caught by the checksum.
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert_eq!(decode_records(&bytes), (vec!(first), first_length));
+    }
+}